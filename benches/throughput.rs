@@ -0,0 +1,186 @@
+// 併發壓測：在同一個程序內啟動 actix 伺服器，模擬耗時的同步查詢，比較兩種寫法
+//
+//   blocking  — 在 actix worker 上直接呼叫（同步的 mysql API 直接寫在 handler 裡）
+//   offloaded — 與 DbPool::run 相同，先取得 semaphore 名額再以 web::block 移到 blocking 執行緒
+//
+// 查詢進行中的同時以另一條連線持續打 /health，觀察不需要資料庫的請求是否被卡住。
+// 不需要 MySQL 或先啟動伺服器：
+//
+//   cargo bench --bench throughput
+//
+// 可用環境變數調整：BENCH_WORKERS（actix worker 數，預設 4）、BENCH_DELAY_MS（每次查詢的耗時，預設 20）、
+// BENCH_POOL（同時進行的查詢上限，對應 database.pool_max，預設 32）、BENCH_CONCURRENCY（併發連線數，預設 64）、
+// BENCH_REQUESTS（每個連線的請求數，預設 20）
+use actix_web::{web, App, HttpResponse, HttpServer};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+struct State {
+    delay: Duration,
+    permits: Semaphore,
+}
+
+// 模擬在 worker 上直接執行同步查詢：整個 worker 在這段時間內無法處理其他請求
+async fn blocking(state: web::Data<State>) -> HttpResponse {
+    thread::sleep(state.delay);
+    HttpResponse::Ok().finish()
+}
+
+// 與 DbPool::run 相同的做法，worker 只是在等待
+async fn offloaded(state: web::Data<State>) -> HttpResponse {
+    let Ok(_permit) = state.permits.acquire().await else {
+        return HttpResponse::ServiceUnavailable().finish();
+    };
+    let delay = state.delay;
+    match web::block(move || thread::sleep(delay)).await {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+// 在背景執行緒啟動伺服器，回傳實際綁定的位址
+fn start_server(workers: usize, delay: Duration, pool: usize) -> SocketAddr {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        actix_web::rt::System::new().block_on(async move {
+            let state = web::Data::new(State { delay, permits: Semaphore::new(pool) });
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(state.clone())
+                    .route("/blocking", web::get().to(blocking))
+                    .route("/offloaded", web::get().to(offloaded))
+                    .route("/health", web::get().to(health))
+            })
+            .workers(workers)
+            .bind("127.0.0.1:0")
+            .expect("bind");
+            tx.send(server.addrs()[0]).expect("send addr");
+            server.run().await
+        })
+    });
+    rx.recv().expect("server addr")
+}
+
+fn env_or(key: &str, default: usize) -> usize {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn connect(addr: SocketAddr) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(addr).expect("connect");
+    stream.set_read_timeout(Some(Duration::from_secs(30))).ok();
+    BufReader::new(stream)
+}
+
+// 使用 keep-alive 連線，依 Content-Length 讀完整個回應
+fn request(reader: &mut BufReader<TcpStream>, path: &str) -> std::io::Result<u16> {
+    let req = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", path);
+    reader.get_mut().write_all(req.as_bytes())?;
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status = status_line.split_whitespace().nth(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line == "\r\n" || line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(status)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> f64 {
+    sorted.get(((sorted.len() as f64 * p) as usize).min(sorted.len().saturating_sub(1)))
+        .map(|d| d.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+// concurrency 條連線各打 per_conn 次 path，同時另一條連線持續打 /health
+fn run(addr: SocketAddr, path: &'static str, concurrency: usize, per_conn: usize) {
+    let done = Arc::new(AtomicBool::new(false));
+    let probe = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut reader = connect(addr);
+            let mut latencies = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                let t = Instant::now();
+                if let Ok(200) = request(&mut reader, "/health") {
+                    latencies.push(t.elapsed());
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            latencies
+        })
+    };
+
+    let started = Instant::now();
+    let handles: Vec<_> = (0..concurrency).map(|_| {
+        thread::spawn(move || {
+            let mut reader = connect(addr);
+            let mut latencies = Vec::with_capacity(per_conn);
+            let mut errors = 0usize;
+            for _ in 0..per_conn {
+                let t = Instant::now();
+                match request(&mut reader, path) {
+                    Ok(200) => latencies.push(t.elapsed()),
+                    _ => errors += 1,
+                }
+            }
+            (latencies, errors)
+        })
+    }).collect();
+
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for handle in handles {
+        let (l, e) = handle.join().expect("client thread");
+        latencies.extend(l);
+        errors += e;
+    }
+    let elapsed = started.elapsed();
+    done.store(true, Ordering::Relaxed);
+    let mut health = probe.join().expect("probe thread");
+    latencies.sort();
+    health.sort();
+
+    println!("── {} ──", path);
+    println!("  完成 {} 個請求（失敗 {}），耗時 {:.2}s，吞吐量 {:.0} req/s",
+        latencies.len(), errors, elapsed.as_secs_f64(), latencies.len() as f64 / elapsed.as_secs_f64());
+    println!("  查詢延遲 p50 {:.1}ms / p95 {:.1}ms / p99 {:.1}ms",
+        percentile(&latencies, 0.50), percentile(&latencies, 0.95), percentile(&latencies, 0.99));
+    println!("  同時的 /health {} 次，延遲 p50 {:.1}ms / p99 {:.1}ms / 最大 {:.1}ms",
+        health.len(), percentile(&health, 0.50), percentile(&health, 0.99), percentile(&health, 1.0));
+}
+
+fn main() {
+    let workers = env_or("BENCH_WORKERS", 4);
+    let delay = Duration::from_millis(env_or("BENCH_DELAY_MS", 20) as u64);
+    let pool = env_or("BENCH_POOL", 32);
+    let concurrency = env_or("BENCH_CONCURRENCY", 64);
+    let per_conn = env_or("BENCH_REQUESTS", 20);
+
+    let addr = start_server(workers, delay, pool);
+    println!("🚀 {} 個 worker、每次查詢 {}ms、查詢上限 {}；{} 個併發連線 × 每個 {} 個請求",
+        workers, delay.as_millis(), pool, concurrency, per_conn);
+    run(addr, "/blocking", concurrency, per_conn);
+    run(addr, "/offloaded", concurrency, per_conn);
+}
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS s_disposition (
+    stock_date DATE NOT NULL,
+    market VARCHAR(10) NOT NULL,
+    symbol INT NOT NULL,
+    name VARCHAR(50) NOT NULL,
+    `start` DATE NULL,
+    `end` DATE NULL,
+    created_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (stock_date, symbol),
+    KEY idx_s_disposition_symbol_end (symbol, `end`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,4 @@
+ALTER TABLE s_disposition
+    DROP COLUMN id,
+    DROP INDEX uk_s_disposition_date_symbol,
+    ADD PRIMARY KEY (stock_date, symbol);
@@ -0,0 +1,5 @@
+ALTER TABLE s_disposition
+    DROP PRIMARY KEY,
+    ADD COLUMN id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT FIRST,
+    ADD PRIMARY KEY (id),
+    ADD UNIQUE KEY uk_s_disposition_date_symbol (stock_date, symbol);
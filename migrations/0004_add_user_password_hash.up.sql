@@ -0,0 +1,2 @@
+ALTER TABLE user
+    ADD COLUMN password_hash VARCHAR(255) NULL AFTER email;
@@ -0,0 +1,2 @@
+ALTER TABLE user
+    ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'analyst' AFTER email;
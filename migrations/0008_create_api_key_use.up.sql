@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS api_key_use (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    api_key_id BIGINT UNSIGNED NOT NULL,
+    method VARCHAR(10) NOT NULL,
+    path VARCHAR(255) NOT NULL,
+    status SMALLINT UNSIGNED NOT NULL,
+    ip VARCHAR(45) NULL,
+    created_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    KEY idx_api_key_use_key_created (api_key_id, created_at),
+    CONSTRAINT fk_api_key_use_api_key FOREIGN KEY (api_key_id) REFERENCES api_key (id) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+    id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+    webhook_id BIGINT UNSIGNED NOT NULL,
+    event VARCHAR(50) NOT NULL,
+    payload JSON NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INT UNSIGNED NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+    last_status SMALLINT UNSIGNED NULL,
+    last_error VARCHAR(500) NULL,
+    created_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP NULL DEFAULT NULL,
+    PRIMARY KEY (id),
+    KEY idx_webhook_delivery_due (status, next_attempt_at),
+    KEY idx_webhook_delivery_webhook (webhook_id, id),
+    CONSTRAINT fk_webhook_delivery_webhook FOREIGN KEY (webhook_id) REFERENCES webhook (id) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS disposition_event (
+    id BIGINT UNSIGNED NOT NULL,
+    event VARCHAR(50) NOT NULL,
+    payload JSON NOT NULL,
+    created_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4;
@@ -0,0 +1,200 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, HttpMessage};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::auth::Principal;
+use crate::error::{AppError, AppResult};
+use crate::models::{ApiKey, Scope};
+use crate::password;
+use crate::store::{ApiKeyStore, UserStore};
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+// 行事曆 App、瀏覽器的 EventSource 與 WebSocket 等無法帶自訂標頭的客戶端改用 ?api_key=
+pub const API_KEY_QUERY: &str = "api_key";
+const KEY_PREFIX: &str = "rca_";
+// 列表上顯示的前綴長度（含 rca_）
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+// 產生新的 key：回傳 (明碼, 顯示用前綴, SHA-256)
+pub fn generate() -> (String, String, String) {
+    let (secret, _) = password::new_secret_token();
+    let key = format!("{}{}", KEY_PREFIX, secret);
+    let prefix = key[..DISPLAY_PREFIX_LEN].to_string();
+    let digest = password::token_digest(&key);
+    (key, prefix, digest)
+}
+
+// 掛在允許機器呼叫的路由上（在 require_role 外層）：帶了 X-API-Key 時驗證 key 與 scope，
+// 以擁有者目前的角色放入 Principal，並在回應後寫入使用紀錄；沒帶時交給 require_role 驗證 access token
+pub fn api_key_auth(scope: Scope) -> ApiKeyAuth {
+    ApiKeyAuth { scope, query: false }
+}
+
+pub struct ApiKeyAuth {
+    scope: Scope,
+    query: bool,
+}
+
+impl ApiKeyAuth {
+    // 也接受網址上的 ?api_key=；key 會出現在訂閱網址中，只用在唯讀的路由
+    pub fn or_query(self) -> Self {
+        Self { query: true, ..self }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiKeyAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = ApiKeyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiKeyMiddleware { service: Rc::new(service), scope: self.scope, query: self.query }))
+    }
+}
+
+pub struct ApiKeyMiddleware<S> {
+    service: Rc<S>,
+    scope: Scope,
+    query: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiKeyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let scope = self.scope;
+        let header = req.headers().get(API_KEY_HEADER).map(|v| v.to_str().unwrap_or_default().to_string());
+        let from_query = header.is_none() && self.query;
+        let raw = if from_query { query_key(req.query_string()) } else { header };
+        Box::pin(async move {
+            let Some(raw) = raw else {
+                return service.call(req).await.map(ServiceResponse::map_into_left_body);
+            };
+
+            let (keys, key) = match authenticate(&req, &raw, scope, from_query).await {
+                Ok(found) => found,
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
+
+            let method = req.method().to_string();
+            let path = req.path().to_string();
+            let ip = req.peer_addr().map(|addr| addr.ip().to_string());
+            let result = service.call(req).await;
+            let status = match &result {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            // 稽核失敗不影響已完成的請求
+            if let Err(e) = keys.record_use(key.id, &method, &path, status.as_u16(), ip.as_deref()).await {
+                tracing::error!(api_key_id = key.id, error = %e, "API key 使用紀錄寫入失敗");
+            }
+            result.map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+fn query_key(query: &str) -> Option<String> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(query).ok()?
+        .into_iter()
+        .find_map(|(name, value)| (name == API_KEY_QUERY).then_some(value))
+}
+
+async fn authenticate(req: &ServiceRequest, raw: &str, scope: Scope, from_query: bool) -> AppResult<(web::Data<dyn ApiKeyStore>, ApiKey)> {
+    let unavailable = || AppError::Unavailable("API key 服務未設定".to_string());
+    let keys = req.app_data::<web::Data<dyn ApiKeyStore>>().cloned().ok_or_else(unavailable)?;
+    let users = req.app_data::<web::Data<dyn UserStore>>().cloned().ok_or_else(unavailable)?;
+
+    let invalid = || AppError::Unauthorized("無效、已撤銷或已過期的 API key".to_string());
+    let key = keys.find_active(&password::token_digest(raw.trim())).await?.ok_or_else(invalid)?;
+    if !key.scopes.contains(&scope) {
+        return Err(AppError::Forbidden(format!("此 API key 沒有 {} 權限", scope.as_str())));
+    }
+    // 網址容易留在瀏覽紀錄與 proxy log，只接受沒有其他權限的 key
+    if from_query && key.scopes.iter().any(|s| *s != scope) {
+        return Err(AppError::Forbidden(format!("放在網址上的 API key 只能有 {} 權限", scope.as_str())));
+    }
+    // 角色以擁有者目前的角色為準，擁有者被刪除後 key 也就失效
+    let owner = users.get_by_id(key.user_id).await?.ok_or_else(invalid)?;
+
+    req.extensions_mut().insert(Principal { user_id: owner.id, role: owner.role });
+    Ok((keys, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{require_role, TokenService};
+    use crate::config::AuthConfig;
+    use crate::memory::{InMemoryApiKeyStore, InMemoryUserStore};
+    use crate::models::{CreateApiKey, CreateUser, Role, UpdateUser};
+    use actix_web::{test, App, HttpResponse};
+    use std::sync::Arc;
+
+    async fn create_key(keys: &dyn ApiKeyStore, user_id: u32, scopes: Vec<Scope>) -> (String, ApiKey) {
+        let (raw, prefix, digest) = generate();
+        let key = CreateApiKey { name: "bot".to_string(), scopes, expires_in_days: None };
+        (raw, keys.create(user_id, &key, &prefix, &digest).await.unwrap())
+    }
+
+    // 寫入路由：scope 不符、key 已撤銷、擁有者降級或刪除後都會被拒絕，通過時留下使用紀錄
+    #[actix_web::test]
+    async fn rejects_wrong_scope_revoked_key_and_downgraded_owner() {
+        let users: Arc<dyn UserStore> = Arc::new(InMemoryUserStore::new());
+        let keys: Arc<dyn ApiKeyStore> = Arc::new(InMemoryApiKeyStore::new());
+        let owner = CreateUser { name: "bot".to_string(), email: "bot@example.com".to_string(), password: String::new(), role: Some(Role::Operator) };
+        let owner = users.create(&owner, "unused").await.unwrap();
+        let (read_only, _) = create_key(keys.as_ref(), owner.id, vec![Scope::DispositionRead]).await;
+        let (writer, writer_key) = create_key(keys.as_ref(), owner.id, vec![Scope::DispositionWrite]).await;
+        let (revoked, revoked_key) = create_key(keys.as_ref(), owner.id, vec![Scope::DispositionWrite]).await;
+        keys.revoke(owner.id, revoked_key.id).await.unwrap();
+
+        let auth = AuthConfig { jwt_secret: Some("x".repeat(32)), ..AuthConfig::default() };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(users.clone()))
+                .app_data(web::Data::from(keys.clone()))
+                .app_data(web::Data::new(TokenService::new(&auth)))
+                .route("/write", web::post()
+                    .to(HttpResponse::NoContent)
+                    .wrap(require_role(Role::Operator))
+                    .wrap(api_key_auth(Scope::DispositionWrite))),
+        ).await;
+        let call = |key: &str| {
+            let req = test::TestRequest::post().uri("/write").insert_header((API_KEY_HEADER, key)).to_request();
+            let app = &app;
+            async move { test::call_service(app, req).await.status().as_u16() }
+        };
+
+        assert_eq!(call(&writer).await, 204);
+        assert_eq!(call(&read_only).await, 403);
+        assert_eq!(call(&revoked).await, 401);
+        assert_eq!(call("rca_unknown").await, 401);
+        let uses = keys.list_uses(writer_key.id, 10).await.unwrap();
+        assert_eq!(uses.len(), 1);
+        assert_eq!((uses[0].method.as_str(), uses[0].status), ("POST", 204));
+
+        // 角色以擁有者目前的角色為準
+        let downgrade = UpdateUser { name: None, email: None, role: Some(Role::Analyst) };
+        users.update(owner.id, &downgrade).await.unwrap();
+        assert_eq!(call(&writer).await, 403);
+        users.delete(owner.id).await.unwrap();
+        assert_eq!(call(&writer).await, 401);
+    }
+}
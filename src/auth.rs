@@ -0,0 +1,292 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{web, HttpMessage};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use crate::config::AuthConfig;
+use crate::error::{AppError, AppResult};
+use crate::models::{CreateUser, Role, TokenPair, UpdateUser, User};
+use crate::store::UserStore;
+use crate::password;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+// JWT 內容，sub 為使用者 id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub email: String,
+    // 角色變更要等 access token 換發後才生效
+    pub role: Role,
+    pub typ: TokenKind,
+    pub iss: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> AppResult<u32> {
+        self.sub.parse().map_err(|_| AppError::Unauthorized("無效的 token".to_string()))
+    }
+}
+
+// 通過驗證的呼叫者（access token 或 API key），放在 request extensions 供 handler 使用
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: u32,
+    pub role: Role,
+}
+
+// 簽發與驗證 token（HS256）
+pub struct TokenService {
+    enabled: bool,
+    issuer: String,
+    access_ttl_secs: u64,
+    refresh_ttl_secs: u64,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    validation: Validation,
+}
+
+impl TokenService {
+    // config 已驗證過 jwt_secret 存在
+    pub fn new(config: &AuthConfig) -> Self {
+        let secret = config.jwt_secret.as_deref().unwrap_or_default().as_bytes();
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[config.issuer.as_str()]);
+
+        Self {
+            enabled: config.enabled,
+            issuer: config.issuer.clone(),
+            access_ttl_secs: config.access_ttl_secs,
+            refresh_ttl_secs: config.refresh_ttl_secs,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            validation,
+        }
+    }
+
+    pub fn issue(&self, user: User) -> AppResult<TokenPair> {
+        Ok(TokenPair {
+            access_token: self.sign(&user, TokenKind::Access, self.access_ttl_secs)?,
+            refresh_token: self.sign(&user, TokenKind::Refresh, self.refresh_ttl_secs)?,
+            token_type: "Bearer",
+            expires_in: self.access_ttl_secs,
+            user,
+        })
+    }
+
+    fn sign(&self, user: &User, typ: TokenKind, ttl_secs: u64) -> AppResult<String> {
+        let iat = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user.id.to_string(),
+            email: user.email.clone(),
+            role: user.role,
+            typ,
+            iss: self.issuer.clone(),
+            iat,
+            exp: iat + ttl_secs as i64,
+        };
+        encode(&Header::new(Algorithm::HS256), &claims, &self.encoding)
+            .map_err(|e| AppError::Internal(format!("簽發 token 失敗: {}", e)))
+    }
+
+    // 驗證簽章、issuer、到期時間，並確認 token 用途（access 不能拿來換發、refresh 不能拿來呼叫 API）
+    pub fn verify(&self, token: &str, expected: TokenKind) -> AppResult<Claims> {
+        let claims = decode::<Claims>(token, &self.decoding, &self.validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => AppError::Unauthorized("token 已過期".to_string()),
+                _ => AppError::Unauthorized("無效的 token".to_string()),
+            })?
+            .claims;
+        if claims.typ != expected {
+            return Err(AppError::Unauthorized("token 類型錯誤".to_string()));
+        }
+        Ok(claims)
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> AppResult<&str> {
+    let value = req.headers()
+        .get(header::AUTHORIZATION)
+        .ok_or_else(|| AppError::Unauthorized("需要登入".to_string()))?
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("無效的 Authorization 標頭".to_string()))?;
+    value.strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| AppError::Unauthorized("Authorization 需為 Bearer token".to_string()))
+}
+
+// 掛在單一路由上的授權 middleware：驗證 access token 並要求至少 `role` 的角色。
+// 外層的 API key middleware 已放入 Principal 時只檢查角色。
+// 成功時把 Principal 放進 request extensions；失敗時直接產生回應而不是回傳 Err，
+// 這樣外層的 CORS middleware 仍會加上標頭
+pub fn require_role(role: Role) -> RequireRole {
+    RequireRole { role }
+}
+
+pub struct RequireRole {
+    role: Role,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware { service: Rc::new(service), role: self.role }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S> {
+    service: Rc<S>,
+    role: Role,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let role = self.role;
+        Box::pin(async move {
+            match authorize(&req, role) {
+                Ok(()) => service.call(req).await.map(ServiceResponse::map_into_left_body),
+                Err(e) => Ok(req.error_response(e).map_into_right_body()),
+            }
+        })
+    }
+}
+
+fn authorize(req: &ServiceRequest, role: Role) -> AppResult<()> {
+    let tokens = req.app_data::<web::Data<TokenService>>()
+        .ok_or_else(|| AppError::Unauthorized("驗證服務未設定".to_string()))?;
+    if !tokens.enabled {
+        return Ok(());
+    }
+
+    let existing = req.extensions().get::<Principal>().cloned();
+    let principal = match existing {
+        Some(principal) => principal,
+        None => {
+            let claims = tokens.verify(bearer_token(req)?, TokenKind::Access)?;
+            let principal = Principal { user_id: claims.user_id()?, role: claims.role };
+            req.extensions_mut().insert(principal.clone());
+            principal
+        }
+    };
+    if principal.role < role {
+        return Err(AppError::Forbidden(format!("需要 {} 以上的權限", role.as_str())));
+    }
+    Ok(())
+}
+
+// 設定了 bootstrap_email 且該使用者不存在時建立 admin，讓全新的資料庫也能登入；
+// 已存在時確保是 admin，且還沒有密碼（加入密碼欄位前建立的帳號）時補上密碼
+pub async fn bootstrap_user(store: &dyn UserStore, config: &AuthConfig) -> AppResult<Option<User>> {
+    let (Some(email), Some(password)) = (&config.bootstrap_email, &config.bootstrap_password) else {
+        return Ok(None);
+    };
+    if let Some(mut user) = store.get_by_email(email).await? {
+        let mut changed = false;
+        if store.get_password_hash(user.id).await?.is_none() {
+            store.set_password_hash(user.id, &password::hash(password.clone()).await?).await?;
+            changed = true;
+        }
+        if user.role != Role::Admin {
+            let promote = UpdateUser { name: None, email: None, role: Some(Role::Admin) };
+            user = store.update(user.id, &promote).await?.unwrap_or(user);
+            changed = true;
+        }
+        return Ok(changed.then_some(user));
+    }
+    let user = CreateUser {
+        name: config.bootstrap_name.clone(),
+        email: email.clone(),
+        password: password.clone(),
+        role: Some(Role::Admin),
+    };
+    let password_hash = password::hash(user.password.clone()).await?;
+    store.create(&user, &password_hash).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(issuer: &str) -> TokenService {
+        TokenService::new(&AuthConfig { jwt_secret: Some("x".repeat(32)), issuer: issuer.to_string(), ..AuthConfig::default() })
+    }
+
+    fn user() -> User {
+        User { id: 7, name: "Amy".to_string(), email: "amy@example.com".to_string(), role: Role::Operator, created_at: None, updated_at: None }
+    }
+
+    fn message(result: AppResult<Claims>) -> String {
+        match result {
+            Err(AppError::Unauthorized(message)) => message,
+            other => panic!("expected 401, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verifies_kind_and_subject() {
+        let tokens = service("rust-crud-api");
+        let pair = tokens.issue(user()).unwrap();
+        let claims = tokens.verify(&pair.access_token, TokenKind::Access).unwrap();
+        assert_eq!((claims.user_id().unwrap(), claims.role), (7, Role::Operator));
+        assert!(tokens.verify(&pair.refresh_token, TokenKind::Refresh).is_ok());
+
+        assert_eq!(message(tokens.verify(&pair.refresh_token, TokenKind::Access)), "token 類型錯誤");
+        assert_eq!(message(tokens.verify(&pair.access_token, TokenKind::Refresh)), "token 類型錯誤");
+    }
+
+    #[test]
+    fn rejects_expired_tampered_and_foreign_tokens() {
+        let tokens = service("rust-crud-api");
+        // 超過 jsonwebtoken 預設 60 秒的容許誤差
+        let expired = tokens.sign(&user(), TokenKind::Access, 0).unwrap();
+        let mut claims = tokens.verify(&expired, TokenKind::Access).unwrap();
+        claims.exp -= 120;
+        let expired = encode(&Header::new(Algorithm::HS256), &claims, &tokens.encoding).unwrap();
+        assert_eq!(message(tokens.verify(&expired, TokenKind::Access)), "token 已過期");
+
+        let access = tokens.issue(user()).unwrap().access_token;
+        let (unsigned, signature) = access.rsplit_once('.').unwrap();
+        let flipped = if signature.starts_with('A') { "B" } else { "A" };
+        let tampered = format!("{}.{}{}", unsigned, flipped, &signature[1..]);
+        assert_eq!(message(tokens.verify(&tampered, TokenKind::Access)), "無效的 token");
+
+        // 同一把金鑰、不同 issuer 簽發的 token 也不接受
+        let foreign = service("another-service").issue(user()).unwrap().access_token;
+        assert_eq!(message(tokens.verify(&foreign, TokenKind::Access)), "無效的 token");
+        let other_key = TokenService::new(&AuthConfig { jwt_secret: Some("y".repeat(32)), ..AuthConfig::default() });
+        assert_eq!(message(tokens.verify(&other_key.issue(user()).unwrap().access_token, TokenKind::Access)), "無效的 token");
+    }
+}
@@ -0,0 +1,144 @@
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use chrono::{Days, Local, NaiveDateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use crate::error::{AppError, AppResult};
+use crate::models::Disposition;
+use crate::store::RowStream;
+
+// RFC 5545 的行尾與單行上限（位元組，不含 CRLF）
+const CRLF: &str = "\r\n";
+const MAX_LINE_OCTETS: usize = 75;
+
+const FEED_END: &str = "END:VCALENDAR\r\n";
+
+// 每筆處置期間一個全天事件；UID 以資料列 id 產生，更新起訖日後同一事件會被覆寫而不是新增。
+// 逐筆產生後送出，不會在記憶體中組出整個行事曆
+pub async fn respond(mut rows: RowStream<Disposition>) -> AppResult<HttpResponse> {
+    let mut head = feed_start();
+    // 先讀第一列：查詢一開始就失敗時還能回傳正常的錯誤回應
+    if let Some(first) = rows.recv().await {
+        head.push_str(&event(&first?));
+    }
+
+    let body = stream::once(async move { Ok::<_, AppError>(Bytes::from(head)) })
+        .chain(stream::unfold(Some(rows), |rows| async move {
+            let mut rows = rows?;
+            match rows.recv().await {
+                Some(row) => Some((row.map(|d| Bytes::from(event(&d))), Some(rows))),
+                None => Some((Ok(Bytes::from_static(FEED_END.as_bytes())), None)),
+            }
+        }));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .streaming(body))
+}
+
+fn feed_start() -> String {
+    let mut ics = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//rust-crud-api//disposition//ZH", "CALSCALE:GREGORIAN", "X-WR-CALNAME:處置股", "X-WR-TIMEZONE:Asia/Taipei"] {
+        push_line(&mut ics, line);
+    }
+    ics
+}
+
+// 沒有處置期間（start/end）的資料無法排進行事曆，回傳空字串
+fn event(d: &Disposition) -> String {
+    let mut ics = String::new();
+    let (Some(start), Some(end)) = (d.start, d.end) else {
+        return ics;
+    };
+    // 全天事件的 DTEND 不包含當天，所以是迄日的隔天
+    let until = end.0.checked_add_days(Days::new(1)).unwrap_or(end.0);
+    let stamp = utc_stamp(d.updated_at.or(d.created_at));
+
+    push_line(&mut ics, "BEGIN:VEVENT");
+    push_line(&mut ics, &format!("UID:disposition-{}@rust-crud-api", d.id));
+    push_line(&mut ics, &format!("DTSTAMP:{}", stamp));
+    push_line(&mut ics, &format!("LAST-MODIFIED:{}", stamp));
+    push_line(&mut ics, &format!("DTSTART;VALUE=DATE:{}", start.0.format("%Y%m%d")));
+    push_line(&mut ics, &format!("DTEND;VALUE=DATE:{}", until.format("%Y%m%d")));
+    push_line(&mut ics, &format!("SUMMARY:{}", escape(&format!("{} {} 處置", d.symbol, d.name))));
+    push_line(&mut ics, &format!("DESCRIPTION:{}", escape(&format!("{} 公告日 {}，處置期間 {} ～ {}", d.market, d.stock_date.map(|s| s.iso()).unwrap_or_default(), start.iso(), end.iso()))));
+    push_line(&mut ics, "TRANSP:TRANSPARENT");
+    push_line(&mut ics, "END:VEVENT");
+    ics
+}
+
+// 資料庫的時間戳記是伺服器當地時間，DTSTAMP 需為 UTC
+fn utc_stamp(value: Option<NaiveDateTime>) -> String {
+    let utc = match value.and_then(|v| v.and_local_timezone(Local).single()) {
+        Some(local) => local.with_timezone(&Utc),
+        None => Utc::now(),
+    };
+    utc.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// 超過 75 位元組的行要折行（CRLF 加一個空白），不能切在 UTF-8 字元中間
+fn push_line(ics: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            ics.push_str(CRLF);
+            ics.push(' ');
+            width = 1;
+        }
+        ics.push(c);
+        width += c.len_utf8();
+    }
+    ics.push_str(CRLF);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateDisposition, TwDate, UpdateDisposition};
+    use crate::memory::InMemoryDispositionStore;
+    use crate::store::DispositionStore;
+
+    fn date(value: &str) -> TwDate {
+        TwDate::parse(value).unwrap()
+    }
+
+    fn disposition_feed(dispositions: &[Disposition]) -> String {
+        feed_start() + &dispositions.iter().map(event).collect::<String>() + FEED_END
+    }
+
+    fn uids(ics: &str) -> Vec<&str> {
+        ics.lines().filter(|l| l.starts_with("UID:")).collect()
+    }
+
+    // 修改處置期間後 UID 不變，只有日期改變；每行都不超過 75 位元組
+    #[actix_web::test]
+    async fn updated_period_keeps_uid() {
+        let store = InMemoryDispositionStore::new();
+        let created = store.create(&CreateDisposition {
+            stock_date: date("114/10/17"),
+            market: "TWSE".to_string(),
+            symbol: "2330".to_string(),
+            name: "台積電".repeat(10),
+            start: Some(date("114/10/20")),
+            end: Some(date("114/10/31")),
+        }).await.unwrap();
+
+        let before = disposition_feed(&[created]);
+        assert!(before.contains("DTSTART;VALUE=DATE:20251020\r\n"));
+        assert!(before.contains("DTEND;VALUE=DATE:20251101\r\n"));
+        assert!(before.contains("SUMMARY:2330 台積電"));
+        assert!(before.split(CRLF).all(|line| line.len() <= MAX_LINE_OCTETS));
+
+        let update = UpdateDisposition { start: None, end: Some(date("114/11/07")) };
+        let updated = store.update(2330, &update).await.unwrap().unwrap();
+        let after = disposition_feed(&[updated]);
+        assert_eq!(uids(&before), uids(&after));
+        assert!(after.contains("DTEND;VALUE=DATE:20251108\r\n"));
+    }
+}
@@ -0,0 +1,11 @@
+use chrono::{FixedOffset, NaiveDate, Utc};
+
+// 台灣沒有日光節約時間，固定 UTC+8
+pub fn taipei_offset() -> FixedOffset {
+    FixedOffset::east_opt(8 * 3600).expect("valid offset")
+}
+
+// 台股交易日以台北時間為準
+pub fn taipei_today() -> NaiveDate {
+    Utc::now().with_timezone(&taipei_offset()).date_naive()
+}
@@ -0,0 +1,134 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::error::AppError;
+use crate::models::{DateFormat, DATE_FORMAT};
+
+// 全域 middleware：讀取 `?date_format=iso|roc`，在處理請求期間設定 DATE_FORMAT，
+// 讓回應中的 TwDate 以指定格式輸出；分頁的 next/prev 連結保留原本的 query，因此會沿用同一格式
+pub struct DateFormatScope;
+
+impl<S, B> Transform<S, ServiceRequest> for DateFormatScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = DateFormatMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(DateFormatMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct DateFormatMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for DateFormatMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let format = match requested_format(req.query_string()) {
+                Ok(format) => format,
+                Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+            };
+            DATE_FORMAT
+                .scope(format, service.call(req))
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        })
+    }
+}
+
+// query 本身格式錯誤時交給各 handler 的 Query 回報，這裡只檢查 date_format
+fn requested_format(query_string: &str) -> Result<DateFormat, AppError> {
+    let params: Vec<(String, String)> = serde_urlencoded::from_str(query_string).unwrap_or_default();
+    match params.iter().find(|(key, _)| key == "date_format") {
+        Some((_, value)) => DateFormat::parse(value)
+            .ok_or_else(|| AppError::BadRequest(format!("無效的 date_format: {}（可用: iso, roc）", value))),
+        None => Ok(DateFormat::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::{create_disposition, get_disposition_history, update_disposition};
+    use crate::events::EventHub;
+    use crate::memory::InMemoryDispositionStore;
+    use crate::store::DispositionStore;
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    // 民國日期寫入，依 date_format 以 ISO 或民國格式讀出
+    #[actix_web::test]
+    async fn dates_follow_requested_format() {
+        let store: Arc<dyn DispositionStore> = Arc::new(InMemoryDispositionStore::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store))
+                .app_data(web::Data::new(EventHub::new()))
+                .wrap(DateFormatScope)
+                .route("/disposition", web::post().to(create_disposition))
+                .route("/disposition/{symbol}/history", web::get().to(get_disposition_history)),
+        )
+        .await;
+
+        let body = json!({ "stock_date": "114/10/17", "market": "TWSE", "symbol": "2330", "name": "台積電", "start": "1141020", "end": "2025-10-31" });
+        let res = test::call_service(&app, test::TestRequest::post().uri("/disposition").set_json(&body).to_request()).await;
+        assert_eq!(res.status(), 201);
+
+        let dates = |body: Value| {
+            let row = &body["data"][0];
+            (row["stock_date"].clone(), row["start"].clone(), row["end"].clone())
+        };
+        let iso: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/disposition/2330/history").to_request()).await;
+        assert_eq!(dates(iso), (json!("2025-10-17"), json!("2025-10-20"), json!("2025-10-31")));
+        let roc: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/disposition/2330/history?date_format=roc").to_request()).await;
+        assert_eq!(dates(roc), (json!("114/10/17"), json!("114/10/20"), json!("114/10/31")));
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/disposition/2330/history?date_format=jp").to_request()).await;
+        assert_eq!(res.status(), 400);
+    }
+
+    // 無法解析的日期和其他欄位一樣回傳 422，並指出是哪個欄位
+    #[actix_web::test]
+    async fn invalid_dates_are_field_errors() {
+        let store: Arc<dyn DispositionStore> = Arc::new(InMemoryDispositionStore::new());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store))
+                .app_data(web::Data::new(EventHub::new()))
+                .route("/disposition", web::post().to(create_disposition))
+                .route("/disposition/{symbol}", web::put().to(update_disposition)),
+        )
+        .await;
+
+        let fields = |body: Value| body["errors"].as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        let body = json!({ "stock_date": "2025-13-01", "market": "NYSE", "symbol": "2330", "name": "台積電", "start": "114/10/20", "end": "下週五" });
+        let res = test::call_service(&app, test::TestRequest::post().uri("/disposition").set_json(&body).to_request()).await;
+        assert_eq!(res.status(), 422);
+        assert_eq!(fields(test::read_body_json(res).await), ["end", "market", "stock_date"]);
+
+        let res = test::call_service(&app, test::TestRequest::put().uri("/disposition/2330").set_json(json!({ "start": "114/02/30" })).to_request()).await;
+        assert_eq!(res.status(), 422);
+        assert_eq!(fields(test::read_body_json(res).await), ["start"]);
+    }
+}
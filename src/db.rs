@@ -1,32 +1,200 @@
 use mysql::*;
 use mysql::prelude::*;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use anyhow::Result;
-use dotenvy::dotenv;
-use std::env;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tracing::Instrument;
+use crate::config::DatabaseConfig;
+use crate::migrate;
+use crate::error::{AppError, AppResult};
+use crate::metrics::{GaugeGuard, METRICS};
 
-// 修正：應該使用 Arc<mysql::Pool> 而不是 Arc<PooledConn>
-pub type DbPool = Arc<mysql::Pool>;
+// mysql crate 是同步的：查詢交給 actix 的 blocking 執行緒執行，
+// 並用 semaphore 把同時進行中的查詢限制在連接池上限內，避免 blocking 執行緒無限堆積
+#[derive(Clone)]
+pub struct DbPool {
+    pool: mysql::Pool,
+    permits: Arc<Semaphore>,
+    acquire_timeout: Duration,
+}
+
+impl DbPool {
+    // 同步取得連線（migration、CLI 使用）
+    pub fn get_conn(&self) -> mysql::Result<PooledConn> {
+        self.pool.get_conn()
+    }
+
+    // 在 blocking 執行緒上取得連線並執行 f，不會卡住 actix worker。
+    // op 為 log 中的操作名稱（例如 user.list），每次呼叫記錄等待連線與執行 SQL 的時間
+    pub async fn run<T, F>(&self, op: &'static str, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut PooledConn) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let span = tracing::info_span!("db", op);
+        let queued = Instant::now();
+        async move {
+            let waiting = METRICS.pool_waiting();
+            let _permit = self.acquire_permit().await?;
+            let pool = self.pool.clone();
+            let timeout = self.acquire_timeout;
+            // blocking 執行緒不會自動帶著目前的 span
+            let span = tracing::Span::current();
+
+            actix_web::web::block(move || {
+                let _entered = span.enter();
+                let mut conn = acquire(&pool, timeout, waiting)?;
+                let _in_use = METRICS.pool_in_use();
+                let wait = queued.elapsed();
+                let started = Instant::now();
+                let result = f(&mut conn);
+                record_query(op, wait, started.elapsed(), result.as_ref().err());
+                result
+            })
+            .await
+            .map_err(|e| AppError::Unavailable(format!("資料庫工作執行失敗: {}", e)))?
+        }
+        .instrument(span)
+        .await
+    }
+
+    // 串流查詢（匯出用）：f 在 blocking 執行緒上逐列呼叫 emit，資料經有界 channel 送出，
+    // 接收端跟不上時會等待，不會把整個結果集放進記憶體；emit 回傳 false 代表接收端已關閉（例如客戶端斷線），應停止讀取。
+    // 連線或查詢錯誤會當作最後一筆送出
+    pub async fn stream<T, F>(&self, op: &'static str, f: F) -> AppResult<mpsc::Receiver<AppResult<T>>>
+    where
+        F: FnOnce(&mut PooledConn, &mut dyn FnMut(T) -> bool) -> AppResult<()> + Send + 'static,
+        T: Send + 'static,
+    {
+        let span = tracing::info_span!("db", op);
+        let queued = Instant::now();
+        let waiting = METRICS.pool_waiting();
+        let permit = self.acquire_permit().instrument(span.clone()).await?;
+        let pool = self.pool.clone();
+        let timeout = self.acquire_timeout;
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let runtime = Handle::current();
+
+        actix_web::rt::task::spawn_blocking(move || {
+            let _entered = span.enter();
+            let _permit = permit;
+            let result = acquire(&pool, timeout, waiting)
+                .and_then(|mut conn| {
+                    let _in_use = METRICS.pool_in_use();
+                    let wait = queued.elapsed();
+                    let started = Instant::now();
+                    // 接收端太久沒有讀取（例如客戶端停住不收）時停止，避免一直占著連線
+                    let result = f(&mut conn, &mut |row| match runtime.block_on(tx.send_timeout(Ok(row), STREAM_IDLE_TIMEOUT)) {
+                        Ok(()) => true,
+                        Err(SendTimeoutError::Timeout(_)) => {
+                            tracing::warn!(idle_secs = STREAM_IDLE_TIMEOUT.as_secs(), "接收端閒置逾時，停止串流");
+                            false
+                        }
+                        Err(SendTimeoutError::Closed(_)) => false,
+                    });
+                    // 串流的時間包含等待接收端讀取
+                    record_query(op, wait, started.elapsed(), result.as_ref().err());
+                    result
+                });
+            if let Err(e) = result {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        Ok(rx)
+    }
+
+    // 等待查詢名額，最多等 acquire_timeout；與取得連線使用同一個上限
+    async fn acquire_permit(&self) -> AppResult<OwnedSemaphorePermit> {
+        match tokio::time::timeout(self.acquire_timeout, self.permits.clone().acquire_owned()).await {
+            Ok(permit) => permit.map_err(|e| AppError::Unavailable(format!("資料庫連接池已關閉: {}", e))),
+            Err(_) => {
+                METRICS.pool_acquire_failed();
+                Err(AppError::Unavailable("等待資料庫連線逾時".to_string()))
+            }
+        }
+    }
+}
+
+// 串流查詢時 channel 最多暫存的列數
+const STREAM_BUFFER: usize = 256;
+// 串流時接收端超過這個時間沒有讀取就停止查詢
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+// 超過這個時間的查詢以 warn 記錄
+const SLOW_QUERY: Duration = Duration::from_millis(500);
+
+// 取得連線後結束等待；逾時或連線失敗另外計數
+fn acquire(pool: &Pool, timeout: Duration, waiting: GaugeGuard) -> AppResult<PooledConn> {
+    let conn = pool.try_get_conn(timeout).map_err(|e| {
+        METRICS.pool_acquire_failed();
+        AppError::Unavailable(format!("資料庫連接失敗: {}", e))
+    });
+    drop(waiting);
+    conn
+}
+
+// 寫入 log 與 /metrics 的查詢時間
+fn record_query(op: &'static str, wait: Duration, elapsed: Duration, error: Option<&AppError>) {
+    let failed = matches!(error, Some(AppError::Database(_) | AppError::Unavailable(_)));
+    METRICS.observe_pool_wait(wait);
+    METRICS.observe_query(op, elapsed, failed);
 
-pub fn create_pool() -> Result<DbPool> {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set in .env file");
-    // println!("{}", &database_url1);
+    let wait_ms = wait.as_millis() as u64;
+    let elapsed_ms = elapsed.as_millis() as u64;
+    match error {
+        // 重複資料、找不到等屬於正常的業務結果，由 handler 的錯誤處理記錄
+        Some(e @ (AppError::Database(_) | AppError::Unavailable(_))) => {
+            tracing::error!(op, wait_ms, elapsed_ms, error = %e, "SQL 執行失敗");
+        }
+        _ if elapsed >= SLOW_QUERY => tracing::warn!(op, wait_ms, elapsed_ms, "SQL 執行緩慢"),
+        _ => tracing::debug!(op, wait_ms, elapsed_ms, "SQL 執行完成"),
+    }
+}
+
+// 建立連接池並套用尚未執行的 migration
+pub fn create_pool(config: &DatabaseConfig) -> Result<DbPool> {
+    let pool = connect(config)?;
+
+    let mut conn = pool.get_conn()?;
+    let applied = migrate::run_pending(&mut conn)?;
+    if !applied.is_empty() {
+        tracing::info!(count = applied.len(), "已套用 migration");
+    }
+
+    Ok(pool)
+}
 
-    // 創建連接池時可以設定更多選項 
-    // database_url是文字，但 &str 才是我們要的，所以要加上 &
-    let opts = Opts::from_url(&database_url)
+// 只建立連接池，不動 schema（migrate 子命令使用）
+pub fn connect(config: &DatabaseConfig) -> Result<DbPool> {
+    let database_url = config.url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("未設定資料庫 URL（database.url 或 DATABASE_URL）"))?;
+
+    // 創建連接池時可以設定更多選項
+    let opts = Opts::from_url(database_url)
         .map_err(|e| anyhow::anyhow!("解析資料庫 URL 失敗: {}", e))?;
-    
+
+    let constraints = PoolConstraints::new(config.pool_min, config.pool_max)
+        .ok_or_else(|| anyhow::anyhow!("pool_min ({}) 不可大於 pool_max ({})", config.pool_min, config.pool_max))?;
+    let opts = OptsBuilder::from_opts(opts)
+        .tcp_connect_timeout(Some(Duration::from_secs(config.connect_timeout_secs)))
+        .pool_opts(PoolOpts::default().with_constraints(constraints));
+
     let pool = Pool::new(opts)?;
 
     // 測試連接
     let mut conn = pool.get_conn()?;
     conn.query_drop("SELECT 1")?;
-    
-    println!("✅ 成功連接到 MySQL 資料庫");
-    
-    // 使用 Arc 包裝連接池，以便在多線程間安全共享
-    Ok(Arc::new(pool))
+
+    tracing::info!("成功連接到 MySQL 資料庫");
+
+    METRICS.set_pool_max(config.pool_max);
+
+    // Pool 本身可以 clone 並在多線程間共享
+    Ok(DbPool {
+        pool,
+        permits: Arc::new(Semaphore::new(config.pool_max)),
+        acquire_timeout: Duration::from_secs(config.acquire_timeout_secs),
+    })
 }
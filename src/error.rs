@@ -0,0 +1,225 @@
+use actix_web::{http::{header, StatusCode}, HttpResponse, ResponseError};
+use crate::models::{ApiResponse, FieldError};
+
+pub type AppResult<T> = Result<T, AppError>;
+
+// 對外的錯誤類型，code 是前端判斷用的固定字串
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{}", conflict_message(field))]
+    Conflict { field: String },
+    #[error("輸入資料驗證失敗")]
+    Validation { errors: Vec<FieldError> },
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("資料庫錯誤: {0}")]
+    Database(String),
+    #[error("服務暫時無法使用: {0}")]
+    Unavailable(String),
+    // 與資料庫無關的伺服器內部錯誤，例如簽發 token、雜湊密碼失敗
+    #[error("伺服器內部錯誤: {0}")]
+    Internal(String),
+}
+
+fn conflict_message(field: &str) -> String {
+    match field {
+        "email" => "電子郵件已存在".to_string(),
+        "stock_date,symbol" => "該日期的處置股已存在".to_string(),
+        // 批次匯入的欄位帶有從 0 起算的索引，例如 rows[2].stock_date,symbol 為第 3 筆
+        other => match other.strip_prefix("rows[").and_then(|rest| rest.split_once("].")) {
+            Some((index, field)) => match index.parse::<usize>() {
+                Ok(index) => format!("第 {} 筆：{}", index + 1, conflict_message(field)),
+                Err(_) => format!("{} 已存在", other),
+            },
+            None => format!("{} 已存在", other),
+        },
+    }
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Conflict { .. } => "CONFLICT",
+            AppError::Validation { .. } => "VALIDATION_FAILED",
+            AppError::BadRequest(_) => "BAD_REQUEST",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::Database(_) => "DATABASE_ERROR",
+            AppError::Unavailable(_) => "SERVICE_UNAVAILABLE",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    // 單一欄位的驗證錯誤
+    pub fn invalid(field: &str, message: impl Into<String>) -> Self {
+        AppError::Validation {
+            errors: vec![FieldError { field: field.to_string(), message: message.into() }],
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // handler 與 middleware 的錯誤都經過這裡轉成回應，在 request span 內記錄，log 會帶上 request_id
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code().as_u16();
+        if self.status_code().is_server_error() {
+            tracing::error!(status, code = self.code(), error = %self, "請求失敗");
+        } else {
+            tracing::info!(status, code = self.code(), error = %self, "請求被拒絕");
+        }
+
+        let mut body = ApiResponse::<()>::error(self.code(), &self.to_string());
+        match self {
+            AppError::Validation { errors } => body.errors = Some(errors.clone()),
+            AppError::Conflict { field } => {
+                body.errors = Some(vec![FieldError { field: field.clone(), message: self.to_string() }]);
+            }
+            _ => {}
+        }
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(body)
+    }
+}
+
+// 依 MySQL 錯誤碼分類
+impl From<mysql::Error> for AppError {
+    fn from(e: mysql::Error) -> Self {
+        match &e {
+            mysql::Error::MySqlError(err) => match err.code {
+                // ER_DUP_ENTRY: "Duplicate entry 'x' for key 'uk_user_email'"
+                1062 => AppError::Conflict { field: duplicate_key_field(&err.message) },
+                // ER_TRUNCATED_WRONG_VALUE / ER_TRUNCATED_WRONG_VALUE_FOR_FIELD
+                1292 | 1366 => AppError::BadRequest(err.message.clone()),
+                // ER_CON_COUNT_ERROR / ER_LOCK_WAIT_TIMEOUT / ER_LOCK_DEADLOCK
+                1040 | 1205 | 1213 => AppError::Unavailable(err.message.clone()),
+                _ => AppError::Database(err.message.clone()),
+            },
+            mysql::Error::IoError(_) | mysql::Error::DriverError(_) => AppError::Unavailable(e.to_string()),
+            _ => AppError::Database(e.to_string()),
+        }
+    }
+}
+
+// validator 的錯誤轉成逐欄位的說明，整體（schema）錯誤的欄位為 __all__
+pub fn field_errors(e: &validator::ValidationErrors) -> Vec<FieldError> {
+    let mut errors: Vec<FieldError> = e.field_errors()
+        .into_iter()
+        .flat_map(|(field, errs)| {
+            errs.iter().map(move |err| FieldError {
+                field: field.to_string(),
+                message: err.message.as_ref().map(|m| m.to_string()).unwrap_or_else(|| err.code.to_string()),
+            })
+        })
+        .collect();
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+    errors
+}
+
+impl From<validator::ValidationErrors> for AppError {
+    fn from(e: validator::ValidationErrors) -> Self {
+        AppError::Validation { errors: field_errors(&e) }
+    }
+}
+
+// 唯一索引名稱對應到欄位
+fn duplicate_key_field(message: &str) -> String {
+    let key = message
+        .rsplit("for key '")
+        .next()
+        .map(|k| k.trim_end_matches('\''))
+        .unwrap_or_default();
+    // MySQL 8 會帶上表名，例如 'user.uk_user_email'
+    let key = key.rsplit('.').next().unwrap_or(key);
+    match key {
+        "uk_user_email" => "email".to_string(),
+        "uk_s_disposition_date_symbol" => "stock_date,symbol".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn mysql_error(code: u16, message: &str) -> AppError {
+        mysql::Error::MySqlError(mysql::MySqlError { state: "HY000".to_string(), message: message.to_string(), code }).into()
+    }
+
+    #[test]
+    fn duplicate_key_field_from_mysql_57_and_8() {
+        assert_eq!(duplicate_key_field("Duplicate entry 'a@b.co' for key 'uk_user_email'"), "email");
+        assert_eq!(duplicate_key_field("Duplicate entry 'a@b.co' for key 'user.uk_user_email'"), "email");
+        assert_eq!(duplicate_key_field("Duplicate entry '2025-10-17-2330' for key 'uk_s_disposition_date_symbol'"), "stock_date,symbol");
+        assert_eq!(duplicate_key_field("Duplicate entry '2025-10-17-2330' for key 's_disposition.uk_s_disposition_date_symbol'"), "stock_date,symbol");
+        assert_eq!(duplicate_key_field("Duplicate entry 'x' for key 'api_key.uk_api_key_prefix'"), "uk_api_key_prefix");
+    }
+
+    #[test]
+    fn maps_mysql_error_codes() {
+        assert!(matches!(mysql_error(1062, "Duplicate entry 'a' for key 'user.uk_user_email'"), AppError::Conflict { field } if field == "email"));
+        for code in [1292, 1366] {
+            assert!(matches!(mysql_error(code, "Incorrect date value"), AppError::BadRequest(_)), "{}", code);
+        }
+        for code in [1040, 1205, 1213] {
+            assert!(matches!(mysql_error(code, "busy"), AppError::Unavailable(_)), "{}", code);
+        }
+        assert!(matches!(mysql_error(1146, "Table doesn't exist"), AppError::Database(_)));
+    }
+
+    #[actix_web::test]
+    async fn response_envelope_has_status_and_code() {
+        let cases = [
+            (AppError::NotFound("x".to_string()), 404, "NOT_FOUND"),
+            (AppError::Conflict { field: "email".to_string() }, 409, "CONFLICT"),
+            (AppError::invalid("email", "格式錯誤"), 422, "VALIDATION_FAILED"),
+            (AppError::BadRequest("x".to_string()), 400, "BAD_REQUEST"),
+            (AppError::Unauthorized("x".to_string()), 401, "UNAUTHORIZED"),
+            (AppError::Forbidden("x".to_string()), 403, "FORBIDDEN"),
+            (AppError::Database("x".to_string()), 500, "DATABASE_ERROR"),
+            (AppError::Unavailable("x".to_string()), 503, "SERVICE_UNAVAILABLE"),
+            (AppError::Internal("x".to_string()), 500, "INTERNAL_ERROR"),
+        ];
+        for (error, status, code) in cases {
+            let res = error.error_response();
+            assert_eq!(res.status().as_u16(), status, "{}", code);
+            assert_eq!(res.headers().contains_key(header::WWW_AUTHENTICATE), status == 401, "{}", code);
+            let body: Value = serde_json::from_slice(&actix_web::body::to_bytes(res.into_body()).await.unwrap()).unwrap();
+            assert_eq!(body["code"], code);
+            assert_eq!(body["success"], false);
+            assert_eq!(body["message"], error.to_string());
+            match status {
+                409 => assert_eq!(body["errors"][0]["field"], "email"),
+                422 => assert_eq!((&body["errors"][0]["field"], &body["errors"][0]["message"]), (&Value::from("email"), &Value::from("格式錯誤"))),
+                _ => assert!(body.get("errors").is_none_or(Value::is_null), "{}", body),
+            }
+        }
+        assert_eq!(AppError::Conflict { field: "stock_date,symbol".to_string() }.to_string(), "該日期的處置股已存在");
+        assert_eq!(AppError::Conflict { field: "rows[0].stock_date,symbol".to_string() }.to_string(), "第 1 筆：該日期的處置股已存在");
+        assert_eq!(AppError::Conflict { field: "rows[x].symbol".to_string() }.to_string(), "rows[x].symbol 已存在");
+    }
+}
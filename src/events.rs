@@ -0,0 +1,298 @@
+use actix_web::web;
+use chrono::{Days, NaiveDate, Utc};
+use futures_util::future;
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use crate::clock::{taipei_offset, taipei_today};
+use crate::error::AppResult;
+use crate::models::{BoundaryAlert, BoundaryKind, Disposition, DispositionEvent, TwDate};
+use crate::store::DispositionStore;
+
+// 保留最近幾筆事件，讓 SSE 客戶端斷線重連時依 Last-Event-ID 補送
+const RECENT_EVENTS: usize = 1000;
+// 訂閱者處理太慢、落後超過這個數量時會收到 Lagged
+const BROADCAST_CAPACITY: usize = 256;
+// relay 每次從事件紀錄讀取的筆數
+const RELAY_BATCH: u32 = 500;
+// 沒有被喚醒時多久檢查一次事件紀錄，CLI 匯入等其他程序的寫入靠這個收到
+const RELAY_INTERVAL: Duration = Duration::from_secs(1);
+
+// 事件紀錄中的一筆；id 由 DispositionStore 依寫入順序遞增，重啟後延續
+#[derive(Debug, Clone)]
+pub struct StreamEvent {
+    pub id: u64,
+    pub event: DispositionEvent,
+}
+
+// 訂閱時要補送的事件；Reset 代表要求的 id 已不在緩衝區（例如伺服器重啟過），客戶端應重新查詢
+pub enum Replay {
+    Events(Vec<Arc<StreamEvent>>),
+    Reset,
+}
+
+struct Recent {
+    last_id: u64,
+    events: VecDeque<Arc<StreamEvent>>,
+}
+
+// 處置股異動的廣播點：DispositionStore 寫入時已在同一個 transaction 內記錄事件並排入 webhook 投遞，
+// relay 依序讀出事件後廣播給 SSE / WebSocket 訂閱者，並喚醒 webhook::Dispatcher 立即投遞
+pub struct EventHub {
+    changed: Notify,
+    wake: Arc<Notify>,
+    recent: Mutex<Recent>,
+    sender: broadcast::Sender<Arc<StreamEvent>>,
+    // 由 watch_boundaries 在台北時間換日時發出，不寫入資料庫也不補送
+    boundaries: broadcast::Sender<Arc<BoundaryAlert>>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self {
+            changed: Notify::new(),
+            wake: Arc::new(Notify::new()),
+            recent: Mutex::new(Recent { last_id: 0, events: VecDeque::with_capacity(RECENT_EVENTS) }),
+            sender: broadcast::channel(BROADCAST_CAPACITY).0,
+            boundaries: broadcast::channel(BROADCAST_CAPACITY).0,
+        }
+    }
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Dispatcher 等待的通知
+    pub fn wake(&self) -> Arc<Notify> {
+        self.wake.clone()
+    }
+
+    // handler 寫入成功後呼叫，讓 relay 立即讀取新事件，不必等下一次輪詢
+    pub fn changed(&self) {
+        self.changed.notify_one();
+    }
+
+    // 補送與開始接收在同一個鎖內完成，事件不會遺漏也不會重複
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> (Replay, broadcast::Receiver<Arc<StreamEvent>>) {
+        let recent = self.recent.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let replay = match last_event_id {
+            None => Replay::Events(Vec::new()),
+            Some(last) if last > recent.last_id => Replay::Reset,
+            Some(last) if last == recent.last_id => Replay::Events(Vec::new()),
+            // 緩衝區最舊一筆之前的事件已經丟棄，或重啟後還沒有收到事件
+            Some(last) if recent.events.front().is_none_or(|oldest| last + 1 < oldest.id) => Replay::Reset,
+            Some(last) => Replay::Events(recent.events.iter().filter(|e| e.id > last).cloned().collect()),
+        };
+        (replay, receiver)
+    }
+
+    pub fn subscribe_boundaries(&self) -> broadcast::Receiver<Arc<BoundaryAlert>> {
+        self.boundaries.subscribe()
+    }
+
+    pub fn announce(&self, alert: BoundaryAlert) {
+        let _ = self.boundaries.send(Arc::new(alert));
+    }
+
+    // 從 last_id 之後開始廣播，之前的事件不補送
+    fn resume_from(&self, last_id: u64) {
+        self.recent.lock().unwrap().last_id = last_id;
+    }
+
+    // 讀取還沒廣播過的事件並依序廣播，回傳筆數；只由 relay 呼叫
+    async fn pump(&self, store: &dyn DispositionStore) -> AppResult<usize> {
+        let after = self.recent.lock().unwrap().last_id;
+        let events = store.events_after(after, RELAY_BATCH).await?;
+        if !events.is_empty() {
+            // 新事件可能同時排入了 webhook 投遞
+            self.wake.notify_one();
+        }
+        let count = events.len();
+        for event in events {
+            self.broadcast(event);
+        }
+        Ok(count)
+    }
+
+    fn broadcast(&self, event: StreamEvent) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.last_id = event.id;
+        let event = Arc::new(event);
+        if recent.events.len() == RECENT_EVENTS {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // 沒有訂閱者時 send 會回傳錯誤，可以忽略
+        let _ = self.sender.send(event);
+    }
+}
+
+// 把事件紀錄轉成廣播：從啟動當下最新的事件之後開始，API 寫入時由 changed 喚醒，
+// 其他程序（例如 CLI 匯入）的寫入則在 RELAY_INTERVAL 內讀到
+pub async fn relay(hub: web::Data<EventHub>, store: Arc<dyn DispositionStore>) {
+    loop {
+        match store.last_event_id().await {
+            Ok(last_id) => {
+                hub.resume_from(last_id);
+                break;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "無法讀取處置股事件紀錄");
+                actix_web::rt::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+    loop {
+        match hub.pump(store.as_ref()).await {
+            // 一批滿了代表可能還有，直接再取
+            Ok(count) if count == RELAY_BATCH as usize => continue,
+            Ok(_) => {}
+            Err(e) => tracing::error!(error = %e, "無法讀取處置股事件紀錄"),
+        }
+        let changed = pin!(hub.changed.notified());
+        let sleep = pin!(actix_web::rt::time::sleep(RELAY_INTERVAL));
+        future::select(changed, sleep).await;
+    }
+}
+
+// 比較兩天各自處置中的股票：to 才有的是進入處置，from 才有的是解除處置。
+// 同一檔股票有重疊的處置期間時，以迄日最晚的一筆為準
+pub async fn boundary_alerts(store: &dyn DispositionStore, from: NaiveDate, to: NaiveDate) -> AppResult<Vec<BoundaryAlert>> {
+    let by_symbol = |rows: Vec<Disposition>| {
+        let mut map: BTreeMap<i32, Disposition> = BTreeMap::new();
+        for row in rows {
+            match map.get(&row.symbol) {
+                Some(current) if current.end >= row.end => {}
+                _ => {
+                    map.insert(row.symbol, row);
+                }
+            }
+        }
+        map
+    };
+    let before = by_symbol(store.get_active(from).await?);
+    let mut after = by_symbol(store.get_active(to).await?);
+
+    let mut alerts = Vec::new();
+    for (symbol, data) in before {
+        if after.remove(&symbol).is_none() {
+            alerts.push(BoundaryAlert { kind: BoundaryKind::Left, date: TwDate(to), data });
+        }
+    }
+    alerts.extend(after.into_values().map(|data| BoundaryAlert { kind: BoundaryKind::Entered, date: TwDate(to), data }));
+    alerts.sort_by_key(|a| a.data.symbol);
+    Ok(alerts)
+}
+
+// 距離下一個台北時間午夜還有多久
+fn until_next_day() -> Duration {
+    let now = Utc::now().with_timezone(&taipei_offset());
+    let midnight = now.date_naive().checked_add_days(Days::new(1))
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|d| d.and_local_timezone(taipei_offset()).single());
+    match midnight {
+        Some(midnight) => (midnight - now).to_std().unwrap_or_default(),
+        None => Duration::from_secs(60),
+    }
+}
+
+// 每到台北時間換日，比較前一天與當天處置中的股票並發出 BoundaryAlert。
+// 換日時查詢失敗會在一分鐘後重試，同一個 from 不會漏掉
+pub async fn watch_boundaries(hub: web::Data<EventHub>, store: Arc<dyn DispositionStore>) {
+    let mut from = taipei_today();
+    loop {
+        let to = taipei_today();
+        if to <= from {
+            // 多等一秒，避免計時誤差導致醒來時還停在前一天
+            actix_web::rt::time::sleep(until_next_day() + Duration::from_secs(1)).await;
+            continue;
+        }
+        match boundary_alerts(store.as_ref(), from, to).await {
+            Ok(alerts) => {
+                for alert in alerts {
+                    hub.announce(alert);
+                }
+                from = to;
+            }
+            Err(e) => {
+                tracing::error!(date = %to, error = %e, "無法檢查處置期間異動");
+                actix_web::rt::time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryDispositionStore;
+    use crate::models::{CreateDisposition, DispositionEventKind};
+
+    fn disposition(symbol: &str, start: &str, end: &str) -> CreateDisposition {
+        CreateDisposition {
+            stock_date: TwDate::parse("114/10/17").unwrap(),
+            market: "TWSE".to_string(),
+            symbol: symbol.to_string(),
+            name: "測試".to_string(),
+            start: TwDate::parse(start),
+            end: TwDate::parse(end),
+        }
+    }
+
+    fn replayed(replay: Replay) -> Option<Vec<u64>> {
+        match replay {
+            Replay::Events(events) => Some(events.iter().map(|e| e.id).collect()),
+            Replay::Reset => None,
+        }
+    }
+
+    // 依 Last-Event-ID 補送之後的事件，之後的新事件從 receiver 收到；未知的 id 要求重新查詢
+    #[actix_web::test]
+    async fn resumes_after_last_event_id() {
+        let store = InMemoryDispositionStore::new();
+        let hub = EventHub::new();
+        for symbol in ["1101", "2330", "2317"] {
+            store.create(&disposition(symbol, "114/10/20", "114/10/31")).await.unwrap();
+        }
+        assert_eq!(hub.pump(&store).await.unwrap(), 3);
+
+        let (replay, mut receiver) = hub.subscribe(Some(1));
+        assert_eq!(replayed(replay), Some(vec![2, 3]));
+        store.delete(2330).await.unwrap();
+        assert_eq!(hub.pump(&store).await.unwrap(), 1);
+        let next = receiver.recv().await.unwrap();
+        assert_eq!((next.id, next.event.event, next.event.data.symbol), (4, DispositionEventKind::Deleted, 2330));
+
+        assert_eq!(replayed(hub.subscribe(Some(4)).0), Some(vec![]));
+        assert_eq!(replayed(hub.subscribe(None).0), Some(vec![]));
+        assert_eq!(replayed(hub.subscribe(Some(99)).0), None);
+
+        // 重啟後從最新的事件接續，緩衝區是空的，較舊的 id 要求重新查詢
+        let restarted = EventHub::new();
+        restarted.resume_from(store.last_event_id().await.unwrap());
+        assert_eq!(replayed(restarted.subscribe(Some(4)).0), Some(vec![]));
+        assert_eq!(replayed(restarted.subscribe(Some(2)).0), None);
+    }
+
+    // 起日當天進入處置、迄日隔天解除處置；期間中間的日子沒有提醒
+    #[actix_web::test]
+    async fn detects_period_boundaries() {
+        let store = InMemoryDispositionStore::new();
+        for (symbol, start, end) in [("2330", "114/10/20", "114/10/31"), ("1101", "114/10/08", "114/10/20")] {
+            store.create(&disposition(symbol, start, end)).await.unwrap();
+        }
+        let day = |value: &str| TwDate::parse(value).unwrap().0;
+        let kinds = |alerts: Vec<BoundaryAlert>| alerts.into_iter().map(|a| (a.kind, a.data.symbol)).collect::<Vec<_>>();
+
+        assert_eq!(kinds(boundary_alerts(&store, day("114/10/19"), day("114/10/20")).await.unwrap()), [(BoundaryKind::Entered, 2330)]);
+        assert_eq!(kinds(boundary_alerts(&store, day("114/10/20"), day("114/10/21")).await.unwrap()), [(BoundaryKind::Left, 1101)]);
+        assert_eq!(kinds(boundary_alerts(&store, day("114/10/22"), day("114/10/23")).await.unwrap()), []);
+        let alert = boundary_alerts(&store, day("114/10/31"), day("114/11/01")).await.unwrap().remove(0);
+        assert_eq!((alert.kind, alert.data.symbol, alert.date.0), (BoundaryKind::Left, 2330, day("114/11/01")));
+    }
+}
@@ -0,0 +1,383 @@
+use chrono::NaiveDate;
+use serde_json::Value as JsonValue;
+use crate::error::{AppError, AppResult};
+use crate::import::{self, ParsedRow};
+use crate::models::{parse_roc_date, CreateDisposition, FieldError, TwDate};
+
+// 證交所（上市）與櫃買中心（上櫃）的處置有價證券公告
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exchange {
+    Twse,
+    Tpex,
+}
+
+impl Exchange {
+    pub fn parse(value: &str) -> Option<Exchange> {
+        match value.to_ascii_lowercase().as_str() {
+            "twse" => Some(Exchange::Twse),
+            "tpex" => Some(Exchange::Tpex),
+            _ => None,
+        }
+    }
+
+    // 對應 s_disposition.market
+    pub fn market(&self) -> &'static str {
+        match self {
+            Exchange::Twse => "TWSE",
+            Exchange::Tpex => "TPEx",
+        }
+    }
+}
+
+// 各種公告格式的欄位名稱：網站 JSON/CSV 用中文標題，OpenAPI 用英文 key
+const DATE_COLUMNS: &[&str] = &["公布日期", "公告日期", "Date"];
+const SYMBOL_COLUMNS: &[&str] = &["證券代號", "Code", "SecuritiesCompanyCode"];
+const NAME_COLUMNS: &[&str] = &["證券名稱", "Name", "CompanyName"];
+const PERIOD_COLUMNS: &[&str] = &["處置起迄時間", "處置起訖時間", "處置期間", "DispositionPeriod"];
+
+// 處置期間的起迄分隔符號，公告中全形、半形都有
+const PERIOD_SEPARATORS: &[char] = &['～', '~', '至', '－'];
+
+// 「114/10/20～114/10/31」拆成起迄日
+pub fn parse_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (start, end) = value.split_once(PERIOD_SEPARATORS)?;
+    Some((parse_roc_date(start)?, parse_roc_date(end)?))
+}
+
+// 自動判斷 JSON 或 CSV；CSV 可以是 UTF-8（可含 BOM）或證交所下載的 Big5
+pub fn parse_announcement(exchange: Exchange, body: &[u8]) -> AppResult<Vec<ParsedRow>> {
+    let text = decode(body);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let rows = if text.starts_with('[') || text.starts_with('{') {
+        parse_json(exchange, text)?
+    } else {
+        parse_csv(exchange, text)?
+    };
+    // 當天沒有處置股時公告檔沒有資料列，回傳空的報表而不是錯誤
+    import::check_row_limit(&rows)?;
+    Ok(rows)
+}
+
+fn decode(body: &[u8]) -> String {
+    match std::str::from_utf8(body) {
+        Ok(text) => text.to_string(),
+        Err(_) => encoding_rs::BIG5.decode(body).0.into_owned(),
+    }
+}
+
+// 支援：物件陣列（OpenAPI）、{ fields, data }（證交所網站）、{ tables: [{ fields, data }] }（櫃買中心網站）
+fn parse_json(exchange: Exchange, text: &str) -> AppResult<Vec<ParsedRow>> {
+    let value: JsonValue = serde_json::from_str(text)
+        .map_err(|e| AppError::BadRequest(format!("無效的 JSON 公告檔: {}", e)))?;
+
+    if let JsonValue::Array(items) = &value {
+        return items.iter()
+            .map(|item| {
+                let object = item.as_object()
+                    .ok_or_else(|| AppError::BadRequest("公告 JSON 陣列的元素需為物件".to_string()))?;
+                let (headers, cells): (Vec<String>, Vec<String>) = object.iter()
+                    .map(|(key, value)| (key.clone(), json_text(value)))
+                    .unzip();
+                Ok(Columns::find(&headers)?.row(exchange, &cells))
+            })
+            .collect();
+    }
+
+    let tables: Vec<&JsonValue> = match value.get("tables").and_then(JsonValue::as_array) {
+        Some(tables) => tables.iter().collect(),
+        None => vec![&value],
+    };
+    let mut rows = Vec::new();
+    for table in tables {
+        // 查無資料時證交所只回傳 stat，沒有 fields/data
+        let (Some(fields), Some(data)) = (
+            table.get("fields").and_then(JsonValue::as_array),
+            table.get("data").and_then(JsonValue::as_array),
+        ) else {
+            continue;
+        };
+        let headers: Vec<String> = fields.iter().map(json_text).collect();
+        let columns = Columns::find(&headers)?;
+        for record in data {
+            let cells: Vec<String> = record.as_array()
+                .map(|cells| cells.iter().map(json_text).collect())
+                .unwrap_or_default();
+            rows.push(columns.row(exchange, &cells));
+        }
+    }
+    Ok(rows)
+}
+
+fn json_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+// 網站下載的 CSV 前面有標題、後面有說明文字，找到含「證券代號」的標題列後，之後有代號的列才是資料
+fn parse_csv(exchange: Exchange, text: &str) -> AppResult<Vec<ParsedRow>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+
+    let mut columns: Option<Columns> = None;
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| AppError::BadRequest(format!("無效的 CSV 公告檔: {}", e)))?;
+        let cells: Vec<String> = record.iter().map(csv_text).collect();
+        match &columns {
+            None => {
+                if cells.iter().any(|cell| SYMBOL_COLUMNS.contains(&cell.as_str())) {
+                    columns = Some(Columns::find(&cells)?);
+                }
+            }
+            Some(columns) => {
+                if cells.get(columns.symbol).is_some_and(|symbol| !symbol.is_empty()) {
+                    rows.push(columns.row(exchange, &cells));
+                }
+            }
+        }
+    }
+    if columns.is_none() {
+        return Err(AppError::BadRequest("CSV 公告檔找不到「證券代號」標題列".to_string()));
+    }
+    Ok(rows)
+}
+
+// 證交所部分 CSV 以 ="0050" 保留代號前導零
+fn csv_text(cell: &str) -> String {
+    cell.strip_prefix("=\"")
+        .and_then(|cell| cell.strip_suffix('"'))
+        .unwrap_or(cell)
+        .trim()
+        .to_string()
+}
+
+struct Columns {
+    date: usize,
+    symbol: usize,
+    name: usize,
+    period: Option<usize>,
+}
+
+impl Columns {
+    fn find(headers: &[String]) -> AppResult<Columns> {
+        let position = |names: &[&str]| headers.iter().position(|h| names.contains(&h.trim()));
+        let required = |names: &[&str]| {
+            position(names).ok_or_else(|| AppError::BadRequest(format!("公告檔缺少「{}」欄位", names[0])))
+        };
+        Ok(Columns {
+            date: required(DATE_COLUMNS)?,
+            symbol: required(SYMBOL_COLUMNS)?,
+            name: required(NAME_COLUMNS)?,
+            period: position(PERIOD_COLUMNS),
+        })
+    }
+
+    fn last(&self) -> usize {
+        self.date.max(self.symbol).max(self.name).max(self.period.unwrap_or(0))
+    }
+
+    // 欄位數不足、日期或期間無法解析時該列無效，其餘欄位交給 CreateDisposition 的驗證
+    fn row(&self, exchange: Exchange, cells: &[String]) -> ParsedRow {
+        if cells.len() <= self.last() {
+            return Err(import::row_error(format!("欄位數不足：需要 {} 欄，只有 {} 欄", self.last() + 1, cells.len())));
+        }
+        let cell = |i: usize| cells.get(i).map(|c| c.trim()).unwrap_or_default();
+        let mut errors = Vec::new();
+
+        let stock_date = parse_roc_date(cell(self.date));
+        if stock_date.is_none() {
+            errors.push(FieldError { field: "stock_date".to_string(), message: format!("無法解析公布日期 '{}'", cell(self.date)) });
+        }
+        let period = match self.period.map(cell).filter(|p| !p.is_empty()) {
+            Some(text) => {
+                let period = parse_period(text);
+                if period.is_none() {
+                    errors.push(FieldError { field: "period".to_string(), message: format!("無法解析處置期間 '{}'", text) });
+                }
+                period
+            }
+            None => None,
+        };
+        let Some(stock_date) = stock_date.filter(|_| errors.is_empty()) else {
+            return Err(errors);
+        };
+
+        import::validated(CreateDisposition {
+            stock_date: TwDate(stock_date),
+            market: exchange.market().to_string(),
+            symbol: cell(self.symbol).to_string(),
+            name: cell(self.name).to_string(),
+            start: period.map(|(start, _)| TwDate(start)),
+            end: period.map(|(_, end)| TwDate(end)),
+        })
+        .map_err(|mut errors| {
+            // 代號不是純數字（例如 ETF 00632R）時也一併顯示代號
+            for error in errors.iter_mut().filter(|e| e.field == "symbol") {
+                error.message = format!("{}: '{}'", error.message, cell(self.symbol));
+            }
+            errors
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryDispositionStore;
+    use crate::models::{BulkRowStatus, ConflictMode};
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/fixtures/exchange/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("無法讀取 {}: {}", path, e))
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    // (stock_date, market, symbol, name, start, end)，無效列為 None
+    type Summary = Option<(String, String, String, String, Option<String>, Option<String>)>;
+
+    fn summary(rows: &[ParsedRow]) -> Vec<Summary> {
+        rows.iter()
+            .map(|row| row.as_ref().ok().map(|d| {
+                (d.stock_date.iso(), d.market.clone(), d.symbol.clone(), d.name.clone(), d.start.map(|s| s.iso()), d.end.map(|e| e.iso()))
+            }))
+            .collect()
+    }
+
+    fn row(stock_date: &str, market: &str, symbol: &str, name: &str, start: &str, end: &str) -> Summary {
+        Some((stock_date.into(), market.into(), symbol.into(), name.into(), Some(start.into()), Some(end.into())))
+    }
+
+    #[test]
+    fn parses_roc_dates_and_periods() {
+        assert_eq!(parse_roc_date("114/03/05"), Some(date(2025, 3, 5)));
+        assert_eq!(parse_roc_date(" 114.3.5 "), Some(date(2025, 3, 5)));
+        assert_eq!(parse_roc_date("114年03月05日"), Some(date(2025, 3, 5)));
+        assert_eq!(parse_roc_date("1140305"), Some(date(2025, 3, 5)));
+        assert_eq!(parse_roc_date("991231"), Some(date(2010, 12, 31)));
+        assert_eq!(parse_roc_date("2025-03-05"), None);
+        assert_eq!(parse_roc_date("114/02/30"), None);
+        assert_eq!(parse_roc_date(""), None);
+
+        assert_eq!(parse_period("114/10/20～114/10/31"), Some((date(2025, 10, 20), date(2025, 10, 31))));
+        assert_eq!(parse_period("1141020 ~ 1141031"), Some((date(2025, 10, 20), date(2025, 10, 31))));
+        assert_eq!(parse_period("114/10/20"), None);
+    }
+
+    #[test]
+    fn parses_twse_website_json() {
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_punish.json")).unwrap();
+        assert_eq!(summary(&rows), vec![
+            row("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            row("2025-10-17", "TWSE", "3661", "世芯-KY", "2025-10-20", "2025-11-03"),
+            None,
+        ]);
+        // ETF 代號含英文字母，不符合 s_disposition.symbol
+        let errors = rows[2].as_ref().unwrap_err();
+        assert_eq!(errors[0].field, "symbol");
+        assert!(errors[0].message.contains("00632R"));
+    }
+
+    #[test]
+    fn parses_twse_openapi_json() {
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_openapi_punish.json")).unwrap();
+        assert_eq!(summary(&rows), vec![
+            row("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            row("2025-10-17", "TWSE", "2603", "長榮", "2025-10-20", "2025-10-31"),
+        ]);
+    }
+
+    #[test]
+    fn parses_twse_big5_csv() {
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_punish.csv")).unwrap();
+        assert_eq!(summary(&rows), vec![
+            row("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            row("2025-10-17", "TWSE", "3661", "世芯-KY", "2025-10-20", "2025-11-03"),
+        ]);
+    }
+
+    #[test]
+    fn parses_tpex_website_json() {
+        let rows = parse_announcement(Exchange::Tpex, &fixture("tpex_disposal.json")).unwrap();
+        assert_eq!(summary(&rows), vec![
+            row("2025-10-17", "TPEx", "6488", "環球晶", "2025-10-20", "2025-10-31"),
+            row("2025-10-17", "TPEx", "8069", "元太", "2025-10-20", "2025-10-31"),
+        ]);
+    }
+
+    #[test]
+    fn parses_tpex_csv_with_bom_and_footer() {
+        let rows = parse_announcement(Exchange::Tpex, &fixture("tpex_disposal.csv")).unwrap();
+        assert_eq!(summary(&rows), vec![
+            row("2025-10-17", "TPEx", "6488", "環球晶", "2025-10-20", "2025-10-31"),
+            row("2025-10-17", "TPEx", "8069", "元太", "2025-10-20", "2025-10-31"),
+            None,
+        ]);
+        assert_eq!(rows[2].as_ref().unwrap_err()[0].field, "period");
+    }
+
+    #[test]
+    fn rejects_files_without_announcement_columns() {
+        assert!(parse_announcement(Exchange::Twse, b"a,b,c\n1,2,3\n").is_err());
+        assert!(parse_announcement(Exchange::Twse, br#"{"stat":"OK","fields":["x"],"data":[["1"]]}"#).is_err());
+    }
+
+    // 當天沒有處置股：查無資料的 JSON、只有標題列的 CSV 都匯入成空的報表
+    #[actix_web::test]
+    async fn empty_announcement_is_empty_report() {
+        let store = InMemoryDispositionStore::new();
+        let empty: [&[u8]; 3] = [
+            "{\"stat\":\"很抱歉，沒有符合條件的資料!\"}".as_bytes(),
+            b"[]",
+            "編號,公告日期,證券代號,證券名稱,處置期間\n共0筆\n".as_bytes(),
+        ];
+        for body in empty {
+            let rows = parse_announcement(Exchange::Tpex, body).unwrap();
+            let report = import::run(&store, ConflictMode::Fail, rows).await.unwrap();
+            assert_eq!((report.total, report.created, report.invalid), (0, 0, 0));
+        }
+    }
+
+    // 有代號但被截斷的列不能默默略過，也不能少了處置期間就匯入
+    #[test]
+    fn short_rows_are_invalid() {
+        let csv = "編號,公告日期,證券代號,證券名稱,累計次數,處置期間\n1,114/10/17,6488,環球晶,1\n2,114/10/17,8069,元太,1,114/10/20~114/10/31\n";
+        let rows = parse_announcement(Exchange::Tpex, csv.as_bytes()).unwrap();
+        assert_eq!(summary(&rows), vec![None, row("2025-10-17", "TPEx", "8069", "元太", "2025-10-20", "2025-10-31")]);
+        let errors = rows[0].as_ref().unwrap_err();
+        assert_eq!(errors[0].field, "__all__");
+        assert!(errors[0].message.contains("欄位數不足"), "{}", errors[0].message);
+
+        let json = r#"{"fields":["公告日期","證券代號","證券名稱","處置期間"],"data":[["114/10/17","2330"]]}"#;
+        let rows = parse_announcement(Exchange::Twse, json.as_bytes()).unwrap();
+        assert_eq!(summary(&rows), vec![None]);
+    }
+
+    // 同一份公告匯入兩次：第一次新增，第二次 skip 全部略過
+    #[actix_web::test]
+    async fn imports_fixture_into_store() {
+        let store = InMemoryDispositionStore::new();
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_punish.json")).unwrap();
+        let report = import::run(&store, ConflictMode::Skip, rows).await.unwrap();
+        assert_eq!((report.created, report.skipped, report.invalid), (2, 0, 1));
+
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_openapi_punish.json")).unwrap();
+        let report = import::run(&store, ConflictMode::Skip, rows).await.unwrap();
+        assert_eq!(
+            report.rows.iter().map(|r| r.status).collect::<Vec<_>>(),
+            vec![BulkRowStatus::Skipped, BulkRowStatus::Created]
+        );
+
+        let rows = parse_announcement(Exchange::Twse, &fixture("twse_openapi_punish.json")).unwrap();
+        assert!(matches!(import::run(&store, ConflictMode::Fail, rows).await, Err(AppError::Conflict { .. })));
+    }
+}
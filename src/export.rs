@@ -0,0 +1,235 @@
+use actix_web::http::header::ContentDisposition;
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use chrono::NaiveDateTime;
+use futures_util::stream::{self, Stream, StreamExt};
+use rust_xlsxwriter::{Format, Workbook};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use crate::clock::taipei_today;
+use crate::error::{AppError, AppResult};
+use crate::models::{DateFormat, Disposition, ExportFormat, TwDate, User};
+use crate::store::RowStream;
+
+// CSV 開頭加上 UTF-8 BOM，Excel 直接開啟時中文才不會變成亂碼
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+const XLSX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+pub enum Cell {
+    Number(f64),
+    Text(String),
+    Empty,
+}
+
+// 可以匯出的資料列；欄位名稱與批次匯入相同，匯出的 CSV 可以直接再匯入
+pub trait ExportRow: Send + 'static {
+    const HEADERS: &'static [&'static str];
+    // 檔名前綴，例如 dispositions-20251017.csv
+    const FILE_NAME: &'static str;
+    fn cells(&self, format: DateFormat) -> Vec<Cell>;
+}
+
+fn date_cell(date: Option<TwDate>, format: DateFormat) -> Cell {
+    date.map_or(Cell::Empty, |d| Cell::Text(d.format(format)))
+}
+
+fn datetime_cell(value: Option<NaiveDateTime>) -> Cell {
+    value.map_or(Cell::Empty, |v| Cell::Text(v.format("%Y-%m-%d %H:%M:%S").to_string()))
+}
+
+impl ExportRow for Disposition {
+    const HEADERS: &'static [&'static str] = &["id", "stock_date", "market", "symbol", "name", "start", "end", "created_at", "updated_at"];
+    const FILE_NAME: &'static str = "dispositions";
+
+    fn cells(&self, format: DateFormat) -> Vec<Cell> {
+        vec![
+            Cell::Number(self.id as f64),
+            date_cell(self.stock_date, format),
+            Cell::Text(self.market.clone()),
+            Cell::Number(self.symbol as f64),
+            Cell::Text(self.name.clone()),
+            date_cell(self.start, format),
+            date_cell(self.end, format),
+            datetime_cell(self.created_at),
+            datetime_cell(self.updated_at),
+        ]
+    }
+}
+
+impl ExportRow for User {
+    const HEADERS: &'static [&'static str] = &["id", "name", "email", "role", "created_at", "updated_at"];
+    const FILE_NAME: &'static str = "users";
+
+    fn cells(&self, _format: DateFormat) -> Vec<Cell> {
+        vec![
+            Cell::Number(self.id as f64),
+            Cell::Text(self.name.clone()),
+            Cell::Text(self.email.clone()),
+            Cell::Text(self.role.as_str().to_string()),
+            datetime_cell(self.created_at),
+            datetime_cell(self.updated_at),
+        ]
+    }
+}
+
+// 日期格式由 handler 傳入：串流的 body 在 DateFormatScope 之外才被讀取，拿不到 DATE_FORMAT
+pub async fn respond<T: ExportRow>(rows: RowStream<T>, export: ExportFormat, format: DateFormat) -> AppResult<HttpResponse> {
+    let file_name = format!("{}-{}", T::FILE_NAME, taipei_today().format("%Y%m%d"));
+    match export {
+        ExportFormat::Csv => csv_response(rows, format, &file_name).await,
+        ExportFormat::Xlsx => xlsx_response(rows, format, &file_name).await,
+    }
+}
+
+fn csv_record(fields: impl IntoIterator<Item = String>) -> AppResult<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(fields)
+        .map_err(|e| AppError::Unavailable(format!("產生 CSV 失敗: {}", e)))?;
+    writer.into_inner().map_err(|e| AppError::Unavailable(format!("產生 CSV 失敗: {}", e.error())))
+}
+
+fn csv_line<T: ExportRow>(row: &T, format: DateFormat) -> AppResult<Bytes> {
+    let fields = row.cells(format).into_iter().map(|cell| match cell {
+        Cell::Number(n) => n.to_string(),
+        Cell::Text(s) => s,
+        Cell::Empty => String::new(),
+    });
+    csv_record(fields).map(Bytes::from)
+}
+
+// 逐列編碼後送出，不會在記憶體中組出整個檔案
+async fn csv_response<T: ExportRow>(mut rows: RowStream<T>, format: DateFormat, file_name: &str) -> AppResult<HttpResponse> {
+    let mut head = UTF8_BOM.to_vec();
+    head.extend(csv_record(T::HEADERS.iter().map(|h| h.to_string()))?);
+    // 先讀第一列：查詢一開始就失敗時（例如連不上資料庫）還能回傳正常的錯誤回應
+    if let Some(first) = rows.recv().await {
+        head.extend_from_slice(&csv_line(&first?, format)?);
+    }
+
+    let body = stream::once(async move { Ok::<_, AppError>(Bytes::from(head)) })
+        .chain(stream::unfold(rows, move |mut rows| async move {
+            let row = rows.recv().await?;
+            Some((row.and_then(|row| csv_line(&row, format)), rows))
+        }));
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition::attachment(format!("{}.csv", file_name)))
+        .streaming(body))
+}
+
+// xlsx 是 zip 檔，無法邊產生邊送出：工作表以 constant memory 模式寫入，
+// 完成的檔案存到暫存檔後再分段讀出，記憶體不會保留整個檔案；暫存檔在回應結束時刪除
+async fn xlsx_response<T: ExportRow>(mut rows: RowStream<T>, format: DateFormat, file_name: &str) -> AppResult<HttpResponse> {
+    let file = web::block(move || -> AppResult<File> {
+        let xlsx_error = |e: rust_xlsxwriter::XlsxError| AppError::Unavailable(format!("產生 Excel 檔失敗: {}", e));
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        sheet.write_row_with_format(0, 0, T::HEADERS.iter().copied(), &Format::new().set_bold()).map_err(xlsx_error)?;
+
+        let mut line: u32 = 0;
+        while let Some(row) = rows.blocking_recv() {
+            line += 1;
+            for (col, cell) in row?.cells(format).into_iter().enumerate() {
+                let col = col as u16;
+                match cell {
+                    Cell::Number(n) => sheet.write_number(line, col, n).map_err(xlsx_error)?,
+                    Cell::Text(s) => sheet.write_string(line, col, s).map_err(xlsx_error)?,
+                    Cell::Empty => sheet,
+                };
+            }
+        }
+
+        let mut file = tempfile::tempfile().map_err(temp_file_error)?;
+        workbook.save_to_writer(&mut file).map_err(xlsx_error)?;
+        file.seek(SeekFrom::Start(0)).map_err(temp_file_error)?;
+        Ok(file)
+    })
+    .await
+    .map_err(|e| AppError::Unavailable(format!("產生 Excel 檔失敗: {}", e)))??;
+
+    Ok(HttpResponse::Ok()
+        .content_type(XLSX_CONTENT_TYPE)
+        .insert_header(ContentDisposition::attachment(format!("{}.xlsx", file_name)))
+        .streaming(file_chunks(file)))
+}
+
+// 每次送出的暫存檔區塊大小
+const FILE_CHUNK: usize = 64 * 1024;
+
+fn temp_file_error(e: std::io::Error) -> AppError {
+    AppError::Unavailable(format!("寫入暫存檔失敗: {}", e))
+}
+
+// 在 blocking 執行緒上逐段讀取檔案，讀到結尾或發生錯誤後結束
+fn file_chunks(file: File) -> impl Stream<Item = AppResult<Bytes>> {
+    stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let read = web::block(move || {
+            let mut chunk = vec![0; FILE_CHUNK];
+            let n = file.read(&mut chunk)?;
+            chunk.truncate(n);
+            Ok::<_, std::io::Error>((chunk, file))
+        })
+        .await;
+        match read {
+            Ok(Ok((chunk, _))) if chunk.is_empty() => None,
+            Ok(Ok((chunk, file))) => Some((Ok(Bytes::from(chunk)), Some(file))),
+            Ok(Err(e)) => Some((Err(temp_file_error(e)), None)),
+            Err(e) => Some((Err(AppError::Unavailable(format!("讀取暫存檔失敗: {}", e))), None)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use tokio::sync::mpsc;
+
+    fn rows(count: u64) -> RowStream<Disposition> {
+        let (tx, rx) = mpsc::channel(8);
+        for id in 1..=count {
+            tx.try_send(Ok(Disposition {
+                id,
+                stock_date: TwDate::parse("114/10/17"),
+                market: "TWSE".to_string(),
+                symbol: 2330,
+                name: "台積電, \"普通股\"".to_string(),
+                start: TwDate::parse("114/10/20"),
+                end: None,
+                created_at: None,
+                updated_at: None,
+            })).unwrap();
+        }
+        rx
+    }
+
+    // BOM 之後是標題列，接著每列一行；含逗號或引號的欄位照 CSV 規則加上引號
+    #[actix_web::test]
+    async fn csv_starts_with_bom_and_header() {
+        let response = respond(rows(2), ExportFormat::Csv, DateFormat::Roc).await.unwrap();
+        let disposition = response.headers().get("content-disposition").unwrap().to_str().unwrap().to_string();
+        assert!(disposition.contains("dispositions-") && disposition.contains(".csv"), "{}", disposition);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert!(body.starts_with(UTF8_BOM));
+        let text = std::str::from_utf8(&body[UTF8_BOM.len()..]).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), [
+            "id,stock_date,market,symbol,name,start,end,created_at,updated_at",
+            r#"1,114/10/17,TWSE,2330,"台積電, ""普通股""",114/10/20,,,"#,
+            r#"2,114/10/17,TWSE,2330,"台積電, ""普通股""",114/10/20,,,"#,
+        ]);
+    }
+
+    // xlsx 從暫存檔分段送出，內容為完整的 zip 檔
+    #[actix_web::test]
+    async fn xlsx_is_streamed_from_temp_file() {
+        let response = respond(rows(3), ExportFormat::Xlsx, DateFormat::Iso).await.unwrap();
+        assert_eq!(response.headers().get("content-type").unwrap(), XLSX_CONTENT_TYPE);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert!(body.starts_with(b"PK\x03\x04"));
+        assert!(body.windows(4).any(|w| w == b"PK\x05\x06"), "缺少 zip 結尾");
+    }
+}
@@ -1,215 +1,905 @@
-use actix_web::{web, HttpResponse};
-use crate::models::{User, CreateUser, UpdateUser, Disposition, CreateDisposition, UpdateDisposition, ApiResponse};
-use crate::repository::{UserRepository, DispositionRepository};
-use crate::db::DbPool;
-
-macro_rules! get_conn {
-    ($pool:expr, $type:ty) => {
-        match $pool.get_conn() {
-            Ok(conn) => conn,
-            Err(e) => {
-                return HttpResponse::InternalServerError().json(
-                    ApiResponse::<$type>::error(&format!("資料庫連接失敗: {}", e))
-                );
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::models::{Role, CreateApiKey, CreatedApiKey, LoginRequest, RefreshRequest, ChangePassword, PasswordResetRequest, PasswordResetConfirm, CreateUser, UpdateUser, CreateDispositionRequest, UpdateDispositionRequest, BulkImportQuery, LogLevel, MARKETS, CreateWebhook, CreatedWebhook, UpdateWebhook, ExportQuery, DispositionFilter, DateFormat, DATE_FORMAT, AsOfQuery, ActiveStatus, TwDate, ApiResponse, Page};
+use crate::store::{UserStore, DispositionStore, ApiKeyStore, WebhookStore};
+use crate::api_key;
+use crate::import;
+use crate::export;
+use crate::calendar;
+use crate::events::EventHub;
+use crate::sse;
+use crate::ws;
+use crate::openapi;
+use crate::logging::{LevelHandle, LOG_LEVELS};
+use crate::metrics::{Gauge, METRICS};
+use crate::webhook;
+use crate::exchange::{self, Exchange};
+use crate::pagination::{PageMode, PageQuery, PageRequest};
+use crate::clock::taipei_today;
+use crate::auth::{Principal, TokenKind, TokenService};
+use crate::config::{MailConfig, MetricsConfig, PasswordResetConfig};
+use crate::mail::{password_reset_mail, Mailer};
+use crate::password;
+use crate::error::{AppError, AppResult};
+use validator::Validate;
+
+pub async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse::success("OK", "服務正常運行"))
+}
+
+// OpenAPI 文件本身不包在 ApiResponse 中，讓產生 client 的工具可以直接讀取
+pub async fn get_openapi() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::document())
+}
+
+pub async fn get_docs() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/html; charset=utf-8").body(openapi::SWAGGER_UI_HTML)
+}
+
+// Prometheus 抓取用，不包在 ApiResponse 中。處置中的檔數在抓取時依台北今日計算
+pub async fn get_metrics(
+    req: HttpRequest,
+    config: web::Data<MetricsConfig>,
+    store: web::Data<dyn DispositionStore>,
+) -> AppResult<HttpResponse> {
+    if let Some(token) = &config.token {
+        let given = req.headers().get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        // 比對摘要，避免逐字比較的時間差洩漏 token
+        if given.map(password::token_digest) != Some(password::token_digest(token)) {
+            return Err(AppError::Unauthorized("需要有效的 metrics token".to_string()));
+        }
+    }
+
+    let mut active: BTreeMap<&str, BTreeSet<i32>> = MARKETS.iter().map(|m| (*m, BTreeSet::new())).collect();
+    for d in store.get_active(taipei_today()).await? {
+        if let Some(symbols) = active.get_mut(d.market.as_str()) {
+            symbols.insert(d.symbol);
+        }
+    }
+    let gauges: Vec<Gauge> = active.into_iter().map(|(market, symbols)| Gauge {
+        name: "disposition_active",
+        help: "今日處置中的股票數",
+        labels: vec![("market", market.to_string())],
+        value: symbols.len() as f64,
+    }).collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(METRICS.render(&gauges)))
+}
+
+// 執行期間調整 log level，重啟後恢復為設定檔的 log.level
+pub async fn get_log_level(level: web::Data<LevelHandle>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiResponse::success(LogLevel { level: level.get().to_string() }, "成功獲取 log level"))
+}
+
+pub async fn update_log_level(level: web::Data<LevelHandle>, body: web::Json<LogLevel>) -> AppResult<HttpResponse> {
+    if !level.set(&body.level) {
+        return Err(AppError::invalid("level", format!("log level 必須是 {} 其中之一", LOG_LEVELS.join(", "))));
+    }
+    tracing::warn!(log_level = level.get(), "已調整 log level");
+    Ok(HttpResponse::Ok().json(ApiResponse::success(LogLevel { level: level.get().to_string() }, "成功調整 log level")))
+}
+
+fn user_not_found(id: u32) -> AppError {
+    AppError::NotFound(format!("找不到 ID 為 {} 的使用者", id))
+}
+
+fn disposition_not_found(symbol: i32) -> AppError {
+    AppError::NotFound(format!("找不到 Symbol 為 {} 的處置股", symbol))
+}
+
+fn period_not_found(symbol: i32, id: u64) -> AppError {
+    AppError::NotFound(format!("找不到 Symbol 為 {} 且 ID 為 {} 的處置股", symbol, id))
+}
+
+pub async fn login(
+    store: web::Data<dyn UserStore>,
+    tokens: web::Data<TokenService>,
+    body: web::Json<LoginRequest>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let invalid = || AppError::Unauthorized("帳號或密碼錯誤".to_string());
+    let user = store.get_by_email(&body.email).await?.ok_or_else(invalid)?;
+    // 尚未設定密碼的帳號需先走重設密碼流程
+    let hash = store.get_password_hash(user.id).await?.ok_or_else(invalid)?;
+    if !password::verify(body.password.clone(), hash).await? {
+        return Err(invalid());
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tokens.issue(user)?, "登入成功")))
+}
+
+pub async fn refresh_token(
+    store: web::Data<dyn UserStore>,
+    tokens: web::Data<TokenService>,
+    body: web::Json<RefreshRequest>,
+) -> AppResult<HttpResponse> {
+    let claims = tokens.verify(&body.refresh_token, TokenKind::Refresh)?;
+    // 使用者被刪除後 refresh token 就不能再用
+    let user = store.get_by_id(claims.user_id()?).await?
+        .ok_or_else(|| AppError::Unauthorized("使用者不存在".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tokens.issue(user)?, "成功換發 token")))
+}
+
+pub async fn change_password(
+    store: web::Data<dyn UserStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<u32>,
+    body: web::Json<ChangePassword>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let id = path.into_inner();
+    // 關閉登入驗證時沒有 principal，只靠舊密碼確認
+    let other_user = match principal.as_deref() {
+        Some(principal) if principal.user_id != id => {
+            if principal.role != Role::Admin {
+                return Err(AppError::Forbidden("只能變更自己的密碼".to_string()));
             }
+            true
         }
+        _ => false,
     };
+    store.get_by_id(id).await?.ok_or_else(|| user_not_found(id))?;
+
+    let body = body.into_inner();
+    // admin 替別人設定密碼時不會知道對方的舊密碼，不檢查
+    if !other_user {
+        let matches = match store.get_password_hash(id).await? {
+            Some(hash) => password::verify(body.old_password, hash).await?,
+            None => false,
+        };
+        if !matches {
+            return Err(AppError::invalid("old_password", "舊密碼錯誤"));
+        }
+    }
+
+    let hash = password::hash(body.new_password).await?;
+    if !store.set_password_hash(id, &hash).await? {
+        return Err(user_not_found(id));
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功變更密碼")))
 }
 
-pub async fn get_user(pool: web::Data<DbPool>) -> HttpResponse {
-    let mut conn = get_conn!(&pool, User);
+// 不論 email 是否存在都回傳相同結果，避免被用來查詢帳號
+pub async fn request_password_reset(
+    store: web::Data<dyn UserStore>,
+    mailer: web::Data<dyn Mailer>,
+    reset: web::Data<PasswordResetConfig>,
+    mail: web::Data<MailConfig>,
+    body: web::Json<PasswordResetRequest>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    if let Some(user) = store.get_by_email(&body.email).await? {
+        let (token, digest) = password::new_secret_token();
+        store.create_password_reset(user.id, &digest, reset.ttl_secs).await?;
 
-    match UserRepository::get_all(&mut conn) {
-        Ok(user) => HttpResponse::Ok().json(ApiResponse::success(user, "成功獲取所有使用者")),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<Vec<User>>::error(&format!("獲取使用者失敗: {}", e))
-        ),
+        let link = format!("{}?token={}", reset.url, token);
+        if let Err(e) = mailer.send(password_reset_mail(&mail.from, &user.email, &link, reset.ttl_secs)).await {
+            tracing::error!(user_id = user.id, error = %e, "重設密碼信寄送失敗");
+        }
     }
+    Ok(HttpResponse::Accepted().json(ApiResponse::success(true, "若該電子郵件已註冊，將會收到重設密碼信")))
+}
+
+pub async fn confirm_password_reset(
+    store: web::Data<dyn UserStore>,
+    body: web::Json<PasswordResetConfirm>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let body = body.into_inner();
+    let hash = password::hash(body.new_password).await?;
+    store.reset_password(&password::token_digest(&body.token), &hash).await?
+        .ok_or_else(|| AppError::BadRequest("重設連結無效或已過期".to_string()))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功重設密碼")))
+}
+
+pub async fn get_user(
+    store: web::Data<dyn UserStore>,
+    req: HttpRequest,
+    query: web::Query<PageQuery>,
+) -> AppResult<HttpResponse> {
+    let page = PageRequest::from_query(&query)
+        .map_err(|e| AppError::BadRequest(format!("無效的分頁參數: {}", e)))?;
+
+    let slice = store.list(&page).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        Page::build(slice, &page, req.path(), req.query_string()),
+        "成功獲取所有使用者",
+    )))
+}
+
+// 日期格式要在 handler 內取得，串流 body 被讀取時已離開 DateFormatScope
+fn current_date_format() -> DateFormat {
+    DATE_FORMAT.try_with(|format| *format).unwrap_or_default()
+}
+
+pub async fn export_users(
+    store: web::Data<dyn UserStore>,
+    query: web::Query<ExportQuery>,
+) -> AppResult<HttpResponse> {
+    let rows = store.export().await?;
+    export::respond(rows, query.format, current_date_format()).await
 }
 
 pub async fn get_user_by_id(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn UserStore>,
     path: web::Path<u32>,
-) -> HttpResponse {
+) -> AppResult<HttpResponse> {
     let id = path.into_inner();
-    let mut conn = get_conn!(&pool, User);
-
-    match UserRepository::get_by_id(&mut conn, id) {
-        Ok(Some(user)) => HttpResponse::Ok().json(ApiResponse::success(user, "成功獲取使用者")),
-        Ok(None) => HttpResponse::NotFound().json(
-            ApiResponse::<User>::error(&format!("找不到 ID 為 {} 的使用者", id))
-        ),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<User>::error(&format!("獲取使用者失敗: {}", e))
-        ),
-    }
+    let user = store.get_by_id(id).await?.ok_or_else(|| user_not_found(id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(user, "成功獲取使用者")))
 }
 
 pub async fn create_user(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn UserStore>,
     user: web::Json<CreateUser>,
-) -> HttpResponse {
-    let mut conn = get_conn!(&pool, User);
-
-    match UserRepository::create(&mut conn, &user.into_inner()) {
-        Ok(new_user) => HttpResponse::Created().json(ApiResponse::success(new_user, "成功創建使用者")),
-        Err(e) => {
-            let error_msg = e.to_string();
-            if error_msg.contains("Duplicate entry") {
-                HttpResponse::BadRequest().json(
-                    ApiResponse::<User>::error("電子郵件已存在")
-                )
-            } else {
-                HttpResponse::InternalServerError().json(
-                    ApiResponse::<User>::error(&format!("創建使用者失敗: {}", error_msg))
-                )
-            }
-        }
-    }
+) -> AppResult<HttpResponse> {
+    user.validate()?;
+    let password_hash = password::hash(user.password.clone()).await?;
+    let new_user = store.create(&user.into_inner(), &password_hash).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(new_user, "成功創建使用者")))
 }
 
 pub async fn update_user(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn UserStore>,
     path: web::Path<u32>,
     user: web::Json<UpdateUser>,
-) -> HttpResponse {
+) -> AppResult<HttpResponse> {
+    user.validate()?;
     let id = path.into_inner();
-    let mut conn = get_conn!(&pool, User);
-
-    match UserRepository::update(&mut conn, id, &user.into_inner()) {
-        Ok(Some(updated_user)) => HttpResponse::Ok().json(ApiResponse::success(updated_user, "成功更新使用者")),
-        Ok(None) => HttpResponse::NotFound().json(
-            ApiResponse::<User>::error(&format!("找不到 ID 為 {} 的使用者", id))
-        ),
-        Err(e) => {
-            let error_msg = e.to_string();
-            if error_msg.contains("Duplicate entry") {
-                HttpResponse::BadRequest().json(
-                    ApiResponse::<User>::error("電子郵件已存在")
-                )
-            } else {
-                HttpResponse::InternalServerError().json(
-                    ApiResponse::<User>::error(&format!("更新使用者失敗: {}", error_msg))
-                )
-            }
-        }
-    }
+    let updated_user = store.update(id, &user.into_inner()).await?.ok_or_else(|| user_not_found(id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated_user, "成功更新使用者")))
 }
 
 pub async fn delete_user(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn UserStore>,
     path: web::Path<u32>,
-) -> HttpResponse {
+) -> AppResult<HttpResponse> {
     let id = path.into_inner();
-    let mut conn = get_conn!(&pool, User);
-
-    match UserRepository::delete(&mut conn, id) {
-        Ok(true) => HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除使用者")),
-        Ok(false) => HttpResponse::NotFound().json(
-            ApiResponse::<bool>::error(&format!("找不到 ID 為 {} 的使用者", id))
-        ),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<bool>::error(&format!("刪除使用者失敗: {}", e))
-        ),
+    if !store.delete(id).await? {
+        return Err(user_not_found(id));
     }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除使用者")))
 }
 
-pub async fn get_disposition(pool: web::Data<DbPool>) -> HttpResponse {
-    let mut conn = get_conn!(&pool, Disposition);
+pub async fn get_disposition(
+    store: web::Data<dyn DispositionStore>,
+    req: HttpRequest,
+    query: web::Query<PageQuery>,
+    filter: web::Query<DispositionFilter>,
+) -> AppResult<HttpResponse> {
+    let mut page = PageRequest::from_query(&query)
+        .map_err(|e| AppError::BadRequest(format!("無效的分頁參數: {}", e)))?;
 
-    match DispositionRepository::get_all(&mut conn) {
-        Ok(disposition) => HttpResponse::Ok().json(ApiResponse::success(disposition, "成功獲取所有處置股")),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<Vec<Disposition>>::error(&format!("獲取處置股失敗: {}", e))
-        ),
+    // 游標以主鍵排序為準，自訂排序時改用 offset 分頁
+    if filter.sort.is_some() {
+        match page.mode {
+            PageMode::Start => page.mode = PageMode::Offset(0),
+            PageMode::Offset(_) => {}
+            PageMode::After(_) | PageMode::Before(_) => {
+                return Err(AppError::BadRequest("無效的分頁參數: 自訂排序時請使用 offset 分頁".to_string()));
+            }
+        }
     }
+
+    let slice = store.list(&filter, &page).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        Page::build(slice, &page, req.path(), req.query_string()),
+        "成功獲取所有處置股",
+    )))
+}
+
+pub async fn export_dispositions(
+    store: web::Data<dyn DispositionStore>,
+    query: web::Query<ExportQuery>,
+    filter: web::Query<DispositionFilter>,
+) -> AppResult<HttpResponse> {
+    let rows = store.export(&filter).await?;
+    export::respond(rows, query.format, current_date_format()).await
+}
+
+pub async fn get_disposition_calendar(
+    store: web::Data<dyn DispositionStore>,
+    filter: web::Query<DispositionFilter>,
+) -> AppResult<HttpResponse> {
+    let rows = store.export(&filter).await?;
+    calendar::respond(rows).await
+}
+
+// 處置股異動的 SSE；帶 Last-Event-ID 重連時補送之後的事件
+pub async fn stream_dispositions(hub: web::Data<EventHub>, req: HttpRequest) -> HttpResponse {
+    let last_event_id = req.headers().get(sse::LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    let (replay, receiver) = hub.subscribe(last_event_id);
+    sse::response(replay, receiver, current_date_format())
+}
+
+// WebSocket 訂閱；握手完成後由 ws::run 在背景處理這條連線
+pub async fn ws_connect(hub: web::Data<EventHub>, req: HttpRequest, body: web::Payload) -> AppResult<HttpResponse> {
+    let (response, session, messages) = actix_ws::handle(&req, body)
+        .map_err(|e| AppError::BadRequest(format!("無法建立 WebSocket 連線: {}", e)))?;
+    let messages = messages.max_frame_size(ws::MAX_MESSAGE_SIZE)
+        .aggregate_continuations()
+        .max_continuation_size(ws::MAX_MESSAGE_SIZE);
+    actix_web::rt::spawn(ws::run(session, messages, hub, current_date_format()));
+    Ok(response)
 }
 
 pub async fn get_disposition_by_symbol(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn DispositionStore>,
     path: web::Path<i32>,
-) -> HttpResponse {
+) -> AppResult<HttpResponse> {
     let symbol = path.into_inner();
-    let mut conn = get_conn!(&pool, Disposition);
+    let disposition = store.get_by_symbol(symbol).await?.ok_or_else(|| disposition_not_found(symbol))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(disposition, "成功獲取處置股")))
+}
 
-    match DispositionRepository::get_by_symbol(&mut conn, symbol) {
-        Ok(Some(disposition)) => HttpResponse::Ok().json(ApiResponse::success(disposition, "成功獲取處置股")),
-        Ok(None) => HttpResponse::NotFound().json(
-            ApiResponse::<Disposition>::error(&format!("找不到 ID 為 {} 的處置股", symbol))
-        ),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<Disposition>::error(&format!("獲取處置股失敗: {}", e))
-        ),
-    }
+pub async fn get_active_disposition(
+    store: web::Data<dyn DispositionStore>,
+    query: web::Query<AsOfQuery>,
+) -> AppResult<HttpResponse> {
+    let as_of = query.as_of.map(|d| d.0).unwrap_or_else(taipei_today);
+
+    let disposition = store.get_active(as_of).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        disposition,
+        &format!("成功獲取 {} 處置中的股票", TwDate(as_of)),
+    )))
+}
+
+pub async fn get_disposition_active_status(
+    store: web::Data<dyn DispositionStore>,
+    path: web::Path<i32>,
+    query: web::Query<AsOfQuery>,
+) -> AppResult<HttpResponse> {
+    let symbol = path.into_inner();
+    let as_of = query.as_of.map(|d| d.0).unwrap_or_else(taipei_today);
+
+    let disposition = store.get_active_by_symbol(symbol, as_of).await?;
+    let status = ActiveStatus {
+        symbol,
+        as_of: TwDate(as_of),
+        active: disposition.is_some(),
+        until: disposition.as_ref().and_then(|d| d.end),
+        disposition,
+    };
+    let message = if status.active { "該股票處置中" } else { "該股票未在處置中" };
+    Ok(HttpResponse::Ok().json(ApiResponse::success(status, message)))
 }
 
 pub async fn create_disposition(
-    pool: web::Data<DbPool>,
-    disposition: web::Json<CreateDisposition>,
-) -> HttpResponse {
-    let stock_date = disposition.stock_date.clone();
-    let name = disposition.name.clone();
-    let mut conn = get_conn!(&pool, Disposition);
-
-    match DispositionRepository::create(&mut conn, &disposition.into_inner()) {
-        Ok(new_disposition) => HttpResponse::Created().json(ApiResponse::success(new_disposition, "成功創建處置股")),
-        Err(e) => {
-            let error_msg = e.to_string();
-            if error_msg.contains("Duplicate entry") {
-                HttpResponse::BadRequest().json(
-                    ApiResponse::<Disposition>::error(&format!("{} {} 已存在", stock_date, name)) 
-                )
-            } else {
-                HttpResponse::InternalServerError().json(
-                    ApiResponse::<Disposition>::error(&format!("創建處置股失敗: {}", error_msg))
-                )
-            }
-        }
-    }
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
+    disposition: web::Json<CreateDispositionRequest>,
+) -> AppResult<HttpResponse> {
+    let disposition = disposition.into_inner().into_disposition()?;
+    let new_disposition = store.create(&disposition).await?;
+    hub.changed();
+    Ok(HttpResponse::Created().json(ApiResponse::success(new_disposition, "成功創建處置股")))
+}
+
+// JSON 陣列或 CSV，整批在一個 transaction 內寫入，回傳逐列結果
+pub async fn import_dispositions(
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
+    req: HttpRequest,
+    query: web::Query<BulkImportQuery>,
+    body: web::Bytes,
+) -> AppResult<HttpResponse> {
+    let rows = import::parse_body(req.content_type(), &body)?;
+    let report = import::run(store.get_ref(), query.on_conflict, rows).await?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report, "批次匯入完成")))
+}
+
+// 上傳證交所或櫃買中心公告原始檔（JSON/CSV），轉成處置股後同批次匯入處理
+pub async fn import_announcement(
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
+    path: web::Path<String>,
+    query: web::Query<BulkImportQuery>,
+    body: web::Bytes,
+) -> AppResult<HttpResponse> {
+    let source = path.into_inner();
+    let exchange = Exchange::parse(&source)
+        .ok_or_else(|| AppError::NotFound(format!("不支援的交易所 '{}'（可用: twse, tpex）", source)))?;
+    let rows = exchange::parse_announcement(exchange, &body)?;
+    let report = import::run(store.get_ref(), query.on_conflict, rows).await?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report, "公告匯入完成")))
 }
 
 pub async fn update_disposition(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
     path: web::Path<i32>,
-    disposition: web::Json<UpdateDisposition>,
-) -> HttpResponse {
+    disposition: web::Json<UpdateDispositionRequest>,
+) -> AppResult<HttpResponse> {
+    let disposition = disposition.into_inner().into_update()?;
     let symbol = path.into_inner();
-    let mut conn = get_conn!(&pool, Disposition);
-
-    match DispositionRepository::update(&mut conn, symbol, &disposition.into_inner()) {
-        Ok(Some(updated_disposition)) => HttpResponse::Ok().json(ApiResponse::success(updated_disposition, "成功更新處置股")),
-        Ok(None) => HttpResponse::NotFound().json(
-            ApiResponse::<Disposition>::error(&format!("找不到 Symbol 為 {} 的處置股", symbol))
-        ),
-        Err(e) => {
-            let error_msg = e.to_string();
-            if error_msg.contains("Duplicate entry") {
-                HttpResponse::BadRequest().json(
-                    ApiResponse::<Disposition>::error("電子郵件已存在")
-                )
-            } else {
-                HttpResponse::InternalServerError().json(
-                    ApiResponse::<Disposition>::error(&format!("更新處置股失敗: {}", error_msg))
-                )
-            }
-        }
-    }
+    let updated_disposition = store.update(symbol, &disposition).await?
+        .ok_or_else(|| disposition_not_found(symbol))?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated_disposition, "成功更新處置股")))
 }
 
 pub async fn delete_disposition(
-    pool: web::Data<DbPool>,
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
     path: web::Path<i32>,
-) -> HttpResponse {
+) -> AppResult<HttpResponse> {
     let symbol = path.into_inner();
-    let mut conn = get_conn!(&pool, Disposition);
-
-    match DispositionRepository::delete(&mut conn, symbol) {
-        Ok(true) => HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除處置股")),
-        Ok(false) => HttpResponse::NotFound().json(
-            ApiResponse::<bool>::error(&format!("找不到 Symbol 為 {} 的處置股", symbol))
-        ),
-        Err(e) => HttpResponse::InternalServerError().json(
-            ApiResponse::<bool>::error(&format!("刪除處置股失敗: {}", e))
-        ),
-    }
-}
\ No newline at end of file
+    store.delete(symbol).await?.ok_or_else(|| disposition_not_found(symbol))?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除處置股")))
+}
+
+pub async fn get_disposition_history(
+    store: web::Data<dyn DispositionStore>,
+    path: web::Path<i32>,
+) -> AppResult<HttpResponse> {
+    let symbol = path.into_inner();
+    let history = store.get_history(symbol).await?;
+    if history.is_empty() {
+        return Err(disposition_not_found(symbol));
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(history, "成功獲取處置歷史")))
+}
+
+pub async fn get_disposition_period(
+    store: web::Data<dyn DispositionStore>,
+    path: web::Path<(i32, u64)>,
+) -> AppResult<HttpResponse> {
+    let (symbol, id) = path.into_inner();
+    let disposition = store.get_by_id(symbol, id).await?.ok_or_else(|| period_not_found(symbol, id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(disposition, "成功獲取處置股")))
+}
+
+pub async fn update_disposition_period(
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
+    path: web::Path<(i32, u64)>,
+    disposition: web::Json<UpdateDispositionRequest>,
+) -> AppResult<HttpResponse> {
+    let disposition = disposition.into_inner().into_update()?;
+    let (symbol, id) = path.into_inner();
+    let updated_disposition = store.update_by_id(symbol, id, &disposition).await?
+        .ok_or_else(|| period_not_found(symbol, id))?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated_disposition, "成功更新處置股")))
+}
+
+pub async fn delete_disposition_period(
+    store: web::Data<dyn DispositionStore>,
+    hub: web::Data<EventHub>,
+    path: web::Path<(i32, u64)>,
+) -> AppResult<HttpResponse> {
+    let (symbol, id) = path.into_inner();
+    store.delete_by_id(symbol, id).await?.ok_or_else(|| period_not_found(symbol, id))?;
+    hub.changed();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除處置股")))
+}
+
+fn current_user(principal: Option<web::ReqData<Principal>>) -> AppResult<Principal> {
+    principal.map(web::ReqData::into_inner)
+        .ok_or_else(|| AppError::Unauthorized("需要登入".to_string()))
+}
+
+fn api_key_not_found(id: u64) -> AppError {
+    AppError::NotFound(format!("找不到 ID 為 {} 的 API key", id))
+}
+
+pub async fn create_api_key(
+    store: web::Data<dyn ApiKeyStore>,
+    principal: Option<web::ReqData<Principal>>,
+    body: web::Json<CreateApiKey>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let user = current_user(principal)?;
+    let (key, prefix, digest) = api_key::generate();
+    let api_key = store.create(user.user_id, &body, &prefix, &digest).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(
+        CreatedApiKey { key, api_key },
+        "成功建立 API key，請妥善保存，之後無法再次取得",
+    )))
+}
+
+pub async fn get_api_keys(
+    store: web::Data<dyn ApiKeyStore>,
+    principal: Option<web::ReqData<Principal>>,
+) -> AppResult<HttpResponse> {
+    let user = current_user(principal)?;
+    let keys = store.list(user.user_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(keys, "成功獲取 API key")))
+}
+
+pub async fn revoke_api_key(
+    store: web::Data<dyn ApiKeyStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<u64>,
+) -> AppResult<HttpResponse> {
+    let user = current_user(principal)?;
+    let id = path.into_inner();
+    let key = store.revoke(user.user_id, id).await?.ok_or_else(|| api_key_not_found(id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(key, "成功撤銷 API key")))
+}
+
+pub async fn get_api_key_uses(
+    store: web::Data<dyn ApiKeyStore>,
+    principal: Option<web::ReqData<Principal>>,
+    path: web::Path<u64>,
+) -> AppResult<HttpResponse> {
+    let user = current_user(principal)?;
+    let id = path.into_inner();
+    store.get_by_id(user.user_id, id).await?.ok_or_else(|| api_key_not_found(id))?;
+    let uses = store.list_uses(id, API_KEY_USE_LIMIT).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(uses, "成功獲取 API key 使用紀錄")))
+}
+
+// 使用紀錄只回傳最近幾筆
+const API_KEY_USE_LIMIT: u32 = 100;
+
+fn webhook_not_found(id: u64) -> AppError {
+    AppError::NotFound(format!("找不到 ID 為 {} 的 webhook", id))
+}
+
+pub async fn get_webhooks(store: web::Data<dyn WebhookStore>) -> AppResult<HttpResponse> {
+    let webhooks = store.list().await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(webhooks, "成功獲取 webhook")))
+}
+
+pub async fn get_webhook(
+    store: web::Data<dyn WebhookStore>,
+    path: web::Path<u64>,
+) -> AppResult<HttpResponse> {
+    let id = path.into_inner();
+    let webhook = store.get_by_id(id).await?.ok_or_else(|| webhook_not_found(id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(webhook, "成功獲取 webhook")))
+}
+
+pub async fn create_webhook(
+    store: web::Data<dyn WebhookStore>,
+    body: web::Json<CreateWebhook>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let secret = webhook::generate_secret();
+    let created = store.create(&body, &secret).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(
+        CreatedWebhook { secret, webhook: created },
+        "成功建立 webhook，請妥善保存 secret，之後無法再次取得",
+    )))
+}
+
+pub async fn update_webhook(
+    store: web::Data<dyn WebhookStore>,
+    path: web::Path<u64>,
+    body: web::Json<UpdateWebhook>,
+) -> AppResult<HttpResponse> {
+    body.validate()?;
+    let id = path.into_inner();
+    let updated = store.update(id, &body).await?.ok_or_else(|| webhook_not_found(id))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated, "成功更新 webhook")))
+}
+
+pub async fn delete_webhook(
+    store: web::Data<dyn WebhookStore>,
+    path: web::Path<u64>,
+) -> AppResult<HttpResponse> {
+    let id = path.into_inner();
+    if !store.delete(id).await? {
+        return Err(webhook_not_found(id));
+    }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(true, "成功刪除 webhook")))
+}
+
+pub async fn get_webhook_deliveries(
+    store: web::Data<dyn WebhookStore>,
+    path: web::Path<u64>,
+) -> AppResult<HttpResponse> {
+    let id = path.into_inner();
+    store.get_by_id(id).await?.ok_or_else(|| webhook_not_found(id))?;
+    let deliveries = store.list_deliveries(id, WEBHOOK_DELIVERY_LIMIT).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(deliveries, "成功獲取 webhook 投遞紀錄")))
+}
+
+// 投遞紀錄只回傳最近幾筆
+const WEBHOOK_DELIVERY_LIMIT: u32 = 100;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AuthConfig;
+    use crate::mail::OutboxMailer;
+    use crate::memory::{InMemoryDispositionStore, InMemoryUserStore};
+    use crate::models::CreateDisposition;
+    use actix_web::body::MessageBody;
+    use actix_web::dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse};
+    use actix_web::{test, App};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    // 以實際的路由表建立關閉登入驗證的 app，處置股使用記憶體儲存
+    fn disposition_app(store: Arc<dyn DispositionStore>) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+        let auth = AuthConfig { enabled: false, jwt_secret: Some("x".repeat(32)), ..AuthConfig::default() };
+        App::new()
+            .app_data(web::Data::from(store))
+            .app_data(web::Data::new(EventHub::new()))
+            .app_data(web::Data::new(TokenService::new(&auth)))
+            .configure(crate::routes::configure)
+    }
+
+    // (stock_date, market, symbol, name, start, end)，期間空字串代表未定
+    async fn seeded(rows: &[(&str, &str, &str, &str, &str, &str)]) -> Arc<dyn DispositionStore> {
+        let store: Arc<dyn DispositionStore> = Arc::new(InMemoryDispositionStore::new());
+        for (stock_date, market, symbol, name, start, end) in rows {
+            store.create(&CreateDisposition {
+                stock_date: TwDate::parse(stock_date).unwrap(),
+                market: market.to_string(),
+                symbol: symbol.to_string(),
+                name: name.to_string(),
+                start: TwDate::parse(start),
+                end: TwDate::parse(end),
+            }).await.unwrap();
+        }
+        store
+    }
+
+    async fn call<S, R, B>(app: &S, req: R) -> (u16, Value)
+    where
+        S: Service<R, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        let res = test::call_service(app, req).await;
+        let status = res.status().as_u16();
+        let body = test::read_body(res).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn symbols(body: &Value) -> Vec<i64> {
+        let items = body["data"]["items"].as_array().or_else(|| body["data"].as_array()).expect("items");
+        items.iter().map(|d| d["symbol"].as_i64().unwrap()).collect()
+    }
+
+    // 名稱比對字面（% 與 _ 不是萬用字元）、期間篩選為區間重疊（含邊界），排序欄位只接受白名單，排序時不能用游標
+    #[actix_web::test]
+    async fn filters_and_sorts_dispositions() {
+        let store = seeded(&[
+            ("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            ("2025-10-18", "TWSE", "2317", "鴻海50%", "2025-11-03", "2025-11-14"),
+            ("2025-10-19", "TWSE", "2603", "長榮_A", "2025-10-01", "2025-10-14"),
+            ("2025-10-17", "TPEx", "6488", "環球晶", "", ""),
+        ]).await;
+        let app = test::init_service(disposition_app(store)).await;
+        let list = |query: &str| {
+            let req = test::TestRequest::get().uri(&format!("/disposition?{}", query)).to_request();
+            let app = &app;
+            async move { call(app, req).await }
+        };
+
+        assert_eq!(symbols(&list("name=50%25").await.1), [2317]);
+        assert_eq!(symbols(&list("name=_").await.1), [2603]);
+        assert_eq!(symbols(&list("market=tpex").await.1), [6488]);
+        assert_eq!(symbols(&list("period_from=2025-10-15&period_to=2025-11-05").await.1), [2330, 2317]);
+        assert_eq!(symbols(&list("period_from=2025-10-31&period_to=2025-11-02").await.1), [2330]);
+        assert_eq!(symbols(&list("period_to=2025-10-20").await.1), [2330, 2603]);
+        assert!(symbols(&list("period_from=2025-11-15").await.1).is_empty());
+
+        // 未定的期間（NULL）在遞減排序時排最後，與 MySQL 相同
+        let (status, body) = list("sort=end:desc&limit=3").await;
+        assert_eq!((status, symbols(&body)), (200, vec![2317, 2330, 2603]));
+        assert_eq!(body["data"]["offset"], 0);
+        let next = body["data"]["next"].as_str().unwrap().split_once('?').unwrap().1.to_string();
+        assert!(next.contains("sort=end%3Adesc") && next.contains("offset=3"), "{}", next);
+        assert_eq!(symbols(&list(&next).await.1), [6488]);
+
+        assert_eq!(list("sort=password").await.0, 400);
+        assert_eq!(list("sort=end:sideways").await.0, 400);
+        let cursor = list("limit=1").await.1["data"]["next"].as_str().unwrap().split_once("cursor=").unwrap().1.to_string();
+        let (status, body) = list(&format!("sort=name&cursor={}", cursor)).await;
+        assert_eq!(status, 400);
+        assert!(body["message"].as_str().unwrap().contains("offset"), "{}", body);
+    }
+
+    fn ids(body: &Value) -> Vec<u64> {
+        body["data"].as_array().expect("items").iter().map(|d| d["id"].as_u64().unwrap()).collect()
+    }
+
+    // 同一檔股票的多段期間：歷史依公告日遞減，/{symbol}/{id} 只處理屬於該股票的那一段
+    #[actix_web::test]
+    async fn manages_periods_by_id() {
+        let store = seeded(&[
+            ("2025-09-01", "TWSE", "2330", "台積電", "2025-09-02", "2025-09-13"),
+            ("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            ("2025-10-17", "TWSE", "2317", "鴻海", "2025-10-20", "2025-10-31"),
+        ]).await;
+        let app = test::init_service(disposition_app(store)).await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        let put = |uri: &str, body: Value| test::TestRequest::put().uri(uri).set_json(body).to_request();
+        let delete = |uri: &str| test::TestRequest::delete().uri(uri).to_request();
+
+        let (status, body) = call(&app, get("/disposition/2330/history")).await;
+        assert_eq!((status, ids(&body)), (200, vec![2, 1]));
+        assert_eq!(call(&app, get("/disposition/9999/history")).await.0, 404);
+
+        let (status, body) = call(&app, get("/disposition/2330/1")).await;
+        assert_eq!((status, body["data"]["start"].as_str()), (200, Some("2025-09-02")));
+        // id 3 屬於 2317，換成 2330 的路徑一律當作找不到
+        assert_eq!(call(&app, get("/disposition/2330/3")).await.0, 404);
+        assert_eq!(call(&app, put("/disposition/2330/3", serde_json::json!({ "end": "2025-11-07" }))).await.0, 404);
+        assert_eq!(call(&app, delete("/disposition/2330/3")).await.0, 404);
+        assert_eq!(call(&app, get("/disposition/2317/3")).await.0, 200);
+
+        // 更新較早的那一段，最新一段不受影響
+        let (status, body) = call(&app, put("/disposition/2330/1", serde_json::json!({ "end": "2025-09-16" }))).await;
+        assert_eq!((status, body["data"]["end"].as_str()), (200, Some("2025-09-16")));
+        let (_, body) = call(&app, get("/disposition/2330")).await;
+        assert_eq!((body["data"]["id"].as_u64(), body["data"]["end"].as_str()), (Some(2), Some("2025-10-31")));
+
+        assert_eq!(call(&app, delete("/disposition/2330/1")).await.0, 200);
+        assert_eq!(call(&app, get("/disposition/2330/1")).await.0, 404);
+        assert_eq!(ids(&call(&app, get("/disposition/2330/history")).await.1), [2]);
+    }
+
+    // 只送一端時要和既有的另一端合併檢查，不能寫入起日晚於迄日的期間
+    #[actix_web::test]
+    async fn one_sided_update_keeps_start_before_end() {
+        let store = seeded(&[("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31")]).await;
+        let app = test::init_service(disposition_app(store)).await;
+        let put = |uri: &str, body: Value| test::TestRequest::put().uri(uri).set_json(body).to_request();
+
+        for uri in ["/disposition/2330", "/disposition/2330/1"] {
+            let (status, body) = call(&app, put(uri, serde_json::json!({ "start": "2025-11-03" }))).await;
+            assert_eq!(status, 422, "{}: {}", uri, body);
+            let (status, _) = call(&app, put(uri, serde_json::json!({ "end": "2025-10-19" }))).await;
+            assert_eq!(status, 422, "{}", uri);
+        }
+        let (status, body) = call(&app, test::TestRequest::get().uri("/disposition/2330/1").to_request()).await;
+        assert_eq!((status, body["data"]["start"].as_str(), body["data"]["end"].as_str()), (200, Some("2025-10-20"), Some("2025-10-31")));
+
+        let (status, body) = call(&app, put("/disposition/2330", serde_json::json!({ "start": "2025-10-31" }))).await;
+        assert_eq!((status, body["data"]["start"].as_str()), (200, Some("2025-10-31")));
+    }
+
+    // 處置期間含頭尾兩天；未帶 as_of 時以台北時間的今天為準
+    #[actix_web::test]
+    async fn active_as_of_includes_both_ends() {
+        let today = crate::clock::taipei_today();
+        let (yesterday, tomorrow) = (TwDate(today.pred_opt().unwrap()).to_string(), TwDate(today.succ_opt().unwrap()).to_string());
+        let today = TwDate(today).to_string();
+        let store = seeded(&[
+            ("2025-10-17", "TWSE", "2330", "台積電", "2025-10-20", "2025-10-31"),
+            ("2025-10-17", "TWSE", "2317", "鴻海", &yesterday, &today),
+            ("2025-10-17", "TWSE", "2603", "長榮", &tomorrow, &tomorrow),
+        ]).await;
+        let app = test::init_service(disposition_app(store)).await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        assert_eq!(symbols(&call(&app, get("/disposition/active?as_of=2025-10-20")).await.1), [2330]);
+        assert_eq!(symbols(&call(&app, get("/disposition/active?as_of=2025-10-31")).await.1), [2330]);
+        assert!(symbols(&call(&app, get("/disposition/active?as_of=2025-11-01")).await.1).is_empty());
+        assert!(symbols(&call(&app, get("/disposition/active?as_of=2025-10-19")).await.1).is_empty());
+        assert_eq!(symbols(&call(&app, get("/disposition/active")).await.1), [2317]);
+
+        let (status, body) = call(&app, get("/disposition/2330/active?as_of=2025-10-31")).await;
+        assert_eq!(status, 200);
+        assert_eq!((body["data"]["active"].as_bool(), body["data"]["until"].as_str()), (Some(true), Some("2025-10-31")));
+        assert_eq!(body["data"]["as_of"], "2025-10-31");
+        let (_, body) = call(&app, get("/disposition/2330/active?as_of=2025-11-01")).await;
+        assert_eq!((body["data"]["active"].as_bool(), &body["data"]["until"], &body["data"]["disposition"]), (Some(false), &Value::Null, &Value::Null));
+
+        let (_, body) = call(&app, get("/disposition/2317/active")).await;
+        assert_eq!(body["data"]["as_of"].as_str(), Some(today.as_str()));
+        assert_eq!((body["data"]["active"].as_bool(), body["data"]["until"].as_str()), (Some(true), Some(today.as_str())));
+        let (_, body) = call(&app, get("/disposition/2603/active")).await;
+        assert_eq!(body["data"]["active"].as_bool(), Some(false));
+    }
+
+    // 驗證失敗回 422，errors 逐欄位列出原因
+    #[actix_web::test]
+    async fn validation_errors_list_fields() {
+        let users: Arc<dyn UserStore> = Arc::new(InMemoryUserStore::new());
+        let app = test::init_service(disposition_app(seeded(&[]).await).app_data(web::Data::from(users)).route("/users", web::post().to(create_user))).await;
+        let post = |uri: &str, body: Value| test::TestRequest::post().uri(uri).set_json(body).to_request();
+        let error_fields = |body: &Value| body["errors"].as_array().expect("errors").iter().map(|e| e["field"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        let (status, body) = call(&app, post("/users", serde_json::json!({ "name": "", "email": "amy", "password": "short" }))).await;
+        assert_eq!((status, body["code"].as_str()), (422, Some("VALIDATION_FAILED")));
+        assert_eq!(error_fields(&body), ["email", "name", "password"]);
+
+        let disposition = serde_json::json!({ "stock_date": "2025-10-17", "market": "NYSE", "symbol": "TSMC", "name": "台積電", "start": "2025-10-31", "end": "2025-10-20" });
+        let (status, body) = call(&app, post("/disposition", disposition.clone())).await;
+        assert_eq!(status, 422);
+        assert_eq!(error_fields(&body), ["market", "symbol"]);
+        assert!(body["errors"].as_array().unwrap().iter().all(|e| !e["message"].as_str().unwrap().is_empty()), "{}", body);
+
+        // 欄位都正確後才檢查起訖日的先後
+        let mut disposition = disposition;
+        disposition["market"] = "TWSE".into();
+        disposition["symbol"] = "2330".into();
+        let (status, body) = call(&app, post("/disposition", disposition)).await;
+        assert_eq!((status, error_fields(&body)), (422, vec!["__all__".to_string()]));
+    }
+
+    // refresh token 換發前會確認使用者還在，刪除後即使 token 未過期也不能再換
+    #[actix_web::test]
+    async fn refresh_fails_after_user_is_deleted() {
+        let store: Arc<dyn UserStore> = Arc::new(InMemoryUserStore::new());
+        let user = CreateUser { name: "Amy".to_string(), email: "amy@example.com".to_string(), password: "password".to_string(), role: None };
+        let user = store.create(&user, "unused").await.unwrap();
+        let tokens = TokenService::new(&AuthConfig { jwt_secret: Some("x".repeat(32)), ..AuthConfig::default() });
+        let pair = tokens.issue(user.clone()).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store.clone()))
+                .app_data(web::Data::new(tokens))
+                .route("/auth/refresh", web::post().to(refresh_token)),
+        ).await;
+        let refresh = |token: &str| test::TestRequest::post().uri("/auth/refresh").set_json(serde_json::json!({ "refresh_token": token })).to_request();
+
+        let (status, body) = call(&app, refresh(&pair.refresh_token)).await;
+        assert_eq!((status, body["data"]["user"]["id"].as_u64()), (200, Some(user.id as u64)));
+        assert_eq!(call(&app, refresh(&pair.access_token)).await.0, 401);
+
+        assert!(store.delete(user.id).await.unwrap());
+        let (status, body) = call(&app, refresh(&pair.refresh_token)).await;
+        assert_eq!((status, body["code"].as_str()), (401, Some("UNAUTHORIZED")));
+    }
+
+    // 重設密碼完整流程：申請 → 從替身信箱取出 token → 設定新密碼 → 用新密碼登入
+    #[actix_web::test]
+    async fn password_reset_flow_with_outbox_mailer() {
+        let store: Arc<dyn UserStore> = Arc::new(InMemoryUserStore::new());
+        let user = CreateUser {
+            name: "Amy".to_string(),
+            email: "amy@example.com".to_string(),
+            password: "old-password".to_string(),
+            role: None,
+        };
+        store.create(&user, &password::hash(user.password.clone()).await.unwrap()).await.unwrap();
+
+        let outbox = Arc::new(OutboxMailer::default());
+        let mailer: Arc<dyn Mailer> = outbox.clone();
+        let auth = AuthConfig { jwt_secret: Some("x".repeat(32)), ..AuthConfig::default() };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(store))
+                .app_data(web::Data::from(mailer))
+                .app_data(web::Data::new(TokenService::new(&auth)))
+                .app_data(web::Data::new(PasswordResetConfig::default()))
+                .app_data(web::Data::new(MailConfig::default()))
+                .route("/auth/login", web::post().to(login))
+                .route("/auth/password-reset", web::post().to(request_password_reset))
+                .route("/auth/password-reset/confirm", web::post().to(confirm_password_reset)),
+        ).await;
+
+        let post = |uri: &str, body: serde_json::Value| test::TestRequest::post().uri(uri).set_json(body).to_request();
+
+        // 不存在的帳號同樣回 202，但不寄信
+        let res = test::call_service(&app, post("/auth/password-reset", serde_json::json!({ "email": "nobody@example.com" }))).await;
+        assert_eq!(res.status(), 202);
+        assert!(outbox.sent.lock().unwrap().is_empty());
+
+        let res = test::call_service(&app, post("/auth/password-reset", serde_json::json!({ "email": "amy@example.com" }))).await;
+        assert_eq!(res.status(), 202);
+        let mail = outbox.sent.lock().unwrap().pop().expect("reset mail");
+        assert_eq!(mail.to, "amy@example.com");
+        let token = mail.body.split("?token=").nth(1).expect("token in link").trim().to_string();
+
+        let confirm = serde_json::json!({ "token": token, "new_password": "new-password" });
+        let res = test::call_service(&app, post("/auth/password-reset/confirm", confirm.clone())).await;
+        assert_eq!(res.status(), 200);
+
+        // token 只能使用一次
+        let res = test::call_service(&app, post("/auth/password-reset/confirm", confirm)).await;
+        assert_eq!(res.status(), 400);
+
+        let res = test::call_service(&app, post("/auth/login", serde_json::json!({ "email": "amy@example.com", "password": "old-password" }))).await;
+        assert_eq!(res.status(), 401);
+        let res = test::call_service(&app, post("/auth/login", serde_json::json!({ "email": "amy@example.com", "password": "new-password" }))).await;
+        assert_eq!(res.status(), 200);
+    }
+}
@@ -0,0 +1,284 @@
+use serde_json::Value as JsonValue;
+use validator::Validate;
+use crate::error::{field_errors, AppError, AppResult};
+use crate::models::{BulkImportReport, BulkRowResult, BulkRowStatus, ConflictMode, CreateDisposition, CreateDispositionRequest, FieldError, ImportOutcome};
+use crate::store::DispositionStore;
+
+// 單次匯入的列數上限，避免一個 transaction 鎖太久
+pub const MAX_BULK_ROWS: usize = 5000;
+// 匯入 body 的大小上限：預設的 256 KiB 放不下 5000 列，公告原始檔每列的欄位也比較多
+pub const MAX_BULK_BYTES: usize = 8 * 1024 * 1024;
+
+const MERGED_PERIOD_MESSAGE: &str = "與既有的處置期間合併後，處置起日晚於迄日";
+
+// 解析並驗證後的一列；失敗時帶著該列的錯誤，不影響其他列
+pub type ParsedRow = Result<CreateDisposition, Vec<FieldError>>;
+
+// 依 Content-Type 決定格式：text/csv 或 JSON 陣列（未指定時當作 JSON）
+pub fn parse_body(content_type: &str, body: &[u8]) -> AppResult<Vec<ParsedRow>> {
+    let rows = match content_type {
+        "text/csv" => parse_csv(body)?,
+        "" | "application/json" => parse_json(body)?,
+        other => return Err(AppError::BadRequest(format!("不支援的 Content-Type: {}，需為 application/json 或 text/csv", other))),
+    };
+    check_row_count(&rows)?;
+    Ok(rows)
+}
+
+fn check_row_count(rows: &[ParsedRow]) -> AppResult<()> {
+    if rows.is_empty() {
+        return Err(AppError::BadRequest("沒有可匯入的資料".to_string()));
+    }
+    check_row_limit(rows)
+}
+
+pub fn check_row_limit(rows: &[ParsedRow]) -> AppResult<()> {
+    if rows.len() > MAX_BULK_ROWS {
+        return Err(AppError::BadRequest(format!("單次最多匯入 {} 筆", MAX_BULK_ROWS)));
+    }
+    Ok(())
+}
+
+// 外層必須是陣列；個別元素格式錯誤只讓該列無效
+pub fn parse_json(body: &[u8]) -> AppResult<Vec<ParsedRow>> {
+    let items: Vec<JsonValue> = serde_json::from_slice(body)
+        .map_err(|e| AppError::BadRequest(format!("無效的 JSON，需為處置股陣列: {}", e)))?;
+    Ok(items.into_iter()
+        .map(|item| {
+            serde_json::from_value::<CreateDispositionRequest>(item)
+                .map_err(|e| row_error(format!("無效的資料: {}", e)))
+                .and_then(validated_request)
+        })
+        .collect())
+}
+
+// 第一列為標題：stock_date,market,symbol,name，可另加 start,end；欄位順序不限
+pub fn parse_csv(body: &[u8]) -> AppResult<Vec<ParsedRow>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+    let headers = reader.headers()
+        .map_err(|e| AppError::BadRequest(format!("無效的 CSV 標題列: {}", e)))?
+        .clone();
+    Ok(reader.records()
+        .map(|record| {
+            record
+                .and_then(|record| record.deserialize::<CreateDispositionRequest>(Some(&headers)))
+                .map_err(|e| row_error(format!("無效的資料: {}", e)))
+                .and_then(validated_request)
+        })
+        .collect())
+}
+
+pub fn validated(row: CreateDisposition) -> ParsedRow {
+    row.validate().map(|_| row).map_err(|e| field_errors(&e))
+}
+
+// 上傳的列：日期格式錯誤也回報在該欄位上
+fn validated_request(row: CreateDispositionRequest) -> ParsedRow {
+    row.into_disposition().map_err(|e| field_errors(&e))
+}
+
+pub fn row_error(message: String) -> Vec<FieldError> {
+    vec![FieldError { field: "__all__".to_string(), message }]
+}
+
+// 批次匯入、交易所公告匯入（API 與 CLI）共用：fail 模式先擋下無效列，其餘有效列整批交給 store
+pub async fn run(store: &dyn DispositionStore, mode: ConflictMode, rows: Vec<ParsedRow>) -> AppResult<BulkImportReport> {
+    if mode == ConflictMode::Fail {
+        let errors = invalid_rows(&rows);
+        if !errors.is_empty() {
+            return Err(AppError::Validation { errors });
+        }
+    }
+
+    let valid: Vec<CreateDisposition> = rows.iter().filter_map(|row| row.as_ref().ok().cloned()).collect();
+    let outcomes = if valid.is_empty() { Vec::new() } else { store.import(&valid, mode).await? };
+    if let Some(index) = conflict_index(&rows, &outcomes) {
+        return Err(AppError::Conflict { field: format!("rows[{}].stock_date,symbol", index) });
+    }
+    Ok(report(mode, rows, outcomes))
+}
+
+// fail 模式下只要有無效列就整批拒絕，欄位名稱加上從 0 起算的索引，例如第 3 筆為 rows[2].symbol
+fn invalid_rows(rows: &[ParsedRow]) -> Vec<FieldError> {
+    rows.iter()
+        .enumerate()
+        .filter_map(|(i, row)| row.as_ref().err().map(|errors| (i, errors)))
+        .flat_map(|(index, errors)| {
+            errors.iter().map(move |e| FieldError { field: format!("rows[{}].{}", index, e.field), message: e.message.clone() })
+        })
+        .collect()
+}
+
+// 有效列交給 store 的順序即 rows 中 Ok 的順序，據此把 outcomes 對回原本的索引
+fn conflict_index(rows: &[ParsedRow], outcomes: &[ImportOutcome]) -> Option<usize> {
+    if outcomes.last() != Some(&ImportOutcome::Conflict) {
+        return None;
+    }
+    rows.iter()
+        .enumerate()
+        .filter(|(_, row)| row.is_ok())
+        .nth(outcomes.len() - 1)
+        .map(|(i, _)| i)
+}
+
+fn report(mode: ConflictMode, rows: Vec<ParsedRow>, outcomes: Vec<ImportOutcome>) -> BulkImportReport {
+    let mut outcomes = outcomes.into_iter();
+    let mut report = BulkImportReport {
+        on_conflict: mode,
+        total: rows.len(),
+        created: 0,
+        updated: 0,
+        skipped: 0,
+        invalid: 0,
+        rows: Vec::with_capacity(rows.len()),
+    };
+
+    for (i, row) in rows.into_iter().enumerate() {
+        let result = match row {
+            Ok(disposition) => {
+                let (status, id, errors) = match outcomes.next() {
+                    Some(ImportOutcome::Created(id)) => (BulkRowStatus::Created, id, None),
+                    Some(ImportOutcome::Updated(id)) => (BulkRowStatus::Updated, id, None),
+                    Some(ImportOutcome::Skipped(id)) => (BulkRowStatus::Skipped, id, None),
+                    Some(ImportOutcome::InvalidPeriod(id)) => {
+                        (BulkRowStatus::Invalid, id, Some(vec![FieldError { field: "period".to_string(), message: MERGED_PERIOD_MESSAGE.to_string() }]))
+                    }
+                    // Conflict 已在 run 中轉成錯誤，這裡不會出現
+                    Some(ImportOutcome::Conflict) | None => continue,
+                };
+                BulkRowResult {
+                    row: i + 1,
+                    status,
+                    id: Some(id),
+                    stock_date: Some(disposition.stock_date),
+                    symbol: Some(disposition.symbol),
+                    errors,
+                }
+            }
+            Err(errors) => BulkRowResult {
+                row: i + 1,
+                status: BulkRowStatus::Invalid,
+                id: None,
+                stock_date: None,
+                symbol: None,
+                errors: Some(errors),
+            },
+        };
+        match result.status {
+            BulkRowStatus::Created => report.created += 1,
+            BulkRowStatus::Updated => report.updated += 1,
+            BulkRowStatus::Skipped => report.skipped += 1,
+            BulkRowStatus::Invalid => report.invalid += 1,
+        }
+        report.rows.push(result);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::InMemoryDispositionStore;
+    use crate::models::{DispositionEventKind, TwDate};
+
+    const CSV: &str = "symbol,name,stock_date,market,start,end\n\
+        2330,台積電,2025-10-17,TWSE,2025-10-20,2025-10-31\n\
+        abc,無效代碼,2025-10-17,TWSE,,\n\
+        6488,環球晶,2025-10-17,TPEx,,\n";
+
+    fn statuses(report: &BulkImportReport) -> Vec<BulkRowStatus> {
+        report.rows.iter().map(|r| r.status).collect()
+    }
+
+    async fn seeded() -> InMemoryDispositionStore {
+        let store = InMemoryDispositionStore::new();
+        let rows = parse_csv("stock_date,market,symbol,name,start,end\n2025-10-17,TWSE,2330,舊名稱,2025-10-20,2025-10-31\n".as_bytes()).unwrap();
+        run(&store, ConflictMode::Fail, rows).await.unwrap();
+        store
+    }
+
+    // 標題列決定欄位順序，start/end 可留空；無效列只影響該列
+    #[test]
+    fn parses_csv_by_header() {
+        let rows = parse_body("text/csv", CSV.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!((first.symbol.as_str(), first.market.as_str()), ("2330", "TWSE"));
+        assert_eq!(first.end, TwDate::parse("2025-10-31"));
+        assert_eq!(rows[1].as_ref().unwrap_err()[0].field, "symbol");
+        assert_eq!(rows[2].as_ref().unwrap().start, None);
+        // 日期格式錯誤標在該欄位上
+        let bad_date = parse_body("text/csv", "stock_date,market,symbol,name,end\n2025-10-17,TWSE,2330,台積電,114/13/01\n".as_bytes()).unwrap();
+        assert_eq!(bad_date[0].as_ref().unwrap_err()[0].field, "end");
+
+        // 匯出的 CSV 開頭有 UTF-8 BOM，要能直接再匯入
+        let exported = format!("\u{feff}{}", CSV);
+        assert!(parse_body("text/csv", exported.as_bytes()).unwrap()[0].is_ok());
+        // 缺少必要欄位時每列都無效
+        let missing = parse_body("text/csv", b"symbol,name\n2330,x\n").unwrap();
+        assert_eq!(missing[0].as_ref().unwrap_err()[0].field, "__all__");
+        assert!(matches!(parse_body("application/xml", b"<a/>"), Err(AppError::BadRequest(_))));
+        assert!(matches!(parse_body("application/json", b"[]"), Err(AppError::BadRequest(_))));
+        let items = vec![serde_json::json!({}); MAX_BULK_ROWS + 1];
+        let body = serde_json::to_vec(&items).unwrap();
+        assert!(matches!(parse_body("application/json", &body), Err(AppError::BadRequest(m)) if m.contains("5000")));
+    }
+
+    // fail：有無效列時整批拒絕並帶列號；與既有資料重複時整批回滾
+    #[actix_web::test]
+    async fn fail_mode_rejects_whole_batch() {
+        let store = seeded().await;
+
+        let rows = parse_csv(CSV.as_bytes()).unwrap();
+        match run(&store, ConflictMode::Fail, rows).await {
+            Err(AppError::Validation { errors }) => assert_eq!(errors[0].field, "rows[1].symbol"),
+            other => panic!("預期驗證錯誤: {:?}", other.map(|r| r.total)),
+        }
+
+        let rows = parse_csv("stock_date,market,symbol,name\n2025-10-17,TPEx,6488,環球晶\n2025-10-17,TWSE,2330,台積電\n".as_bytes()).unwrap();
+        match run(&store, ConflictMode::Fail, rows).await {
+            Err(AppError::Conflict { field }) => {
+                assert_eq!(field, "rows[1].stock_date,symbol");
+                assert_eq!(AppError::Conflict { field }.to_string(), "第 2 筆：該日期的處置股已存在");
+            }
+            other => panic!("預期重複錯誤: {:?}", other.map(|r| r.total)),
+        }
+        assert!(store.get_by_symbol(6488).await.unwrap().is_none(), "衝突前的列也要回滾");
+        assert_eq!(store.last_event_id().await.unwrap(), 1, "回滾的列不會留下事件");
+    }
+
+    // skip：重複的保留既有資料，無效的略過，其餘寫入
+    #[actix_web::test]
+    async fn skip_mode_keeps_existing_rows() {
+        let store = seeded().await;
+        let report = run(&store, ConflictMode::Skip, parse_csv(CSV.as_bytes()).unwrap()).await.unwrap();
+
+        assert_eq!(statuses(&report), [BulkRowStatus::Skipped, BulkRowStatus::Invalid, BulkRowStatus::Created]);
+        assert_eq!((report.created, report.skipped, report.invalid), (1, 1, 1));
+        assert_eq!(store.get_by_symbol(2330).await.unwrap().unwrap().name, "舊名稱");
+        assert!(store.get_by_symbol(6488).await.unwrap().is_some());
+    }
+
+    // upsert：重複的更新名稱與有提供的日期；合併後起日晚於迄日的列視為無效、不寫入
+    #[actix_web::test]
+    async fn upsert_mode_merges_periods() {
+        let store = seeded().await;
+        let csv = "stock_date,market,symbol,name,end\n2025-10-17,TWSE,2330,新名稱,2025-11-07\n";
+        let report = run(&store, ConflictMode::Upsert, parse_csv(csv.as_bytes()).unwrap()).await.unwrap();
+        assert_eq!(statuses(&report), [BulkRowStatus::Updated]);
+        let events = store.events_after(1, 10).await.unwrap();
+        assert_eq!(events.iter().map(|e| (e.id, e.event.event)).collect::<Vec<_>>(), [(2, DispositionEventKind::Updated)]);
+        let updated = store.get_by_symbol(2330).await.unwrap().unwrap();
+        assert_eq!(updated.name, "新名稱");
+        assert_eq!((updated.start, updated.end), (TwDate::parse("2025-10-20"), TwDate::parse("2025-11-07")));
+
+        let csv = "stock_date,market,symbol,name,start\n2025-10-17,TWSE,2330,再改名,2025-12-01\n";
+        let report = run(&store, ConflictMode::Upsert, parse_csv(csv.as_bytes()).unwrap()).await.unwrap();
+        assert_eq!(statuses(&report), [BulkRowStatus::Invalid]);
+        assert_eq!(report.rows[0].errors.as_ref().unwrap()[0].field, "period");
+        assert_eq!(store.get_by_symbol(2330).await.unwrap().unwrap().name, "新名稱");
+        assert_eq!(store.last_event_id().await.unwrap(), 2);
+    }
+}
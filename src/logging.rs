@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+// 由低到高，與 config 的 log.level 相同
+pub const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+const DEFAULT_LEVEL: usize = 2;
+
+// 相依套件（hyper、rustls 等）的事件只記錄 warn 以上，避免 debug 時被大量輸出淹沒
+const OWN_TARGET: &str = env!("CARGO_CRATE_NAME");
+const DEPENDENCY_LEVEL: usize = 1;
+
+// 目前的 log level，可在執行期間透過 PUT /admin/log-level 調整
+#[derive(Clone)]
+pub struct LevelHandle {
+    level: Arc<AtomicUsize>,
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LevelHandle {
+    pub fn get(&self) -> &'static str {
+        LOG_LEVELS[self.level.load(Ordering::Relaxed)]
+    }
+
+    // 不認得的 level 回傳 false，維持原本的設定
+    pub fn set(&self, level: &str) -> bool {
+        let Some(index) = level_index(level) else {
+            return false;
+        };
+        if self.filter.reload(filter(index)).is_err() {
+            return false;
+        }
+        self.level.store(index, Ordering::Relaxed);
+        true
+    }
+}
+
+fn level_index(level: &str) -> Option<usize> {
+    LOG_LEVELS.iter().position(|l| l.eq_ignore_ascii_case(level))
+}
+
+// 本身的 target 依設定的 level，相依套件最多到 warn
+fn filter(index: usize) -> EnvFilter {
+    EnvFilter::new(format!("{},{}={}", LOG_LEVELS[index.min(DEPENDENCY_LEVEL)], OWN_TARGET, LOG_LEVELS[index]))
+}
+
+// 每個事件輸出一行 JSON：timestamp、level、target、message 與事件欄位在最上層，
+// 所在 span 的名稱與欄位（例如 request_id）由外到內放在 spans
+fn subscriber<W>(level: &str, writer: W) -> (impl Subscriber + Send + Sync, LevelHandle)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let index = level_index(level).unwrap_or(DEFAULT_LEVEL);
+    let (filter, handle) = reload::Layer::new(filter(index));
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(fmt::layer().json().flatten_event(true).with_current_span(false).with_writer(writer));
+    (subscriber, LevelHandle { level: Arc::new(AtomicUsize::new(index)), filter: handle })
+}
+
+// 安裝全域的 JSON subscriber，輸出到 stdout；只在啟動時呼叫一次
+pub fn init(level: &str) -> LevelHandle {
+    let (subscriber, handle) = subscriber(level, std::io::stdout);
+    subscriber.init();
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'w self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<Value> {
+            let bytes = self.0.lock().unwrap();
+            String::from_utf8_lossy(&bytes).lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+        }
+    }
+
+    // 事件帶有所在 span 的欄位；調整 level 後立即生效，相依套件仍只到 warn
+    #[test]
+    fn writes_span_fields_and_follows_level() {
+        let buffer = Buffer::default();
+        let (subscriber, level) = subscriber("info", buffer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request", request_id = "abc123");
+            let _entered = request.enter();
+            tracing::info!(status = 200, "請求完成");
+            tracing::debug!("看不到");
+            assert!(level.set("debug"));
+            assert!(!level.set("verbose"));
+            tracing::info_span!("db", op = "user.list").in_scope(|| tracing::debug!(elapsed_ms = 3u64, "SQL 執行完成"));
+            tracing::debug!(target: "hyper::proto", "相依套件的 debug 看不到");
+        });
+
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0]["message"].as_str(), lines[0]["status"].as_u64()), (Some("請求完成"), Some(200)));
+        assert_eq!(lines[0]["spans"][0]["request_id"], "abc123");
+        assert_eq!(lines[1]["level"], "DEBUG");
+        assert_eq!(lines[1]["elapsed_ms"], 3);
+        assert_eq!(lines[1]["spans"], serde_json::json!([
+            { "name": "request", "request_id": "abc123" },
+            { "name": "db", "op": "user.list" },
+        ]));
+        assert_eq!(level.get(), "debug");
+    }
+}
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use crate::error::AppResult;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// 寄信的抽象，實際 SMTP 之後再接；handler 只依賴這個 trait
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> AppResult<()>;
+}
+
+// 本機替身：只在 log 記錄寄件資訊。信件內容含有重設密碼的 token，
+// 看得到 log 的人就能接管帳號，只有 log_body（mail.log_body）開啟時才一併寫入，方便開發時複製連結
+pub struct LogMailer {
+    pub log_body: bool,
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> AppResult<()> {
+        if self.log_body {
+            tracing::info!(from = %mail.from, to = %mail.to, subject = %mail.subject, body = %mail.body, "寄出信件");
+        } else {
+            tracing::info!(from = %mail.from, to = %mail.to, subject = %mail.subject, "寄出信件");
+        }
+        Ok(())
+    }
+}
+
+pub fn password_reset_mail(from: &str, to: &str, link: &str, ttl_secs: u64) -> Mail {
+    Mail {
+        from: from.to_string(),
+        to: to.to_string(),
+        subject: "重設密碼".to_string(),
+        body: format!(
+            "請在 {} 分鐘內開啟以下連結重設密碼，若不是您本人申請請忽略此信：\n{}",
+            ttl_secs.div_ceil(60),
+            link
+        ),
+    }
+}
+
+// 測試用：把寄出的信留在記憶體
+#[cfg(test)]
+#[derive(Default)]
+pub struct OutboxMailer {
+    pub sent: std::sync::Mutex<Vec<Mail>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Mailer for OutboxMailer {
+    async fn send(&self, mail: Mail) -> AppResult<()> {
+        self.sent.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
@@ -1,64 +1,284 @@
 mod models;
+mod config;
 mod db;
+mod migrate;
 mod repository;
+mod pagination;
+mod clock;
+mod error;
+mod store;
+mod memory;
+mod auth;
+mod password;
+mod mail;
 mod handler;
-use actix_web::HttpResponse;
+mod api_key;
+mod import;
+mod export;
+mod calendar;
+mod events;
+mod sse;
+mod ws;
+mod webhook;
+mod exchange;
+mod date_format;
+mod logging;
+mod metrics;
+mod request_id;
+mod openapi;
+mod routes;
 use actix_cors::Cors;
-use crate::models::ApiResponse;
+use crate::error::AppError;
 
 use actix_web::{web, App, HttpServer};
-use handler::*;
+use std::sync::Arc;
+use crate::store::{UserStore, DispositionStore, ApiKeyStore, WebhookStore, MySqlUserStore, MySqlDispositionStore, MySqlApiKeyStore, MySqlWebhookStore};
+use crate::memory::{InMemoryUserStore, InMemoryDispositionStore, InMemoryApiKeyStore, InMemoryWebhookStore};
+use crate::config::{Config, RunMode, StorageBackend};
+use crate::auth::TokenService;
+use crate::mail::{LogMailer, Mailer};
+use crate::exchange::Exchange;
+use crate::models::ConflictMode;
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(ApiResponse::success("OK", "服務正常運行"))
+// 依 database.backend 建立的各個 store
+type Stores = (Arc<dyn UserStore>, Arc<dyn DispositionStore>, Arc<dyn ApiKeyStore>, Arc<dyn WebhookStore>);
+
+// `rust-crud-api migrate [up|down [N]|status]`
+fn run_migrate_command(config: &Config, args: &[String]) -> anyhow::Result<()> {
+    let pool = db::connect(&config.database)?;
+    let mut conn = pool.get_conn()?;
+
+    match args.first().map(String::as_str).unwrap_or("up") {
+        "up" => {
+            let applied = migrate::run_pending(&mut conn)?;
+            println!("✅ 已套用 {} 個 migration", applied.len());
+        }
+        "down" => {
+            let steps = match args.get(1) {
+                Some(n) => n.parse().map_err(|_| anyhow::anyhow!("無效的回滾步數: {}", n))?,
+                None => 1,
+            };
+            let reverted = migrate::rollback(&mut conn, steps)?;
+            println!("✅ 已回滾 {} 個 migration", reverted.len());
+        }
+        "status" => {
+            for m in migrate::status(&mut conn)? {
+                let state = match (m.applied, m.applied_at) {
+                    (true, Some(at)) => format!("已套用 {}", at),
+                    (true, None) => "已套用".to_string(),
+                    (false, _) => "未套用".to_string(),
+                };
+                println!("{:04} {:<40} {}", m.version, m.name, state);
+            }
+        }
+        other => anyhow::bail!("未知的 migrate 指令: {}（可用: up, down [N], status）", other),
+    }
+    Ok(())
+}
+
+// `rust-crud-api import <twse|tpex> <檔案> [--on-conflict fail|skip|upsert] [--dry-run]`
+async fn run_import_command(config: &Config, args: &[String]) -> anyhow::Result<()> {
+    const USAGE: &str = "用法: import <twse|tpex> <檔案> [--on-conflict fail|skip|upsert] [--dry-run]";
+    let [source, path, options @ ..] = args else {
+        anyhow::bail!(USAGE);
+    };
+    let exchange = Exchange::parse(source).ok_or_else(|| anyhow::anyhow!("不支援的交易所: {}（可用: twse, tpex）", source))?;
+    let mut mode = ConflictMode::Fail;
+    let mut dry_run = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--dry-run" => dry_run = true,
+            "--on-conflict" => {
+                let value = options.next().map(String::as_str).unwrap_or_default();
+                mode = ConflictMode::parse(value).ok_or_else(|| anyhow::anyhow!("無效的 --on-conflict: {}（可用: fail, skip, upsert）", value))?;
+            }
+            other => anyhow::bail!("未知的參數: {}\n{}", other, USAGE),
+        }
+    }
+
+    let body = std::fs::read(path).map_err(|e| anyhow::anyhow!("無法讀取 {}: {}", path, e))?;
+    let rows = exchange::parse_announcement(exchange, &body)?;
+    if dry_run {
+        for (i, row) in rows.iter().enumerate() {
+            match row {
+                Ok(d) => println!("{:>4} ✅ {} {} {} {} {}～{}", i + 1, d.stock_date.iso(), d.market, d.symbol, d.name,
+                    d.start.map_or("?".to_string(), |date| date.iso()), d.end.map_or("?".to_string(), |date| date.iso())),
+                Err(errors) => println!("{:>4} ❌ {}", i + 1, errors.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join("; ")),
+            }
+        }
+        return Ok(());
+    }
+
+    if config.database.backend == StorageBackend::Memory {
+        anyhow::bail!("記憶體儲存不會保留匯入結果，請連接 MySQL 或改用 --dry-run");
+    }
+    let store = MySqlDispositionStore::new(db::create_pool(&config.database)?);
+    let report = match import::run(&store, mode, rows).await {
+        Ok(report) => report,
+        Err(AppError::Validation { errors }) => {
+            for e in &errors {
+                eprintln!("❌ {}: {}", e.field, e.message);
+            }
+            anyhow::bail!("有 {} 個欄位錯誤，整批未匯入（可改用 --on-conflict skip 略過無效列）", errors.len());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    println!(
+        "✅ {} 公告匯入完成：共 {} 筆，新增 {}、更新 {}、略過 {}、無效 {}",
+        exchange.market(), report.total, report.created, report.updated, report.skipped, report.invalid
+    );
+    for row in report.rows.iter().filter(|r| r.errors.is_some()) {
+        let errors = row.errors.iter().flatten().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>();
+        println!("{:>4} ❌ {}", row.row, errors.join("; "));
+    }
+    Ok(())
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // 初始化資料庫連接池
-    let pool = match db::create_pool() {
-        Ok(pool) => pool,
+    dotenvy::dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mode = match args.first().map(String::as_str) {
+        Some("migrate" | "import") => RunMode::Command,
+        _ => RunMode::Server,
+    };
+
+    // 設定錯誤直接列出並結束，不啟動伺服器
+    let config = match Config::load(mode) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("❌ 無法建立資料庫連接池: {}", e);
+            eprintln!("❌ 設定載入失敗: {}", e);
             std::process::exit(1);
         }
     };
+    // 之後的 log 都是 JSON；CLI 子命令的結果仍直接印出
+    let log_level = web::Data::new(logging::init(&config.log.level));
 
-    println!("🚀 啟動 Rust CRUD API 伺服器...");
+    if args.first().map(String::as_str) == Some("migrate") {
+        if let Err(e) = run_migrate_command(&config, &args[1..]) {
+            eprintln!("❌ migrate 失敗: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.first().map(String::as_str) == Some("import") {
+        if let Err(e) = run_import_command(&config, &args[1..]).await {
+            eprintln!("❌ 匯入失敗: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // backend = memory 時不需要資料庫，其餘情況使用 MySQL
+    let (user_store, disposition_store, api_key_store, webhook_store): Stores =
+        if config.database.backend == StorageBackend::Memory {
+            tracing::warn!("使用記憶體儲存（資料不會保存）");
+            let webhooks = Arc::new(InMemoryWebhookStore::new());
+            (
+                Arc::new(InMemoryUserStore::new()),
+                Arc::new(InMemoryDispositionStore::with_webhooks(webhooks.clone())),
+                Arc::new(InMemoryApiKeyStore::new()),
+                webhooks,
+            )
+        } else {
+            // 初始化資料庫連接池
+            let pool = match db::create_pool(&config.database) {
+                Ok(pool) => pool,
+                Err(e) => {
+                    tracing::error!(error = %e, "無法建立資料庫連接池");
+                    std::process::exit(1);
+                }
+            };
+            (
+                Arc::new(MySqlUserStore::new(pool.clone())),
+                Arc::new(MySqlDispositionStore::new(pool.clone())),
+                Arc::new(MySqlApiKeyStore::new(pool.clone())),
+                Arc::new(MySqlWebhookStore::new(pool)),
+            )
+        };
+
+    match auth::bootstrap_user(user_store.as_ref(), &config.auth).await {
+        Ok(Some(user)) => tracing::info!(email = %user.email, "已設定初始使用者"),
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "無法建立初始使用者");
+            std::process::exit(1);
+        }
+    }
+    if !config.auth.enabled {
+        tracing::warn!("已關閉登入驗證，所有路由都可直接存取");
+    }
+    if config.mail.log_body {
+        tracing::warn!("已開啟 mail.log_body，重設密碼連結會寫進 log，只能用於本機開發");
+    }
+    let tokens = web::Data::new(TokenService::new(&config.auth));
+    let mailer: Arc<dyn Mailer> = Arc::new(LogMailer { log_body: config.mail.log_body });
+    let password_reset = web::Data::new(config.password_reset.clone());
+    let mail = web::Data::new(config.mail.clone());
+    let metrics_config = web::Data::new(config.metrics.clone());
+
+    // 事件廣播與 webhook 投遞在背景執行，與 HTTP worker 無關
+    let hub = web::Data::new(events::EventHub::new());
+    actix_web::rt::spawn(events::relay(hub.clone(), disposition_store.clone()));
+    let dispatcher = webhook::Dispatcher::new(webhook_store.clone(), config.webhook.clone());
+    actix_web::rt::spawn(dispatcher.run(hub.wake()));
+    // 台北時間換日時檢查處置期間的起訖，提醒 WebSocket 訂閱者
+    actix_web::rt::spawn(events::watch_boundaries(hub.clone(), disposition_store.clone()));
+
+    let (host, port) = config.bind_addr();
+    tracing::info!(%host, port, log_level = log_level.get(), "啟動 Rust CRUD API 伺服器");
 
-    HttpServer::new(move || {  
+    let cors_config = config.cors.clone();
+    let server = HttpServer::new(move || {
         // 配置 CORS
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:5174")  // 你的 React 開發服務器
-            .allowed_origin("http://localhost:3000")  // Create React App 默認端口
-            .allowed_origin("http://127.0.0.1:5174")
-            .allowed_origin("http://127.0.0.1:3000")
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-            .allowed_headers(vec![
-                "Content-Type",
-                "Authorization",
-                "Accept",
-            ])
+        let cors = cors_config.origins.iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(cors_config.methods.iter().map(String::as_str))
+            .allowed_headers(cors_config.headers.iter().map(String::as_str))
+            .expose_headers([request_id::REQUEST_ID_HEADER])
             .supports_credentials()
-            .max_age(3600);
-            
+            .max_age(cors_config.max_age);
+
+
         // to(handler)
         App::new()
-            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(user_store.clone()))
+            .app_data(web::Data::from(disposition_store.clone()))
+            .app_data(web::Data::from(api_key_store.clone()))
+            .app_data(web::Data::from(webhook_store.clone()))
+            .app_data(hub.clone())
+            .app_data(tokens.clone())
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(password_reset.clone())
+            .app_data(mail.clone())
+            .app_data(log_level.clone())
+            .app_data(metrics_config.clone())
+            // 參數解析錯誤也回傳統一的 JSON 格式
+            .app_data(web::QueryConfig::default().error_handler(|err, _req| {
+                AppError::BadRequest(format!("無效的查詢參數: {}", err)).into()
+            }))
+            .app_data(web::PathConfig::default().error_handler(|err, _req| {
+                AppError::BadRequest(format!("無效的路徑參數: {}", err)).into()
+            }))
+            .app_data(web::JsonConfig::default().error_handler(|err, _req| {
+                AppError::BadRequest(format!("無效的 JSON: {}", err)).into()
+            }))
+            .wrap(date_format::DateFormatScope)
             .wrap(cors)
-            .route("/health", web::get().to(health_check))  // 健康檢查
-            .route("/user", web::get().to(get_user))
-            .route("/user/{id}", web::get().to(get_user_by_id))
-            .route("/user", web::post().to(create_user))
-            .route("/user/{id}", web::put().to(update_user))
-            .route("/user/{id}", web::delete().to(delete_user))
-            .route("/disposition", web::get().to(get_disposition))
-            .route("/disposition/{symbol}", web::get().to(get_disposition_by_symbol))
-            .route("/disposition", web::post().to(create_disposition))
-            .route("/disposition/{symbol}", web::put().to(update_disposition))
-            .route("/disposition/{symbol}", web::delete().to(delete_disposition))
-    })
-    .bind("127.0.0.1:8888")?
-    .run()
-    .await
+            .wrap(metrics::HttpMetrics)
+            // 最外層：CORS 預檢與被拒絕的請求也會有 request id 與 log
+            .wrap(request_id::RequestTracing)
+            .configure(routes::configure)  // 路由與權限見 src/routes.rs
+    });
+
+    let server = match config.server.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    server.bind((host, port))?
+        .run()
+        .await
 }
\ No newline at end of file
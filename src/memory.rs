@@ -0,0 +1,836 @@
+use crate::models::{ApiKey, ApiKeyUse, CreateApiKey, User, CreateUser, UpdateUser, Disposition, CreateDisposition, ConflictMode, ImportOutcome, UpdateDisposition, DispositionEvent, DispositionEventKind, DispositionFilter, DispositionSortField, Webhook, CreateWebhook, UpdateWebhook, WebhookDelivery, PendingDelivery, DeliveryAttempt, DeliveryStatus};
+use crate::store::{UserStore, DispositionStore, ApiKeyStore, WebhookStore, RowStream};
+use async_trait::async_trait;
+use crate::pagination::{paginate, paginate_sorted, Keyed, PageRequest, Slice};
+use crate::error::{AppError, AppResult};
+use crate::events::StreamEvent;
+use chrono::{Local, NaiveDate, NaiveDateTime, Timelike};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+
+// 記憶體版本的儲存，行為盡量與 MySQL 一致（唯一鍵、排序），方便測試與展示
+fn now() -> NaiveDateTime {
+    // MySQL 的 TIMESTAMP 只到秒
+    let now = Local::now().naive_local();
+    now.with_nanosecond(0).unwrap_or(now)
+}
+
+// 資料已經在記憶體裡，一次放進足夠大的 channel 即可
+fn into_stream<T>(rows: Vec<T>) -> RowStream<T> {
+    let (tx, rx) = mpsc::channel(rows.len().max(1));
+    for row in rows {
+        let _ = tx.try_send(Ok(row));
+    }
+    rx
+}
+
+struct PasswordReset {
+    user_id: u32,
+    token_hash: String,
+    expires_at: NaiveDateTime,
+    used: bool,
+}
+
+#[derive(Default)]
+struct UserTable {
+    next_id: u32,
+    rows: Vec<User>,
+    // 密碼雜湊不放在 User 上，跟 MySQL 一樣另外查
+    password_hashes: HashMap<u32, String>,
+    resets: Vec<PasswordReset>,
+}
+
+#[derive(Default)]
+pub struct InMemoryUserStore {
+    table: RwLock<UserTable>,
+}
+
+impl InMemoryUserStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserStore for InMemoryUserStore {
+    async fn list(&self, page: &PageRequest<u32>) -> AppResult<Slice<User>> {
+        let table = self.table.read().unwrap();
+        Ok(paginate(table.rows.clone(), page))
+    }
+
+    async fn export(&self) -> AppResult<RowStream<User>> {
+        let mut rows = self.table.read().unwrap().rows.clone();
+        rows.sort_by_key(|u| u.id);
+        Ok(into_stream(rows))
+    }
+
+    async fn get_by_id(&self, id: u32) -> AppResult<Option<User>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().find(|u| u.id == id).cloned())
+    }
+
+    async fn get_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().find(|u| u.email == email).cloned())
+    }
+
+    async fn create(&self, user: &CreateUser, password_hash: &str) -> AppResult<User> {
+        let mut table = self.table.write().unwrap();
+        if table.rows.iter().any(|u| u.email == user.email) {
+            return Err(AppError::Conflict { field: "email".to_string() });
+        }
+
+        table.next_id += 1;
+        let created = now();
+        let new_user = User {
+            id: table.next_id,
+            name: user.name.clone(),
+            email: user.email.clone(),
+            role: user.role.unwrap_or_default(),
+            created_at: Some(created),
+            updated_at: Some(created),
+        };
+        table.rows.push(new_user.clone());
+        table.password_hashes.insert(new_user.id, password_hash.to_string());
+        Ok(new_user)
+    }
+
+    async fn update(&self, id: u32, user: &UpdateUser) -> AppResult<Option<User>> {
+        let mut table = self.table.write().unwrap();
+        if let Some(email) = &user.email
+            && table.rows.iter().any(|u| u.id != id && &u.email == email)
+        {
+            return Err(AppError::Conflict { field: "email".to_string() });
+        }
+
+        let Some(row) = table.rows.iter_mut().find(|u| u.id == id) else {
+            return Ok(None);
+        };
+        if user.name.is_none() && user.email.is_none() && user.role.is_none() {
+            return Ok(Some(row.clone()));
+        }
+        if let Some(name) = &user.name {
+            row.name = name.clone();
+        }
+        if let Some(email) = &user.email {
+            row.email = email.clone();
+        }
+        if let Some(role) = user.role {
+            row.role = role;
+        }
+        row.updated_at = Some(now());
+        Ok(Some(row.clone()))
+    }
+
+    async fn delete(&self, id: u32) -> AppResult<bool> {
+        let mut table = self.table.write().unwrap();
+        let before = table.rows.len();
+        table.rows.retain(|u| u.id != id);
+        table.password_hashes.remove(&id);
+        table.resets.retain(|r| r.user_id != id);
+        Ok(table.rows.len() < before)
+    }
+
+    async fn get_password_hash(&self, id: u32) -> AppResult<Option<String>> {
+        let table = self.table.read().unwrap();
+        Ok(table.password_hashes.get(&id).cloned())
+    }
+
+    async fn set_password_hash(&self, id: u32, password_hash: &str) -> AppResult<bool> {
+        let mut table = self.table.write().unwrap();
+        if !table.rows.iter().any(|u| u.id == id) {
+            return Ok(false);
+        }
+        table.password_hashes.insert(id, password_hash.to_string());
+        Ok(true)
+    }
+
+    async fn create_password_reset(&self, user_id: u32, token_hash: &str, ttl_secs: u64) -> AppResult<()> {
+        let mut table = self.table.write().unwrap();
+        table.resets.push(PasswordReset {
+            user_id,
+            token_hash: token_hash.to_string(),
+            expires_at: now() + chrono::Duration::seconds(ttl_secs as i64),
+            used: false,
+        });
+        Ok(())
+    }
+
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> AppResult<Option<u32>> {
+        let mut table = self.table.write().unwrap();
+        let current = now();
+        let Some(user_id) = table.resets.iter()
+            .find(|r| r.token_hash == token_hash && !r.used && r.expires_at > current)
+            .map(|r| r.user_id)
+        else {
+            return Ok(None);
+        };
+
+        table.password_hashes.insert(user_id, password_hash.to_string());
+        for reset in table.resets.iter_mut().filter(|r| r.user_id == user_id) {
+            reset.used = true;
+        }
+        Ok(Some(user_id))
+    }
+}
+
+// 事件只保留最近幾筆，relay 只會讀還沒廣播過的
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Default)]
+struct DispositionTable {
+    next_id: u64,
+    rows: Vec<Disposition>,
+    last_event_id: u64,
+    events: VecDeque<StreamEvent>,
+}
+
+#[derive(Default)]
+pub struct InMemoryDispositionStore {
+    table: RwLock<DispositionTable>,
+    // 設定時，寫入的同時排入 webhook 投遞，對應 MySQL 在同一個 transaction 內寫入 webhook_delivery
+    webhooks: Option<Arc<InMemoryWebhookStore>>,
+}
+
+impl InMemoryDispositionStore {
+    // 不排入 webhook 投遞，只在測試使用
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_webhooks(webhooks: Arc<InMemoryWebhookStore>) -> Self {
+        Self { webhooks: Some(webhooks), ..Self::default() }
+    }
+
+    // 在持有 table 寫入鎖時找出列並更新，與 MySQL 在同一個 transaction 內 SELECT ... FOR UPDATE 相同
+    fn update_at(&self, table: &mut DispositionTable, index: Option<usize>, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let Some(i) = index else {
+            return Ok(None);
+        };
+        let row = &mut table.rows[i];
+        if disposition.start.is_none() && disposition.end.is_none() {
+            return Ok(Some(row.clone()));
+        }
+        (row.start, row.end) = disposition.merged_period(row.start, row.end)?;
+        row.updated_at = Some(now());
+        let updated = row.clone();
+        self.record_event(table, DispositionEventKind::Updated, updated.clone())?;
+        Ok(Some(updated))
+    }
+
+    // 在持有 table 寫入鎖時呼叫，事件 id 依寫入順序遞增
+    fn record_event(&self, table: &mut DispositionTable, kind: DispositionEventKind, data: Disposition) -> AppResult<()> {
+        let event = DispositionEvent::new(kind, data);
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.enqueue(kind.as_str(), &event.payload()?)?;
+        }
+        table.last_event_id += 1;
+        if table.events.len() == MAX_EVENTS {
+            table.events.pop_front();
+        }
+        table.events.push_back(StreamEvent { id: table.last_event_id, event });
+        Ok(())
+    }
+}
+
+// 對應 MySQL 的 `WHERE symbol = ? ORDER BY end DESC, id DESC LIMIT 1`（DESC 時 NULL 排最後）
+fn latest_index(rows: &[Disposition], symbol: i32) -> Option<usize> {
+    rows.iter()
+        .enumerate()
+        .filter(|(_, d)| d.symbol == symbol)
+        .max_by_key(|(_, d)| (d.end, d.id))
+        .map(|(i, _)| i)
+}
+
+fn find_date_symbol(rows: &[Disposition], disposition: &Disposition) -> Option<usize> {
+    rows.iter().position(|d| d.symbol == disposition.symbol && d.stock_date == disposition.stock_date)
+}
+
+// 轉成尚未指定 id 的資料列
+fn new_disposition(disposition: &CreateDisposition) -> AppResult<Disposition> {
+    let symbol: i32 = disposition.symbol.parse().map_err(|e| {
+        AppError::invalid("symbol", format!("無效的股票代碼格式 '{}': {}", disposition.symbol, e))
+    })?;
+    let created = now();
+    Ok(Disposition {
+        id: 0,
+        stock_date: Some(disposition.stock_date),
+        market: disposition.market.clone(),
+        symbol,
+        name: disposition.name.clone(),
+        start: disposition.start,
+        end: disposition.end,
+        created_at: Some(created),
+        updated_at: Some(created),
+    })
+}
+
+impl DispositionTable {
+    fn insert(&mut self, mut disposition: Disposition) -> Disposition {
+        self.next_id += 1;
+        disposition.id = self.next_id;
+        self.rows.push(disposition.clone());
+        disposition
+    }
+}
+
+fn id_index(rows: &[Disposition], symbol: i32, id: u64) -> Option<usize> {
+    rows.iter().position(|d| d.id == id && d.symbol == symbol)
+}
+
+fn covers(d: &Disposition, as_of: NaiveDate) -> bool {
+    matches!((d.start, d.end), (Some(start), Some(end)) if start.0 <= as_of && as_of <= end.0)
+}
+
+// 與 repository 的 filter_conditions 相同語意；NULL 比較在 SQL 中為假
+fn matches_filter(d: &Disposition, filter: &DispositionFilter) -> bool {
+    if let Some(market) = &filter.market
+        && !d.market.eq_ignore_ascii_case(market)
+    {
+        return false;
+    }
+    if filter.symbol.is_some_and(|symbol| d.symbol != symbol) {
+        return false;
+    }
+    if let Some(name) = &filter.name
+        && !d.name.to_lowercase().contains(&name.to_lowercase())
+    {
+        return false;
+    }
+    if filter.stock_date_from.is_some_and(|from| d.stock_date.is_none_or(|date| date < from)) {
+        return false;
+    }
+    if filter.stock_date_to.is_some_and(|to| d.stock_date.is_none_or(|date| date > to)) {
+        return false;
+    }
+    if filter.period_to.is_some_and(|to| d.start.is_none_or(|start| start > to)) {
+        return false;
+    }
+    if filter.period_from.is_some_and(|from| d.end.is_none_or(|end| end < from)) {
+        return false;
+    }
+    true
+}
+
+// MySQL 遞增排序時 NULL 在前，Option 的比較結果相同
+fn compare_field(a: &Disposition, b: &Disposition, field: DispositionSortField) -> Ordering {
+    match field {
+        DispositionSortField::StockDate => a.stock_date.cmp(&b.stock_date),
+        DispositionSortField::Market => a.market.cmp(&b.market),
+        DispositionSortField::Symbol => a.symbol.cmp(&b.symbol),
+        DispositionSortField::Name => a.name.cmp(&b.name),
+        DispositionSortField::Start => a.start.cmp(&b.start),
+        DispositionSortField::End => a.end.cmp(&b.end),
+        DispositionSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+        DispositionSortField::UpdatedAt => a.updated_at.cmp(&b.updated_at),
+    }
+}
+
+#[async_trait]
+impl DispositionStore for InMemoryDispositionStore {
+    async fn list(&self, filter: &DispositionFilter, page: &PageRequest<(NaiveDate, i32)>) -> AppResult<Slice<Disposition>> {
+        let table = self.table.read().unwrap();
+        let mut matched: Vec<Disposition> = table.rows.iter().filter(|d| matches_filter(d, filter)).cloned().collect();
+
+        match filter.sort {
+            Some(sort) => {
+                matched.sort_by(|a, b| {
+                    let ord = compare_field(a, b, sort.field);
+                    let ord = if sort.desc { ord.reverse() } else { ord };
+                    ord.then_with(|| a.key().cmp(&b.key()))
+                });
+                Ok(paginate_sorted(matched, page))
+            }
+            None => Ok(paginate(matched, page)),
+        }
+    }
+
+    async fn export(&self, filter: &DispositionFilter) -> AppResult<RowStream<Disposition>> {
+        let table = self.table.read().unwrap();
+        let mut matched: Vec<Disposition> = table.rows.iter().filter(|d| matches_filter(d, filter)).cloned().collect();
+        match filter.sort {
+            Some(sort) => matched.sort_by(|a, b| {
+                let ord = compare_field(a, b, sort.field);
+                let ord = if sort.desc { ord.reverse() } else { ord };
+                ord.then_with(|| a.key().cmp(&b.key()))
+            }),
+            None => matched.sort_by_key(|d| d.key()),
+        }
+        Ok(into_stream(matched))
+    }
+
+    async fn get_by_symbol(&self, symbol: i32) -> AppResult<Option<Disposition>> {
+        let table = self.table.read().unwrap();
+        Ok(latest_index(&table.rows, symbol).map(|i| table.rows[i].clone()))
+    }
+
+    async fn get_active(&self, as_of: NaiveDate) -> AppResult<Vec<Disposition>> {
+        let table = self.table.read().unwrap();
+        let mut active: Vec<Disposition> = table.rows.iter().filter(|d| covers(d, as_of)).cloned().collect();
+        active.sort_by(|a, b| a.market.cmp(&b.market).then(a.symbol.cmp(&b.symbol)));
+        Ok(active)
+    }
+
+    async fn get_active_by_symbol(&self, symbol: i32, as_of: NaiveDate) -> AppResult<Option<Disposition>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter()
+            .filter(|d| d.symbol == symbol && covers(d, as_of))
+            .max_by_key(|d| d.end)
+            .cloned())
+    }
+
+    async fn get_history(&self, symbol: i32) -> AppResult<Vec<Disposition>> {
+        let table = self.table.read().unwrap();
+        let mut history: Vec<Disposition> = table.rows.iter().filter(|d| d.symbol == symbol).cloned().collect();
+        history.sort_by(|a, b| b.stock_date.cmp(&a.stock_date).then(b.id.cmp(&a.id)));
+        Ok(history)
+    }
+
+    async fn get_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        let table = self.table.read().unwrap();
+        Ok(id_index(&table.rows, symbol, id).map(|i| table.rows[i].clone()))
+    }
+
+    async fn create(&self, disposition: &CreateDisposition) -> AppResult<Disposition> {
+        let new_disposition = new_disposition(disposition)?;
+        let mut table = self.table.write().unwrap();
+        if find_date_symbol(&table.rows, &new_disposition).is_some() {
+            return Err(AppError::Conflict { field: "stock_date,symbol".to_string() });
+        }
+        let created = table.insert(new_disposition);
+        self.record_event(&mut table, DispositionEventKind::Created, created.clone())?;
+        Ok(created)
+    }
+
+    // 在複本上處理整批，全部成功才換回去，模擬 transaction
+    async fn import(&self, rows: &[CreateDisposition], mode: ConflictMode) -> AppResult<Vec<ImportOutcome>> {
+        let parsed = rows.iter().map(new_disposition).collect::<AppResult<Vec<_>>>()?;
+        let mut table = self.table.write().unwrap();
+        let mut draft = DispositionTable { next_id: table.next_id, rows: table.rows.clone(), ..DispositionTable::default() };
+        let mut outcomes = Vec::with_capacity(parsed.len());
+        let mut changed = Vec::new();
+
+        for disposition in parsed {
+            let outcome = match (find_date_symbol(&draft.rows, &disposition), mode) {
+                (None, _) => {
+                    let created = draft.insert(disposition);
+                    let id = created.id;
+                    changed.push((DispositionEventKind::Created, created));
+                    ImportOutcome::Created(id)
+                }
+                (Some(i), ConflictMode::Upsert) => {
+                    let row = &mut draft.rows[i];
+                    let (start, end) = (disposition.start.or(row.start), disposition.end.or(row.end));
+                    if matches!((start, end), (Some(start), Some(end)) if start > end) {
+                        ImportOutcome::InvalidPeriod(row.id)
+                    } else {
+                        row.market = disposition.market;
+                        row.name = disposition.name;
+                        row.start = start;
+                        row.end = end;
+                        row.updated_at = Some(now());
+                        changed.push((DispositionEventKind::Updated, row.clone()));
+                        ImportOutcome::Updated(row.id)
+                    }
+                }
+                (Some(i), ConflictMode::Skip) => ImportOutcome::Skipped(draft.rows[i].id),
+                (Some(_), ConflictMode::Fail) => {
+                    outcomes.push(ImportOutcome::Conflict);
+                    return Ok(outcomes);
+                }
+            };
+            outcomes.push(outcome);
+        }
+
+        table.next_id = draft.next_id;
+        table.rows = draft.rows;
+        for (kind, disposition) in changed {
+            self.record_event(&mut table, kind, disposition)?;
+        }
+        Ok(outcomes)
+    }
+
+    async fn update(&self, symbol: i32, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let mut table = self.table.write().unwrap();
+        let index = latest_index(&table.rows, symbol);
+        self.update_at(&mut table, index, disposition)
+    }
+
+    async fn update_by_id(&self, symbol: i32, id: u64, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let mut table = self.table.write().unwrap();
+        let index = id_index(&table.rows, symbol, id);
+        self.update_at(&mut table, index, disposition)
+    }
+
+    async fn delete(&self, symbol: i32) -> AppResult<Option<Disposition>> {
+        let mut table = self.table.write().unwrap();
+        let Some(i) = latest_index(&table.rows, symbol) else {
+            return Ok(None);
+        };
+        let deleted = table.rows.remove(i);
+        self.record_event(&mut table, DispositionEventKind::Deleted, deleted.clone())?;
+        Ok(Some(deleted))
+    }
+
+    async fn delete_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        let mut table = self.table.write().unwrap();
+        let Some(i) = id_index(&table.rows, symbol, id) else {
+            return Ok(None);
+        };
+        let deleted = table.rows.remove(i);
+        self.record_event(&mut table, DispositionEventKind::Deleted, deleted.clone())?;
+        Ok(Some(deleted))
+    }
+
+    async fn events_after(&self, after: u64, limit: u32) -> AppResult<Vec<StreamEvent>> {
+        let table = self.table.read().unwrap();
+        Ok(table.events.iter().filter(|e| e.id > after).take(limit as usize).cloned().collect())
+    }
+
+    async fn last_event_id(&self) -> AppResult<u64> {
+        Ok(self.table.read().unwrap().last_event_id)
+    }
+}
+
+struct StoredApiKey {
+    key: ApiKey,
+    key_hash: String,
+}
+
+#[derive(Default)]
+struct ApiKeyTable {
+    next_id: u64,
+    next_use_id: u64,
+    rows: Vec<StoredApiKey>,
+    uses: Vec<ApiKeyUse>,
+}
+
+#[derive(Default)]
+pub struct InMemoryApiKeyStore {
+    table: RwLock<ApiKeyTable>,
+}
+
+impl InMemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for InMemoryApiKeyStore {
+    async fn list(&self, user_id: u32) -> AppResult<Vec<ApiKey>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().rev().filter(|r| r.key.user_id == user_id).map(|r| r.key.clone()).collect())
+    }
+
+    async fn get_by_id(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().find(|r| r.key.user_id == user_id && r.key.id == id).map(|r| r.key.clone()))
+    }
+
+    async fn create(&self, user_id: u32, key: &CreateApiKey, prefix: &str, key_hash: &str) -> AppResult<ApiKey> {
+        let mut table = self.table.write().unwrap();
+        table.next_id += 1;
+        let created = now();
+        let api_key = ApiKey {
+            id: table.next_id,
+            user_id,
+            name: key.name.clone(),
+            prefix: prefix.to_string(),
+            scopes: key.scopes.clone(),
+            expires_at: key.expires_in_days.map(|days| created + chrono::Duration::days(days as i64)),
+            last_used_at: None,
+            revoked_at: None,
+            created_at: Some(created),
+        };
+        table.rows.push(StoredApiKey { key: api_key.clone(), key_hash: key_hash.to_string() });
+        Ok(api_key)
+    }
+
+    async fn revoke(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        let mut table = self.table.write().unwrap();
+        let Some(row) = table.rows.iter_mut().find(|r| r.key.user_id == user_id && r.key.id == id) else {
+            return Ok(None);
+        };
+        row.key.revoked_at.get_or_insert_with(now);
+        Ok(Some(row.key.clone()))
+    }
+
+    async fn find_active(&self, key_hash: &str) -> AppResult<Option<ApiKey>> {
+        let table = self.table.read().unwrap();
+        let current = now();
+        Ok(table.rows.iter()
+            .find(|r| r.key_hash == key_hash)
+            .filter(|r| r.key.revoked_at.is_none() && r.key.expires_at.is_none_or(|at| at > current))
+            .map(|r| r.key.clone()))
+    }
+
+    async fn record_use(&self, id: u64, method: &str, path: &str, status: u16, ip: Option<&str>) -> AppResult<()> {
+        let mut table = self.table.write().unwrap();
+        let used = now();
+        if let Some(row) = table.rows.iter_mut().find(|r| r.key.id == id) {
+            row.key.last_used_at = Some(used);
+        }
+        table.next_use_id += 1;
+        let use_id = table.next_use_id;
+        table.uses.push(ApiKeyUse {
+            id: use_id,
+            api_key_id: id,
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+            ip: ip.map(str::to_string),
+            created_at: Some(used),
+        });
+        Ok(())
+    }
+
+    async fn list_uses(&self, id: u64, limit: u32) -> AppResult<Vec<ApiKeyUse>> {
+        let table = self.table.read().unwrap();
+        Ok(table.uses.iter().rev().filter(|u| u.api_key_id == id).take(limit as usize).cloned().collect())
+    }
+}
+
+struct StoredWebhook {
+    webhook: Webhook,
+    secret: String,
+}
+
+#[derive(Default)]
+struct WebhookTable {
+    next_id: u64,
+    next_delivery_id: u64,
+    rows: Vec<StoredWebhook>,
+    deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Default)]
+pub struct InMemoryWebhookStore {
+    table: RwLock<WebhookTable>,
+}
+
+impl InMemoryWebhookStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 為每個啟用中的 webhook 排入一筆投遞；由 InMemoryDispositionStore 在寫入時呼叫
+    fn enqueue(&self, event: &str, payload: &str) -> AppResult<()> {
+        let mut table = self.table.write().unwrap();
+        let payload: serde_json::Value = serde_json::from_str(payload)
+            .map_err(|e| AppError::Internal(format!("無效的 payload: {}", e)))?;
+        let created = now();
+        let targets: Vec<u64> = table.rows.iter().filter(|r| r.webhook.active).map(|r| r.webhook.id).collect();
+        for webhook_id in targets {
+            table.next_delivery_id += 1;
+            let id = table.next_delivery_id;
+            table.deliveries.push(WebhookDelivery {
+                id,
+                webhook_id,
+                event: event.to_string(),
+                payload: payload.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_at: Some(created),
+                last_status: None,
+                last_error: None,
+                created_at: Some(created),
+                delivered_at: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebhookStore for InMemoryWebhookStore {
+    async fn list(&self) -> AppResult<Vec<Webhook>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().map(|r| r.webhook.clone()).collect())
+    }
+
+    async fn get_by_id(&self, id: u64) -> AppResult<Option<Webhook>> {
+        let table = self.table.read().unwrap();
+        Ok(table.rows.iter().find(|r| r.webhook.id == id).map(|r| r.webhook.clone()))
+    }
+
+    async fn create(&self, webhook: &CreateWebhook, secret: &str) -> AppResult<Webhook> {
+        let mut table = self.table.write().unwrap();
+        table.next_id += 1;
+        let created = now();
+        let new_webhook = Webhook {
+            id: table.next_id,
+            url: webhook.url.clone(),
+            description: webhook.description.clone(),
+            active: true,
+            created_at: Some(created),
+            updated_at: Some(created),
+        };
+        table.rows.push(StoredWebhook { webhook: new_webhook.clone(), secret: secret.to_string() });
+        Ok(new_webhook)
+    }
+
+    async fn update(&self, id: u64, webhook: &UpdateWebhook) -> AppResult<Option<Webhook>> {
+        let mut table = self.table.write().unwrap();
+        let Some(row) = table.rows.iter_mut().find(|r| r.webhook.id == id) else {
+            return Ok(None);
+        };
+        if let Some(url) = &webhook.url {
+            row.webhook.url = url.clone();
+        }
+        if let Some(description) = &webhook.description {
+            row.webhook.description = description.clone();
+        }
+        if let Some(active) = webhook.active {
+            row.webhook.active = active;
+        }
+        row.webhook.updated_at = Some(now());
+        Ok(Some(row.webhook.clone()))
+    }
+
+    async fn delete(&self, id: u64) -> AppResult<bool> {
+        let mut table = self.table.write().unwrap();
+        let before = table.rows.len();
+        table.rows.retain(|r| r.webhook.id != id);
+        table.deliveries.retain(|d| d.webhook_id != id);
+        Ok(table.rows.len() < before)
+    }
+
+    async fn due(&self, limit: u32) -> AppResult<Vec<PendingDelivery>> {
+        let table = self.table.read().unwrap();
+        let current = now();
+        let mut due: Vec<&WebhookDelivery> = table.deliveries.iter()
+            .filter(|d| d.status == DeliveryStatus::Pending && d.next_attempt_at.is_some_and(|at| at <= current))
+            .collect();
+        due.sort_by_key(|d| (d.next_attempt_at, d.id));
+        Ok(due.into_iter()
+            .filter_map(|d| {
+                let row = table.rows.iter().find(|r| r.webhook.id == d.webhook_id)?;
+                Some(PendingDelivery {
+                    id: d.id,
+                    webhook_id: d.webhook_id,
+                    url: row.webhook.url.clone(),
+                    secret: row.secret.clone(),
+                    event: d.event.clone(),
+                    payload: d.payload.to_string(),
+                    attempts: d.attempts,
+                })
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn record_attempt(&self, id: u64, attempt: &DeliveryAttempt) -> AppResult<()> {
+        let mut table = self.table.write().unwrap();
+        let Some(delivery) = table.deliveries.iter_mut().find(|d| d.id == id) else {
+            return Ok(());
+        };
+        let attempted = now();
+        delivery.attempts += 1;
+        delivery.last_status = attempt.status;
+        delivery.last_error = attempt.error.clone();
+        delivery.next_attempt_at = attempt.retry_in.map(|secs| attempted + chrono::Duration::seconds(secs as i64));
+        delivery.status = attempt.status();
+        delivery.delivered_at = attempt.delivered.then_some(attempted);
+        Ok(())
+    }
+
+    async fn list_deliveries(&self, webhook_id: u64, limit: u32) -> AppResult<Vec<WebhookDelivery>> {
+        let table = self.table.read().unwrap();
+        Ok(table.deliveries.iter().rev().filter(|d| d.webhook_id == webhook_id).take(limit as usize).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Role, TwDate};
+
+    fn user(email: &str) -> CreateUser {
+        CreateUser { name: "Amy".to_string(), email: email.to_string(), password: "unused-password".to_string(), role: None }
+    }
+
+    fn disposition(stock_date: &str, symbol: &str, start: &str, end: &str) -> CreateDisposition {
+        CreateDisposition {
+            stock_date: TwDate::parse(stock_date).unwrap(),
+            market: "TWSE".to_string(),
+            symbol: symbol.to_string(),
+            name: "台積電".to_string(),
+            start: TwDate::parse(start),
+            end: TwDate::parse(end),
+        }
+    }
+
+    fn period(start: &str, end: &str) -> UpdateDisposition {
+        UpdateDisposition { start: TwDate::parse(start), end: TwDate::parse(end) }
+    }
+
+    // email 唯一、更新只改有帶的欄位、刪除後密碼雜湊一併移除；與 MySQL 的 uk_user_email 相同回傳 Conflict { email }
+    #[actix_web::test]
+    async fn user_store_contract() {
+        let store = InMemoryUserStore::new();
+        let amy = store.create(&user("amy@example.com"), "hash-a").await.unwrap();
+        let bob = store.create(&user("bob@example.com"), "hash-b").await.unwrap();
+        assert_eq!((amy.id, bob.id, amy.role), (1, 2, Role::Analyst));
+        assert!(matches!(store.create(&user("amy@example.com"), "x").await, Err(AppError::Conflict { field }) if field == "email"));
+        assert_eq!(store.get_by_email("bob@example.com").await.unwrap().map(|u| u.id), Some(bob.id));
+
+        let rename = UpdateUser { name: Some("Amy Chen".to_string()), email: None, role: Some(Role::Operator) };
+        let updated = store.update(amy.id, &rename).await.unwrap().unwrap();
+        assert_eq!((updated.name.as_str(), updated.email.as_str(), updated.role), ("Amy Chen", "amy@example.com", Role::Operator));
+        let steal = UpdateUser { name: None, email: Some("bob@example.com".to_string()), role: None };
+        assert!(matches!(store.update(amy.id, &steal).await, Err(AppError::Conflict { field }) if field == "email"));
+        assert!(store.update(99, &rename).await.unwrap().is_none());
+
+        assert_eq!(store.get_password_hash(bob.id).await.unwrap().as_deref(), Some("hash-b"));
+        assert!(store.delete(bob.id).await.unwrap());
+        assert!(!store.delete(bob.id).await.unwrap());
+        assert!(store.get_by_id(bob.id).await.unwrap().is_none());
+        assert!(store.get_password_hash(bob.id).await.unwrap().is_none());
+        assert!(!store.set_password_hash(bob.id, "hash").await.unwrap());
+    }
+
+    // (stock_date, symbol) 唯一；沒有指定 id 的查詢、更新、刪除都作用在 end 最晚（相同時 id 最大）的一筆
+    #[actix_web::test]
+    async fn disposition_store_contract() {
+        let store = InMemoryDispositionStore::new();
+        let first = store.create(&disposition("114/10/17", "2330", "114/10/20", "114/10/31")).await.unwrap();
+        let second = store.create(&disposition("114/10/24", "2330", "114/10/27", "114/10/28")).await.unwrap();
+        let open = store.create(&disposition("114/10/27", "2330", "", "")).await.unwrap();
+        assert_eq!((first.id, second.id, open.id), (1, 2, 3));
+        assert!(matches!(
+            store.create(&disposition("114/10/17", "2330", "", "")).await,
+            Err(AppError::Conflict { field }) if field == "stock_date,symbol"
+        ));
+        store.create(&disposition("114/10/17", "2317", "", "")).await.unwrap();
+
+        // 起訖日未定（NULL）的一筆排在最後，不會被當成最新
+        assert_eq!(store.get_by_symbol(2330).await.unwrap().map(|d| d.id), Some(first.id));
+        assert!(store.get_by_symbol(1101).await.unwrap().is_none());
+        let history: Vec<u64> = store.get_history(2330).await.unwrap().iter().map(|d| d.id).collect();
+        assert_eq!(history, vec![open.id, second.id, first.id]);
+
+        let updated = store.update(2330, &period("", "114/11/03")).await.unwrap().unwrap();
+        assert_eq!((updated.id, updated.end.map(|d| d.iso())), (first.id, Some("2025-11-03".to_string())));
+        // 只改一端時以合併後的期間檢查
+        assert!(matches!(store.update(2330, &period("114/11/10", "")).await, Err(AppError::Validation { .. })));
+        assert!(store.update(1101, &period("114/11/10", "")).await.unwrap().is_none());
+        assert!(store.update_by_id(2317, first.id, &period("114/11/01", "")).await.unwrap().is_none());
+
+        assert_eq!(store.delete(2330).await.unwrap().map(|d| d.id), Some(first.id));
+        assert_eq!(store.get_by_symbol(2330).await.unwrap().map(|d| d.id), Some(second.id));
+        assert!(store.delete_by_id(2330, first.id).await.unwrap().is_none());
+        assert_eq!(store.delete_by_id(2330, open.id).await.unwrap().map(|d| d.id), Some(open.id));
+
+        // 每次成功的寫入都留下一筆事件，失敗的不會
+        let kinds: Vec<&str> = store.events_after(0, 100).await.unwrap().iter().map(|e| e.event.event.as_str()).collect();
+        assert_eq!(kinds, [
+            "disposition.created", "disposition.created", "disposition.created", "disposition.created",
+            "disposition.updated", "disposition.deleted", "disposition.deleted",
+        ]);
+        assert_eq!(store.last_event_id().await.unwrap(), 7);
+    }
+}
@@ -0,0 +1,289 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+// 全域的指標：db.rs 與 middleware 直接記錄，GET /metrics 輸出 Prometheus 文字格式
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+// 秒，涵蓋一般 API 到匯出等較慢的請求
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+// 沒有對應路由（404）的請求合併成一個 label，避免任意路徑讓時間序列無限增加
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    // 各 bucket 自己的次數，輸出時再累加
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, self.count);
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), self.count);
+    }
+}
+
+type HttpKey = (String, String, u16);
+
+#[derive(Default)]
+pub struct Metrics {
+    // (method, route, status)
+    http: Mutex<BTreeMap<HttpKey, Histogram>>,
+    // op 例如 user.list
+    queries: Mutex<BTreeMap<&'static str, Histogram>>,
+    query_errors: Mutex<BTreeMap<&'static str, u64>>,
+    pool_wait: Mutex<Histogram>,
+    pool_acquire_failures: AtomicU64,
+    pool_in_use: AtomicI64,
+    pool_waiting: AtomicI64,
+    // 0 代表沒有使用 MySQL（記憶體儲存），不輸出連接池指標
+    pool_max: AtomicUsize,
+}
+
+// 進行中的數量：建立時 +1，drop 時 -1，錯誤或 panic 時也會扣回
+pub struct GaugeGuard(&'static AtomicI64);
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let key = (method_label(method).to_string(), route.to_string(), status);
+        self.http.lock().unwrap().entry(key).or_default().observe(elapsed);
+    }
+
+    pub fn set_pool_max(&self, max: usize) {
+        self.pool_max.store(max, Ordering::Relaxed);
+    }
+
+    // 等待 permit 與取得連線的期間
+    pub fn pool_waiting(&'static self) -> GaugeGuard {
+        self.pool_waiting.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(&self.pool_waiting)
+    }
+
+    // 持有連線執行 SQL 的期間
+    pub fn pool_in_use(&'static self) -> GaugeGuard {
+        self.pool_in_use.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(&self.pool_in_use)
+    }
+
+    pub fn observe_pool_wait(&self, wait: Duration) {
+        self.pool_wait.lock().unwrap().observe(wait);
+    }
+
+    pub fn pool_acquire_failed(&self) {
+        self.pool_acquire_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_query(&self, op: &'static str, elapsed: Duration, failed: bool) {
+        self.queries.lock().unwrap().entry(op).or_default().observe(elapsed);
+        if failed {
+            *self.query_errors.lock().unwrap().entry(op).or_default() += 1;
+        }
+    }
+
+    // Prometheus text exposition format；gauges 為當下計算的業務指標，例如各市場處置中的檔數
+    pub fn render(&self, gauges: &[Gauge]) -> String {
+        let mut out = String::new();
+
+        let http = self.http.lock().unwrap().clone();
+        header(&mut out, "http_requests_total", "counter", "HTTP 請求數");
+        for ((method, route, status), histogram) in &http {
+            let _ = writeln!(out, "http_requests_total{{{}}} {}", http_labels(method, route, *status), histogram.count);
+        }
+        header(&mut out, "http_request_duration_seconds", "histogram", "HTTP 請求處理時間（串流回應只計算到開始回應）");
+        for ((method, route, status), histogram) in &http {
+            histogram.write(&mut out, "http_request_duration_seconds", &http_labels(method, route, *status));
+        }
+
+        let pool_max = self.pool_max.load(Ordering::Relaxed);
+        if pool_max > 0 {
+            header(&mut out, "db_pool_max_connections", "gauge", "連接池上限（database.pool_max）");
+            let _ = writeln!(out, "db_pool_max_connections {}", pool_max);
+            header(&mut out, "db_pool_connections_in_use", "gauge", "正在執行 SQL 的連線數");
+            let _ = writeln!(out, "db_pool_connections_in_use {}", self.pool_in_use.load(Ordering::Relaxed));
+            header(&mut out, "db_pool_waiting", "gauge", "等待取得連線的查詢數");
+            let _ = writeln!(out, "db_pool_waiting {}", self.pool_waiting.load(Ordering::Relaxed));
+            header(&mut out, "db_pool_wait_seconds", "histogram", "從排隊到取得連線的時間");
+            self.pool_wait.lock().unwrap().write(&mut out, "db_pool_wait_seconds", "");
+            header(&mut out, "db_pool_acquire_failures_total", "counter", "取得連線逾時或失敗的次數");
+            let _ = writeln!(out, "db_pool_acquire_failures_total {}", self.pool_acquire_failures.load(Ordering::Relaxed));
+        }
+
+        let queries = self.queries.lock().unwrap().clone();
+        header(&mut out, "db_query_duration_seconds", "histogram", "資料庫操作的執行時間");
+        for (op, histogram) in &queries {
+            histogram.write(&mut out, "db_query_duration_seconds", &format!("op=\"{}\"", escape(op)));
+        }
+        header(&mut out, "db_query_errors_total", "counter", "資料庫錯誤（不含重複資料等業務錯誤）");
+        for (op, count) in self.query_errors.lock().unwrap().iter() {
+            let _ = writeln!(out, "db_query_errors_total{{op=\"{}\"}} {}", escape(op), count);
+        }
+
+        let mut last = "";
+        for gauge in gauges {
+            if gauge.name != last {
+                header(&mut out, gauge.name, "gauge", gauge.help);
+                last = gauge.name;
+            }
+            let labels: Vec<String> = gauge.labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape(v))).collect();
+            let _ = writeln!(out, "{}{} {}", gauge.name, braces(&labels.join(",")), gauge.value);
+        }
+        out
+    }
+}
+
+// 同名的 gauge 需相鄰
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// 自訂的 HTTP 方法一律記為 OTHER，避免任意方法名稱讓時間序列無限增加
+const HTTP_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH"];
+
+fn method_label(method: &str) -> &'static str {
+    HTTP_METHODS.iter().find(|m| **m == method).copied().unwrap_or("OTHER")
+}
+
+fn http_labels(method: &str, route: &str, status: u16) -> String {
+    format!("method=\"{}\",route=\"{}\",status=\"{}\"", escape(method), escape(route), status)
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// 全域 middleware：依路由樣板（例如 /user/{id}）、方法與狀態碼記錄請求數與處理時間
+pub struct HttpMetrics;
+
+impl<S, B> Transform<S, ServiceRequest> for HttpMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = HttpMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(HttpMetricsMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct HttpMetricsMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for HttpMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let method = req.method().to_string();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let res = service.call(req).await;
+            let (route, status) = match &res {
+                Ok(res) => (res.request().match_pattern(), res.status()),
+                Err(e) => (None, e.as_response_error().status_code()),
+            };
+            let route = route.unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+            METRICS.observe_request(&method, &route, status.as_u16(), started.elapsed());
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // bucket 為累計值，label 依路由樣板分組
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/user/{id}", 200, Duration::from_millis(3));
+        metrics.observe_request("GET", "/user/{id}", 200, Duration::from_millis(300));
+        metrics.observe_request("PURGE", UNMATCHED_ROUTE, 404, Duration::from_millis(1));
+        metrics.observe_request("X-SCAN", UNMATCHED_ROUTE, 404, Duration::from_millis(1));
+        metrics.observe_query("user.find", Duration::from_millis(20), true);
+        metrics.set_pool_max(10);
+        let gauges = ["TWSE", "TPEx"].iter().zip([3.0, 0.0]).map(|(market, value)| Gauge {
+            name: "disposition_active",
+            help: "處置中的股票數",
+            labels: vec![("market", market.to_string())],
+            value,
+        });
+
+        let text = metrics.render(&gauges.collect::<Vec<_>>());
+        let lines: Vec<&str> = text.lines().collect();
+        for expected in [
+            r#"http_requests_total{method="GET",route="/user/{id}",status="200"} 2"#,
+            r#"http_request_duration_seconds_bucket{method="GET",route="/user/{id}",status="200",le="0.005"} 1"#,
+            r#"http_request_duration_seconds_bucket{method="GET",route="/user/{id}",status="200",le="0.25"} 1"#,
+            r#"http_request_duration_seconds_bucket{method="GET",route="/user/{id}",status="200",le="0.5"} 2"#,
+            r#"http_request_duration_seconds_count{method="GET",route="/user/{id}",status="200"} 2"#,
+            r#"http_requests_total{method="OTHER",route="unmatched",status="404"} 2"#,
+            r#"db_query_duration_seconds_bucket{op="user.find",le="+Inf"} 1"#,
+            r#"db_query_errors_total{op="user.find"} 1"#,
+            "db_pool_max_connections 10",
+            "db_pool_wait_seconds_count 0",
+            r#"disposition_active{market="TPEx"} 0"#,
+        ] {
+            assert!(lines.contains(&expected), "缺少 {}\n{}", expected, text);
+        }
+        assert_eq!(lines.iter().filter(|l| **l == "# TYPE disposition_active gauge").count(), 1);
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use mysql::{prelude::*, PooledConn, Value};
+use chrono::NaiveDateTime;
+use crate::repository::parse_datetime;
+
+// 內嵌的 schema 版本，版本號必須遞增；新增 migration 時在最後面加一筆
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../migrations/", $name, ".up.sql")),
+            down: include_str!(concat!("../migrations/", $name, ".down.sql")),
+        }
+    };
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_create_user"),
+    migration!(2, "0002_create_s_disposition"),
+    migration!(3, "0003_add_s_disposition_id"),
+    migration!(4, "0004_add_user_password_hash"),
+    migration!(5, "0005_create_password_reset"),
+    migration!(6, "0006_add_user_role"),
+    migration!(7, "0007_create_api_key"),
+    migration!(8, "0008_create_api_key_use"),
+    migration!(9, "0009_create_webhook"),
+    migration!(10, "0010_create_webhook_delivery"),
+    migration!(11, "0011_create_disposition_event"),
+];
+
+pub struct MigrationStatus {
+    pub version: u32,
+    pub name: &'static str,
+    pub applied: bool,
+    pub applied_at: Option<NaiveDateTime>,
+}
+
+fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+fn ensure_table(conn: &mut PooledConn) -> Result<()> {
+    conn.query_drop(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INT UNSIGNED NOT NULL PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            applied_at TIMESTAMP NULL DEFAULT CURRENT_TIMESTAMP
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4",
+    )?;
+    Ok(())
+}
+
+fn applied_versions(conn: &mut PooledConn) -> Result<Vec<(u32, Value)>> {
+    let rows: Vec<(u32, Value)> = conn.query("SELECT version, applied_at FROM schema_migrations ORDER BY version")?;
+    Ok(rows)
+}
+
+// 資料庫已套用比程式更新的版本時拒絕繼續，避免舊程式操作新 schema
+fn check_not_ahead(applied: &[(u32, Value)]) -> Result<()> {
+    let latest = latest_version();
+    if let Some((version, _)) = applied.iter().find(|(v, _)| *v > latest) {
+        anyhow::bail!(
+            "資料庫 schema 版本 {} 比程式支援的最新版本 {} 還新，請更新程式後再啟動",
+            version, latest
+        );
+    }
+    Ok(())
+}
+
+// 多個 instance 同時啟動時，只有拿到鎖的那一個檢查並套用 migration，其他的等它完成後再檢查
+const LOCK_NAME: &str = "rust_crud_api.schema_migrations";
+const LOCK_TIMEOUT_SECS: u32 = 60;
+
+// GET_LOCK 是 session 層級的鎖，不論成功與否都要在同一條連線上釋放，避免連線回到 pool 後還持有鎖
+fn with_lock<T>(conn: &mut PooledConn, f: impl FnOnce(&mut PooledConn) -> Result<T>) -> Result<T> {
+    let locked: Option<Option<i64>> = conn.exec_first("SELECT GET_LOCK(?, ?)", (LOCK_NAME, LOCK_TIMEOUT_SECS))?;
+    if locked.flatten() != Some(1) {
+        anyhow::bail!("等待 migration 鎖逾時（{} 秒），可能有其他 instance 正在套用 migration", LOCK_TIMEOUT_SECS);
+    }
+    let result = f(conn);
+    conn.exec_drop("SELECT RELEASE_LOCK(?)", (LOCK_NAME,))?;
+    result
+}
+
+// 依序套用尚未執行的 migration，回傳這次套用的版本
+pub fn run_pending(conn: &mut PooledConn) -> Result<Vec<u32>> {
+    with_lock(conn, apply_pending)
+}
+
+fn apply_pending(conn: &mut PooledConn) -> Result<Vec<u32>> {
+    ensure_table(conn)?;
+    let applied = applied_versions(conn)?;
+    check_not_ahead(&applied)?;
+
+    let mut done = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.iter().any(|(v, _)| *v == migration.version) {
+            continue;
+        }
+        // MySQL 的 DDL 會自動 commit，所以每一步成功後立即記錄版本
+        conn.query_drop(migration.up)
+            .map_err(|e| anyhow::anyhow!("套用 migration {} 失敗: {}", migration.name, e))?;
+        conn.exec_drop(
+            "INSERT INTO schema_migrations (version, name) VALUES (?, ?)",
+            (migration.version, migration.name),
+        )?;
+        tracing::info!(version = migration.version, name = migration.name, "已套用 migration");
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+// 由新到舊回滾 steps 個已套用的版本
+pub fn rollback(conn: &mut PooledConn, steps: usize) -> Result<Vec<u32>> {
+    with_lock(conn, |conn| revert(conn, steps))
+}
+
+fn revert(conn: &mut PooledConn, steps: usize) -> Result<Vec<u32>> {
+    ensure_table(conn)?;
+    let applied = applied_versions(conn)?;
+    check_not_ahead(&applied)?;
+
+    let mut done = Vec::new();
+    for (version, _) in applied.iter().rev().take(steps) {
+        let migration = MIGRATIONS.iter()
+            .find(|m| m.version == *version)
+            .ok_or_else(|| anyhow::anyhow!("找不到版本 {} 的 migration", version))?;
+        conn.query_drop(migration.down)
+            .map_err(|e| anyhow::anyhow!("回滾 migration {} 失敗: {}", migration.name, e))?;
+        conn.exec_drop("DELETE FROM schema_migrations WHERE version = ?", (migration.version,))?;
+        tracing::info!(version = migration.version, name = migration.name, "已回滾 migration");
+        done.push(migration.version);
+    }
+    Ok(done)
+}
+
+pub fn status(conn: &mut PooledConn) -> Result<Vec<MigrationStatus>> {
+    ensure_table(conn)?;
+    let applied = applied_versions(conn)?;
+    check_not_ahead(&applied)?;
+
+    Ok(MIGRATIONS.iter().map(|m| {
+        let row = applied.iter().find(|(v, _)| *v == m.version);
+        let applied_at = row.and_then(|(_, at)| parse_datetime(at.clone()));
+        MigrationStatus { version: m.version, name: m.name, applied: row.is_some(), applied_at }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(versions: &[u32]) -> Vec<(u32, Value)> {
+        versions.iter().map(|v| (*v, Value::NULL)).collect()
+    }
+
+    #[test]
+    fn versions_increase() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(MIGRATIONS.first().map(|m| m.version), Some(1));
+    }
+
+    #[test]
+    fn rejects_schema_ahead_of_binary() {
+        let latest = latest_version();
+        assert!(check_not_ahead(&applied(&[])).is_ok());
+        assert!(check_not_ahead(&applied(&[1, 2, latest])).is_ok());
+
+        let err = check_not_ahead(&applied(&[1, 2, latest, latest + 1])).unwrap_err().to_string();
+        assert!(err.contains(&format!("版本 {} ", latest + 1)) && err.contains(&format!("最新版本 {} ", latest)), "{}", err);
+    }
+}
@@ -1,60 +1,858 @@
-use serde::{Deserialize, Serialize};
-use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use validator::{Validate, ValidationError, ValidationErrors};
+use crate::error::{AppError, AppResult};
+
+// 允許的市場別：上市、上櫃
+pub const MARKETS: &[&str] = &["TWSE", "TPEx"];
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+// 權限由低到高，較高的角色涵蓋較低角色的權限
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // 只能查詢處置股
+    #[default]
+    Analyst,
+    // 可以新增、修改、刪除處置股
+    Operator,
+    // 可以管理使用者
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Analyst => "analyst",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        match value {
+            "analyst" => Some(Role::Analyst),
+            "operator" => Some(Role::Operator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
     pub id: u32,
     pub name: String,
     pub email: String,
+    pub role: Role,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct CreateUser {
+    #[validate(length(min = 1, max = 100, message = "名稱長度需介於 1 到 100 個字"))]
     pub name: String,
+    #[validate(email(message = "電子郵件格式錯誤"))]
     pub email: String,
+    // 只用來計算雜湊，不會存明碼也不會回傳
+    #[serde(skip_serializing)]
+    #[validate(length(min = 8, max = 128, message = "密碼長度需介於 8 到 128 個字"))]
+    pub password: String,
+    // 未指定時為 analyst
+    pub role: Option<Role>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct UpdateUser {
+    #[validate(length(min = 1, max = 100, message = "名稱長度需介於 1 到 100 個字"))]
     pub name: Option<String>,
+    #[validate(email(message = "電子郵件格式錯誤"))]
     pub email: Option<String>,
+    pub role: Option<Role>,
+}
+
+// POST /auth/login
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct LoginRequest {
+    #[validate(email(message = "電子郵件格式錯誤"))]
+    pub email: String,
+    pub password: String,
+}
+
+// POST /user/{id}/password
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct ChangePassword {
+    pub old_password: String,
+    #[validate(length(min = 8, max = 128, message = "密碼長度需介於 8 到 128 個字"))]
+    pub new_password: String,
+}
+
+// POST /auth/password-reset
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(email(message = "電子郵件格式錯誤"))]
+    pub email: String,
+}
+
+// POST /auth/password-reset/confirm
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    #[validate(length(min = 8, max = 128, message = "密碼長度需介於 8 到 128 個字"))]
+    pub new_password: String,
+}
+
+// POST /auth/refresh
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+// 登入、換發 token 的回應
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: &'static str,
+    // access token 的有效秒數
+    pub expires_in: u64,
+    pub user: User,
+}
+
+// API key 可使用的範圍
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "disposition:read")]
+    DispositionRead,
+    #[serde(rename = "disposition:write")]
+    DispositionWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::DispositionRead => "disposition:read",
+            Scope::DispositionWrite => "disposition:write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Scope> {
+        match value {
+            "disposition:read" => Some(Scope::DispositionRead),
+            "disposition:write" => Some(Scope::DispositionWrite),
+            _ => None,
+        }
+    }
+}
+
+// 給機器用的 API key，只保存 SHA-256，明碼只在建立時回傳一次
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    pub id: u64,
+    pub user_id: u32,
+    pub name: String,
+    // 明碼的前幾個字元，方便辨認是哪一把
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct CreateApiKey {
+    #[validate(length(min = 1, max = 100, message = "名稱長度需介於 1 到 100 個字"))]
+    pub name: String,
+    #[validate(length(min = 1, message = "至少需要一個 scope"))]
+    pub scopes: Vec<Scope>,
+    // 省略時不會過期
+    #[validate(range(min = 1, max = 3650, message = "有效天數需介於 1 到 3650"))]
+    pub expires_in_days: Option<u32>,
+}
+
+// POST /api-keys 的回應，key 只會出現這一次
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+// 每次以 API key 呼叫 API 的紀錄
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyUse {
+    pub id: u64,
+    pub api_key_id: u64,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub ip: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+// GET/PUT /admin/log-level
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevel {
+    pub level: String,
+}
+
+// 處置股異動事件，webhook 的 X-Webhook-Event 與 payload 的 event 使用 as_str
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DispositionEventKind {
+    #[serde(rename = "disposition.created")]
+    Created,
+    #[serde(rename = "disposition.updated")]
+    Updated,
+    #[serde(rename = "disposition.deleted")]
+    Deleted,
+}
+
+impl DispositionEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DispositionEventKind::Created => "disposition.created",
+            DispositionEventKind::Updated => "disposition.updated",
+            DispositionEventKind::Deleted => "disposition.deleted",
+        }
+    }
+
+    // SSE 的 event 名稱
+    pub fn action(&self) -> &'static str {
+        match self {
+            DispositionEventKind::Created => "created",
+            DispositionEventKind::Updated => "updated",
+            DispositionEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DispositionEvent {
+    pub event: DispositionEventKind,
+    pub occurred_at: NaiveDateTime,
+    // 刪除事件為刪除前的資料
+    pub data: Disposition,
+}
+
+impl DispositionEvent {
+    pub fn new(event: DispositionEventKind, data: Disposition) -> Self {
+        Self { event, occurred_at: chrono::Local::now().naive_local(), data }
+    }
+
+    // 寫入事件紀錄與 webhook payload 的 JSON；日期一律是 ISO，不受該次請求的 date_format 影響
+    pub fn payload(&self) -> AppResult<String> {
+        DATE_FORMAT.sync_scope(DateFormat::Iso, || serde_json::to_string(self))
+            .map_err(|e| AppError::Internal(format!("無法序列化事件: {}", e)))
+    }
+}
+
+// 跨過處置期間的起訖日：起日當天進入處置，迄日隔天解除處置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryKind {
+    Entered,
+    Left,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundaryAlert {
+    pub kind: BoundaryKind,
+    // 跨過邊界的那一天（台北時間）
+    pub date: TwDate,
+    pub data: Disposition,
+}
+
+// 接收處置股異動通知的端點；secret 只在建立時回傳一次，之後僅用於簽章
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub id: u64,
+    pub url: String,
+    pub description: String,
+    pub active: bool,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+fn validate_webhook_url(url: &str) -> Result<(), ValidationError> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(ValidationError::new("scheme").with_message("網址必須以 http:// 或 https:// 開頭".into()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct CreateWebhook {
+    #[validate(url(message = "網址格式錯誤"), length(max = 500, message = "網址最多 500 個字"), custom(function = "validate_webhook_url"))]
+    pub url: String,
+    #[serde(default)]
+    #[validate(length(max = 255, message = "說明最多 255 個字"))]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct UpdateWebhook {
+    #[validate(url(message = "網址格式錯誤"), length(max = 500, message = "網址最多 500 個字"), custom(function = "validate_webhook_url"))]
+    pub url: Option<String>,
+    #[validate(length(max = 255, message = "說明最多 255 個字"))]
+    pub description: Option<String>,
+    // 停用後不再產生新的投遞，已排入的仍會重試
+    pub active: Option<bool>,
+}
+
+// POST /webhooks 的回應，secret 只會出現這一次
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    pub secret: String,
+    #[serde(flatten)]
+    pub webhook: Webhook,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    // 等待投遞或重試
+    Pending,
+    Delivered,
+    // 重試次數用完
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<DeliveryStatus> {
+        match value {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+// 一筆事件對一個 webhook 的投遞；同時是重試佇列與投遞紀錄
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub id: u64,
+    pub webhook_id: u64,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    // 最後一次嘗試的 HTTP 狀態碼，連線失敗時為 None
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+// 到期待送的投遞，帶著簽章需要的 url 與 secret
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    pub id: u64,
+    pub webhook_id: u64,
+    pub url: String,
+    pub secret: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: u32,
+}
+
+// 一次投遞嘗試的結果；retry_in 為 None 時表示不再重試
+#[derive(Debug, Clone)]
+pub struct DeliveryAttempt {
+    pub delivered: bool,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub retry_in: Option<u64>,
+}
+
+impl DeliveryAttempt {
+    pub fn status(&self) -> DeliveryStatus {
+        match (self.delivered, self.retry_in) {
+            (true, _) => DeliveryStatus::Delivered,
+            (false, Some(_)) => DeliveryStatus::Pending,
+            (false, None) => DeliveryStatus::Failed,
+        }
+    }
+}
+
+// 回應中日期的格式，由 `?date_format=iso|roc` 決定（見 date_format.rs）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    #[default]
+    Iso,
+    Roc,
+}
+
+impl DateFormat {
+    pub fn parse(value: &str) -> Option<DateFormat> {
+        match value {
+            "iso" => Some(DateFormat::Iso),
+            "roc" => Some(DateFormat::Roc),
+            _ => None,
+        }
+    }
+}
+
+tokio::task_local! {
+    // 本次請求的日期格式；不在請求範圍內（例如 CLI）時為 ISO
+    pub static DATE_FORMAT: DateFormat;
+}
+
+// 民國日期：114/03/05、114.03.05、114-03-05、114年03月05日，或不含分隔的 1140305
+pub fn parse_roc_date(value: &str) -> Option<NaiveDate> {
+    let parts: Vec<&str> = value.trim()
+        .split(['/', '.', '-', '年', '月', '日'])
+        .filter(|part| !part.is_empty())
+        .collect();
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] => (*year, *month, *day),
+        [compact] if (6..=7).contains(&compact.len()) && compact.bytes().all(|b| b.is_ascii_digit()) => {
+            let split = compact.len() - 4;
+            (&compact[..split], &compact[split..split + 2], &compact[split + 2..])
+        }
+        _ => return None,
+    };
+    // 四位數年份是西元，不是民國
+    if year.len() > 3 {
+        return None;
+    }
+    let year: i32 = year.parse().ok()?;
+    if year < 1 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year + 1911, month.parse().ok()?, day.parse().ok()?)
+}
+
+// 處置股的日期：輸入接受 ISO（2025-10-17）或民國（114/10/17），輸出依本次請求的 DATE_FORMAT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TwDate(pub NaiveDate);
+
+impl TwDate {
+    pub fn parse(value: &str) -> Option<TwDate> {
+        let value = value.trim();
+        parse_roc_date(value)
+            .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok())
+            .map(TwDate)
+    }
+
+    pub fn iso(&self) -> String {
+        self.0.format("%Y-%m-%d").to_string()
+    }
+
+    // 民國元年以前沒有民國日期，維持 ISO
+    pub fn roc(&self) -> String {
+        let year = self.0.year() - 1911;
+        if year < 1 {
+            return self.iso();
+        }
+        format!("{}/{:02}/{:02}", year, self.0.month(), self.0.day())
+    }
+
+    pub fn format(&self, format: DateFormat) -> String {
+        match format {
+            DateFormat::Iso => self.iso(),
+            DateFormat::Roc => self.roc(),
+        }
+    }
+}
+
+impl From<NaiveDate> for TwDate {
+    fn from(date: NaiveDate) -> Self {
+        TwDate(date)
+    }
+}
+
+// 顯示（含回應訊息）與序列化都跟著本次請求的格式
+impl std::fmt::Display for TwDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = DATE_FORMAT.try_with(|format| *format).unwrap_or_default();
+        f.write_str(&self.format(format))
+    }
+}
+
+impl Serialize for TwDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TwDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        TwDate::parse(&raw).ok_or_else(|| {
+            serde::de::Error::custom(format!("無效的日期 '{}'，需為 YYYY-MM-DD 或民國 YYY/MM/DD", raw))
+        })
+    }
 }
 
 // Stocks 資料庫的 Disposition 模型
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Disposition {
-    pub stock_date: Option<NaiveDate>,
+    pub id: u64,
+    pub stock_date: Option<TwDate>,
     pub market: String,
     pub symbol: i32,
     pub name: String,
-    pub start: Option<NaiveDate>,
-    pub end: Option<NaiveDate>,
+    pub start: Option<TwDate>,
+    pub end: Option<TwDate>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Validate)]
+#[validate(schema(function = "validate_create_period"))]
 pub struct CreateDisposition {
-    pub stock_date: String,
+    pub stock_date: TwDate,
+    #[validate(custom(function = "validate_market"))]
     pub market: String,
+    #[validate(custom(function = "validate_symbol"))]
     pub symbol: String,
+    #[validate(length(min = 1, max = 50, message = "名稱長度需介於 1 到 50 個字"))]
     pub name: String,
+    // 處置期間，公告時通常已知；未提供時之後再用 PUT 補上
+    pub start: Option<TwDate>,
+    pub end: Option<TwDate>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UpdateDisposition {
+    pub start: Option<TwDate>,
+    pub end: Option<TwDate>,
+}
+
+// 請求 body 中的處置股（含批次匯入的每一列）：日期先以字串接收，格式錯誤與其他欄位一樣由 Validate 回報為該欄位的 422，
+// 通過後以 into_disposition 轉成 CreateDisposition
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[validate(schema(function = "validate_create_request_period"))]
+pub struct CreateDispositionRequest {
+    #[validate(custom(function = "validate_date"))]
+    pub stock_date: String,
+    #[validate(custom(function = "validate_market"))]
+    pub market: String,
+    #[validate(custom(function = "validate_symbol"))]
+    pub symbol: String,
+    #[validate(length(min = 1, max = 50, message = "名稱長度需介於 1 到 50 個字"))]
+    pub name: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
     pub start: Option<String>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_date"))]
     pub end: Option<String>,
 }
 
+impl CreateDispositionRequest {
+    pub fn into_disposition(self) -> Result<CreateDisposition, ValidationErrors> {
+        self.validate()?;
+        Ok(CreateDisposition {
+            stock_date: request_date("stock_date", &self.stock_date)?,
+            market: self.market,
+            symbol: self.symbol,
+            name: self.name,
+            start: self.start.as_deref().map(|value| request_date("start", value)).transpose()?,
+            end: self.end.as_deref().map(|value| request_date("end", value)).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate)]
+#[validate(schema(function = "validate_update_request_period"))]
+pub struct UpdateDispositionRequest {
+    #[validate(custom(function = "validate_date"))]
+    pub start: Option<String>,
+    #[validate(custom(function = "validate_date"))]
+    pub end: Option<String>,
+}
+
+impl UpdateDispositionRequest {
+    pub fn into_update(self) -> Result<UpdateDisposition, ValidationErrors> {
+        self.validate()?;
+        Ok(UpdateDisposition {
+            start: self.start.as_deref().map(|value| request_date("start", value)).transpose()?,
+            end: self.end.as_deref().map(|value| request_date("end", value)).transpose()?,
+        })
+    }
+}
+
+impl UpdateDisposition {
+    // 只更新起日或迄日時，與既有的期間合併後也不能起日晚於迄日
+    pub fn merged_period(&self, start: Option<TwDate>, end: Option<TwDate>) -> AppResult<(Option<TwDate>, Option<TwDate>)> {
+        let (start, end) = (self.start.or(start), self.end.or(end));
+        validate_period(start, end).map_err(|e| AppError::invalid("period", e.message.unwrap_or_default()))?;
+        Ok((start, end))
+    }
+}
+
+fn validation_error(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError::new(code).with_message(message.into())
+}
+
+fn validate_market(value: &str) -> Result<(), ValidationError> {
+    if MARKETS.contains(&value) {
+        Ok(())
+    } else {
+        Err(validation_error("market", "市場別需為 TWSE 或 TPEx"))
+    }
+}
+
+fn validate_symbol(value: &str) -> Result<(), ValidationError> {
+    let numeric = !value.is_empty() && value.len() <= 6 && value.bytes().all(|b| b.is_ascii_digit());
+    if numeric {
+        Ok(())
+    } else {
+        Err(validation_error("symbol", "股票代碼需為 1 到 6 位數字"))
+    }
+}
+
+fn invalid_date(value: &str) -> ValidationError {
+    ValidationError::new("date").with_message(format!("無效的日期 '{}'，需為 YYYY-MM-DD 或民國 YYY/MM/DD", value).into())
+}
+
+fn validate_date(value: &str) -> Result<(), ValidationError> {
+    TwDate::parse(value).map(|_| ()).ok_or_else(|| invalid_date(value))
+}
+
+// 已通過 validate_date，這裡的錯誤只是保險
+fn request_date(field: &'static str, value: &str) -> Result<TwDate, ValidationErrors> {
+    TwDate::parse(value).ok_or_else(|| {
+        let mut errors = ValidationErrors::new();
+        errors.add(field, invalid_date(value));
+        errors
+    })
+}
+
+fn validate_period(start: Option<TwDate>, end: Option<TwDate>) -> Result<(), ValidationError> {
+    match (start, end) {
+        (Some(start), Some(end)) if start > end => Err(validation_error("period", "處置起日不可晚於迄日")),
+        _ => Ok(()),
+    }
+}
+
+fn validate_create_period(value: &CreateDisposition) -> Result<(), ValidationError> {
+    validate_period(value.start, value.end)
+}
+
+// 日期格式錯誤已由欄位回報，這裡只比較兩個都能解析的日期
+fn validate_request_period(start: Option<&str>, end: Option<&str>) -> Result<(), ValidationError> {
+    validate_period(start.and_then(TwDate::parse), end.and_then(TwDate::parse))
+}
+
+fn validate_create_request_period(value: &CreateDispositionRequest) -> Result<(), ValidationError> {
+    validate_request_period(value.start.as_deref(), value.end.as_deref())
+}
+
+fn validate_update_request_period(value: &UpdateDispositionRequest) -> Result<(), ValidationError> {
+    validate_request_period(value.start.as_deref(), value.end.as_deref())
+}
+
+// 批次匯入遇到同一天、同代碼已存在時的處理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictMode {
+    // 更新既有資料的市場別、名稱，以及有提供的處置期間
+    Upsert,
+    // 保留既有資料，該列標記為 skipped
+    Skip,
+    // 整批取消，任何一列無效或重複都不寫入
+    #[default]
+    Fail,
+}
+
+impl ConflictMode {
+    pub fn parse(value: &str) -> Option<ConflictMode> {
+        match value {
+            "upsert" => Some(ConflictMode::Upsert),
+            "skip" => Some(ConflictMode::Skip),
+            "fail" => Some(ConflictMode::Fail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkImportQuery {
+    #[serde(default)]
+    pub on_conflict: ConflictMode,
+}
+
+// store 回報的單列結果，值為處置股 id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Created(u64),
+    Updated(u64),
+    Skipped(u64),
+    // upsert 時只提供起日或迄日，與既有的處置期間合併後起日晚於迄日；該列不寫入
+    InvalidPeriod(u64),
+    // 只在 fail 模式出現，且一定是最後一筆，此時整批已回滾
+    Conflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkRowStatus {
+    Created,
+    Updated,
+    Skipped,
+    Invalid,
+}
+
+// 批次匯入的逐列結果，row 從 1 開始（CSV 不含標題列）
+#[derive(Debug, Serialize)]
+pub struct BulkRowResult {
+    pub row: usize,
+    pub status: BulkRowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stock_date: Option<TwDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkImportReport {
+    pub on_conflict: ConflictMode,
+    pub total: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+    pub rows: Vec<BulkRowResult>,
+}
+
+// GET /disposition 的篩選條件
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DispositionFilter {
+    pub market: Option<String>,
+    pub symbol: Option<i32>,
+    // 名稱包含的字串
+    pub name: Option<String>,
+    pub stock_date_from: Option<TwDate>,
+    pub stock_date_to: Option<TwDate>,
+    // 處置期間 [start, end] 與 [period_from, period_to] 有重疊
+    pub period_from: Option<TwDate>,
+    pub period_to: Option<TwDate>,
+    #[serde(default, deserialize_with = "deserialize_sort")]
+    pub sort: Option<DispositionSort>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispositionSortField {
+    StockDate,
+    Market,
+    Symbol,
+    Name,
+    Start,
+    End,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DispositionSort {
+    pub field: DispositionSortField,
+    pub desc: bool,
+}
+
+// `sort=field:asc|desc`，方向可省略（預設 asc）
+fn deserialize_sort<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DispositionSort>, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    let (field, order) = raw.split_once(':').unwrap_or((raw.as_str(), "asc"));
+    let field = match field {
+        "stock_date" => DispositionSortField::StockDate,
+        "market" => DispositionSortField::Market,
+        "symbol" => DispositionSortField::Symbol,
+        "name" => DispositionSortField::Name,
+        "start" => DispositionSortField::Start,
+        "end" => DispositionSortField::End,
+        "created_at" => DispositionSortField::CreatedAt,
+        "updated_at" => DispositionSortField::UpdatedAt,
+        other => return Err(serde::de::Error::custom(format!("不支援的排序欄位: {}", other))),
+    };
+    let desc = match order {
+        "asc" => false,
+        "desc" => true,
+        other => return Err(serde::de::Error::custom(format!("不支援的排序方向: {}", other))),
+    };
+    Ok(Some(DispositionSort { field, desc }))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+// 匯出端點的 `?format=csv|xlsx`，篩選條件另外以 DispositionFilter 讀取
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+// `?as_of=YYYY-MM-DD`（或民國日期），未提供時為台北時間的今天
+#[derive(Debug, Deserialize)]
+pub struct AsOfQuery {
+    pub as_of: Option<TwDate>,
+}
+
+// GET /disposition/{symbol}/active 的回應
+#[derive(Debug, Serialize)]
+pub struct ActiveStatus {
+    pub symbol: i32,
+    pub as_of: TwDate,
+    pub active: bool,
+    pub until: Option<TwDate>,
+    pub disposition: Option<Disposition>,
+}
+
+// 分頁列表回應，放在 ApiResponse 的 data 中
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub limit: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+// 單一欄位的錯誤說明
+#[derive(Debug, Serialize, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
 // 通用 API 回應
 #[derive(Debug, Serialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
     pub message: String,
     pub data: Option<T>,
+    // 錯誤時的固定代碼，例如 NOT_FOUND、CONFLICT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
 }
 
 impl<T> ApiResponse<T> {
@@ -63,14 +861,81 @@ impl<T> ApiResponse<T> {
             success: true,
             message: message.to_string(),
             data: Some(data),
+            code: None,
+            errors: None,
         }
     }
 
-    pub fn error(message: &str) -> Self {
+    pub fn error(code: &str, message: &str) -> Self {
         Self {
             success: false,
             message: message.to_string(),
             data: None,
+            code: Some(code.to_string()),
+            errors: None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::field_errors;
+
+    fn fields(errors: ValidationErrors) -> Vec<String> {
+        field_errors(&errors).into_iter().map(|e| e.field).collect()
+    }
+
+    fn user(name: &str, email: &str) -> CreateUser {
+        CreateUser { name: name.to_string(), email: email.to_string(), password: "password".to_string(), role: None }
+    }
+
+    fn request(market: &str, symbol: &str, start: Option<&str>, end: Option<&str>) -> CreateDispositionRequest {
+        CreateDispositionRequest {
+            stock_date: "2025-10-17".to_string(),
+            market: market.to_string(),
+            symbol: symbol.to_string(),
+            name: "台積電".to_string(),
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn validates_user_email_and_name() {
+        assert!(user("Amy", "amy@example.com").validate().is_ok());
+        assert_eq!(fields(user("Amy", "not-an-email").validate().unwrap_err()), ["email"]);
+        assert_eq!(fields(user("", "amy@example.com").validate().unwrap_err()), ["name"]);
+        assert!(user(&"名".repeat(100), "amy@example.com").validate().is_ok());
+        assert_eq!(fields(user(&"名".repeat(101), "@").validate().unwrap_err()), ["email", "name"]);
+    }
+
+    #[test]
+    fn validates_disposition_fields() {
+        assert!(request("TWSE", "2330", None, None).into_disposition().is_ok());
+        assert!(request("TPEx", "006208", None, None).into_disposition().is_ok());
+        // 市場別大小寫需與交易所的寫法一致
+        assert_eq!(fields(request("twse", "2330", None, None).into_disposition().unwrap_err()), ["market"]);
+        assert_eq!(fields(request("NYSE", "2330", None, None).into_disposition().unwrap_err()), ["market"]);
+        for symbol in ["", "23a0", "1234567", "-233"] {
+            assert_eq!(fields(request("TWSE", symbol, None, None).into_disposition().unwrap_err()), ["symbol"], "{}", symbol);
+        }
+        assert_eq!(fields(request("TWSE", "2330", Some("2025-13-01"), None).into_disposition().unwrap_err()), ["start"]);
+    }
+
+    #[test]
+    fn validates_period_order() {
+        assert!(request("TWSE", "2330", Some("2025-10-20"), Some("2025-10-20")).into_disposition().is_ok());
+        // 民國年與西元年可以混用，比較的是日期
+        assert!(request("TWSE", "2330", Some("114/10/20"), Some("2025-10-31")).into_disposition().is_ok());
+        assert_eq!(fields(request("TWSE", "2330", Some("2025-10-31"), Some("2025-10-20")).into_disposition().unwrap_err()), ["__all__"]);
+
+        let update = |start: Option<&str>, end: Option<&str>| UpdateDispositionRequest { start: start.map(str::to_string), end: end.map(str::to_string) }.into_update();
+        assert_eq!(fields(update(Some("2025-10-31"), Some("2025-10-20")).unwrap_err()), ["__all__"]);
+        let one_sided = update(Some("2025-11-01"), None).unwrap();
+        assert!(one_sided.merged_period(TwDate::parse("2025-10-20"), TwDate::parse("2025-11-01")).is_ok());
+        assert!(matches!(
+            one_sided.merged_period(TwDate::parse("2025-10-20"), TwDate::parse("2025-10-31")),
+            Err(AppError::Validation { errors }) if errors[0].field == "period"
+        ));
+    }
+}
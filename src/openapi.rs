@@ -0,0 +1,758 @@
+use serde_json::{json, Map, Value};
+use std::sync::LazyLock;
+use crate::logging::LOG_LEVELS;
+use crate::models::{MARKETS, MIN_PASSWORD_LEN};
+use crate::routes::{Permission, RouteSpec, QUERY_KEY_PATHS, ROUTES};
+
+// 文件內容：路徑、方法與權限取自 routes::ROUTES，每個 handler 的說明、body 與回應型別寫在 OPERATIONS。
+// 新增路由時若沒有補上說明，openapi 的測試會失敗
+#[derive(Clone, Copy)]
+enum Body {
+    None,
+    // components.schemas 中的型別
+    Schema(&'static str),
+    List(&'static str),
+    Page(&'static str),
+    // JSON 基本型別，例如刪除成功時的 true
+    Primitive(&'static str),
+    // 批次匯入：CreateDisposition 的 JSON 陣列或 CSV
+    Bulk,
+    // 非 JSON 的內容，例如 CSV、行事曆
+    Raw(&'static [&'static str]),
+}
+
+#[derive(Clone, Copy)]
+enum Query {
+    Page,
+    Filter,
+    AsOf,
+    Export,
+    OnConflict,
+}
+
+struct Operation {
+    handler: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    status: u16,
+    request: Body,
+    response: Body,
+    query: &'static [Query],
+}
+
+macro_rules! operations {
+    ($( $handler:ident => $tag:literal, $status:literal, $summary:literal, $request:expr, $response:expr, $query:expr; )*) => {
+        const OPERATIONS: &[Operation] = &[
+            $( Operation { handler: stringify!($handler), tag: $tag, summary: $summary, status: $status, request: $request, response: $response, query: $query }, )*
+        ];
+    };
+}
+
+use Body::{List, Page, Primitive, Raw, Schema};
+const NO_BODY: Body = Body::None;
+const CSV_OR_XLSX: &[&str] = &["text/csv", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"];
+
+operations! {
+    health_check                  => "系統",     200, "健康檢查", NO_BODY, Primitive("string"), &[];
+    get_openapi                   => "系統",     200, "OpenAPI 文件（本文件）", NO_BODY, Raw(&["application/json"]), &[];
+    get_docs                      => "系統",     200, "Swagger UI", NO_BODY, Raw(&["text/html"]), &[];
+    get_metrics                   => "系統",     200, "Prometheus 指標（設定 metrics.token 時需帶 Bearer token）", NO_BODY, Raw(&["text/plain"]), &[];
+    get_log_level                 => "系統",     200, "目前的 log level", NO_BODY, Schema("LogLevel"), &[];
+    update_log_level              => "系統",     200, "執行期間調整 log level（重啟後恢復設定檔的值）", Schema("LogLevel"), Schema("LogLevel"), &[];
+
+    login                         => "認證",     200, "登入，取得 access token 與 refresh token", Schema("LoginRequest"), Schema("TokenPair"), &[];
+    refresh_token                 => "認證",     200, "以 refresh token 換發新的一組 token", Schema("RefreshRequest"), Schema("TokenPair"), &[];
+    request_password_reset        => "認證",     202, "寄出重設密碼連結（不透露帳號是否存在）", Schema("PasswordResetRequest"), Primitive("boolean"), &[];
+    confirm_password_reset        => "認證",     200, "以重設連結的 token 設定新密碼", Schema("PasswordResetConfirm"), Primitive("boolean"), &[];
+
+    get_user                      => "使用者",   200, "使用者列表", NO_BODY, Page("User"), &[Query::Page];
+    export_users                  => "使用者",   200, "匯出使用者（CSV 或 Excel）", NO_BODY, Raw(CSV_OR_XLSX), &[Query::Export];
+    get_user_by_id                => "使用者",   200, "取得使用者", NO_BODY, Schema("User"), &[];
+    create_user                   => "使用者",   201, "建立使用者", Schema("CreateUser"), Schema("User"), &[];
+    update_user                   => "使用者",   200, "更新使用者", Schema("UpdateUser"), Schema("User"), &[];
+    delete_user                   => "使用者",   200, "刪除使用者", NO_BODY, Primitive("boolean"), &[];
+    change_password               => "使用者",   200, "變更密碼（本人或 admin）", Schema("ChangePassword"), Primitive("boolean"), &[];
+
+    create_api_key                => "API key",  201, "建立 API key，明碼只在回應中出現一次", Schema("CreateApiKey"), Schema("CreatedApiKey"), &[];
+    get_api_keys                  => "API key",  200, "列出自己的 API key", NO_BODY, List("ApiKey"), &[];
+    revoke_api_key                => "API key",  200, "撤銷 API key", NO_BODY, Schema("ApiKey"), &[];
+    get_api_key_uses              => "API key",  200, "最近 100 筆使用紀錄", NO_BODY, List("ApiKeyUse"), &[];
+
+    get_webhooks                  => "Webhook",  200, "Webhook 列表", NO_BODY, List("Webhook"), &[];
+    create_webhook                => "Webhook",  201, "建立 webhook，secret 只在回應中出現一次", Schema("CreateWebhook"), Schema("CreatedWebhook"), &[];
+    get_webhook                   => "Webhook",  200, "取得 webhook", NO_BODY, Schema("Webhook"), &[];
+    update_webhook                => "Webhook",  200, "更新或停用 webhook", Schema("UpdateWebhook"), Schema("Webhook"), &[];
+    delete_webhook                => "Webhook",  200, "刪除 webhook", NO_BODY, Primitive("boolean"), &[];
+    get_webhook_deliveries        => "Webhook",  200, "最近 100 筆投遞紀錄", NO_BODY, List("WebhookDelivery"), &[];
+
+    ws_connect                    => "即時",     101, "WebSocket 訂閱個股的處置異動與起訖提醒", NO_BODY, NO_BODY, &[];
+    stream_dispositions           => "即時",     200, "處置股異動的 Server-Sent Events，可帶 Last-Event-ID 補送", NO_BODY, Raw(&["text/event-stream"]), &[];
+
+    get_disposition               => "處置股",   200, "處置股列表（篩選、排序、分頁）", NO_BODY, Page("Disposition"), &[Query::Filter, Query::Page];
+    get_active_disposition        => "處置股",   200, "指定日期處置中的股票", NO_BODY, List("Disposition"), &[Query::AsOf];
+    export_dispositions           => "處置股",   200, "匯出處置股（CSV 或 Excel），篩選條件同列表", NO_BODY, Raw(CSV_OR_XLSX), &[Query::Export, Query::Filter];
+    get_disposition_calendar      => "處置股",   200, "處置期間的 iCalendar 訂閱", NO_BODY, Raw(&["text/calendar"]), &[Query::Filter];
+    get_disposition_by_symbol     => "處置股",   200, "個股最近一筆處置", NO_BODY, Schema("Disposition"), &[];
+    get_disposition_active_status => "處置股",   200, "個股在指定日期是否處置中", NO_BODY, Schema("ActiveStatus"), &[Query::AsOf];
+    get_disposition_history       => "處置股",   200, "個股的處置歷史（新到舊）", NO_BODY, List("Disposition"), &[];
+    get_disposition_period        => "處置股",   200, "取得單一處置期間", NO_BODY, Schema("Disposition"), &[];
+    create_disposition            => "處置股",   201, "新增處置股", Schema("CreateDisposition"), Schema("Disposition"), &[];
+    import_dispositions           => "處置股",   200, "批次匯入（JSON 陣列或 CSV）", Body::Bulk, Schema("BulkImportReport"), &[Query::OnConflict];
+    import_announcement           => "處置股",   200, "匯入證交所或櫃買中心公告原始檔", Raw(&["application/json", "text/csv"]), Schema("BulkImportReport"), &[Query::OnConflict];
+    update_disposition            => "處置股",   200, "更新個股最近一筆的處置期間", Schema("UpdateDisposition"), Schema("Disposition"), &[];
+    delete_disposition            => "處置股",   200, "刪除個股最近一筆處置", NO_BODY, Primitive("boolean"), &[];
+    update_disposition_period     => "處置股",   200, "更新指定的處置期間", Schema("UpdateDisposition"), Schema("Disposition"), &[];
+    delete_disposition_period     => "處置股",   200, "刪除指定的處置期間", NO_BODY, Primitive("boolean"), &[];
+}
+
+// 一次產生後重複使用
+static DOCUMENT: LazyLock<Value> = LazyLock::new(build);
+
+pub fn document() -> &'static Value {
+    &DOCUMENT
+}
+
+// Swagger UI 的靜態檔由 CDN 載入，伺服器只回傳這一頁
+pub const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="zh-Hant">
+<head>
+  <meta charset="utf-8">
+  <title>Rust CRUD API 文件</title>
+  <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://cdn.jsdelivr.net/npm/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui", persistAuthorization: true });
+  </script>
+</body>
+</html>
+"##;
+
+fn build() -> Value {
+    let mut paths = Map::new();
+    for route in ROUTES {
+        // 沒有說明的路由不列入，由測試檢查
+        let Some(op) = OPERATIONS.iter().find(|op| op.handler == route.handler) else {
+            continue;
+        };
+        let item = paths.entry(route.path).or_insert_with(|| json!({}));
+        item[route.method.as_str().to_lowercase()] = operation(route, op);
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Rust CRUD API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "處置股與使用者管理 API。回應一律包在 ApiResponse 中（匯出、行事曆、SSE 等檔案或串流除外）。",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "responses": {
+                "Error": {
+                    "description": "錯誤，`code` 為固定代碼，例如 NOT_FOUND、VALIDATION_FAILED",
+                    "content": { "application/json": { "schema": schema_ref("ErrorResponse") } },
+                },
+            },
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+                "apiKeyQuery": { "type": "apiKey", "in": "query", "name": "api_key" },
+            },
+        },
+    })
+}
+
+fn operation(route: &RouteSpec, op: &Operation) -> Value {
+    let mut parameters: Vec<Value> = path_parameters(route.path);
+    for query in op.query {
+        parameters.extend(query_parameters(*query));
+    }
+    // 處置股的日期都可以改用民國格式輸出
+    if route.path.starts_with("/disposition") || route.path == "/ws" {
+        parameters.push(query_parameter("date_format", json!({ "type": "string", "enum": ["iso", "roc"], "default": "iso" }), "回應中處置股日期的格式"));
+    }
+
+    let mut responses = Map::new();
+    responses.insert(op.status.to_string(), success_response(op));
+    let mut errors = vec!["400"];
+    if !matches!(op.request, Body::None) {
+        errors.push("422");
+    }
+    let (description, security) = match route.permission {
+        Permission::Public => ("不需登入".to_string(), json!([])),
+        Permission::Role(role) => {
+            errors.extend(["401", "403"]);
+            match route.api_key_scope {
+                Some(scope) if QUERY_KEY_PATHS.contains(&route.path) => (
+                    format!("需要 {} 以上的角色，或帶有 `{}` scope 的 API key（可放在 `api_key` 參數）", role.as_str(), scope.as_str()),
+                    json!([{ "bearer": [] }, { "apiKey": [] }, { "apiKeyQuery": [] }]),
+                ),
+                Some(scope) => (
+                    format!("需要 {} 以上的角色，或帶有 `{}` scope 的 API key", role.as_str(), scope.as_str()),
+                    json!([{ "bearer": [] }, { "apiKey": [] }]),
+                ),
+                None => (format!("需要 {} 以上的角色", role.as_str()), json!([{ "bearer": [] }])),
+            }
+        }
+    };
+    if route.path.contains('{') {
+        errors.push("404");
+    }
+    for status in errors {
+        responses.insert(status.to_string(), json!({ "$ref": "#/components/responses/Error" }));
+    }
+
+    let mut value = json!({
+        "operationId": op.handler,
+        "tags": [op.tag],
+        "summary": op.summary,
+        "description": description,
+        "security": security,
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(body) = request_body(op.request) {
+        value["requestBody"] = body;
+    }
+    value
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn nullable(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn path_parameters(path: &str) -> Vec<Value> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            let (schema, description) = match name {
+                "symbol" => (json!({ "type": "integer" }), "股票代碼"),
+                "exchange" => (json!({ "type": "string", "enum": ["twse", "tpex"] }), "交易所"),
+                _ => (json!({ "type": "integer", "minimum": 1 }), "資料 id"),
+            };
+            json!({ "name": name, "in": "path", "required": true, "schema": schema, "description": description })
+        })
+        .collect()
+}
+
+fn query_parameter(name: &str, schema: Value, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "schema": schema, "description": description })
+}
+
+fn query_parameters(query: Query) -> Vec<Value> {
+    let date = || schema_ref("TwDate");
+    match query {
+        Query::Page => vec![
+            query_parameter("limit", json!({ "type": "integer", "minimum": 1 }), "每頁筆數"),
+            query_parameter("offset", json!({ "type": "integer", "minimum": 0 }), "offset 分頁，不可與 cursor 同時使用"),
+            query_parameter("cursor", json!({ "type": "string" }), "游標分頁，值取自回應的 next / prev"),
+        ],
+        Query::Filter => vec![
+            query_parameter("market", json!({ "type": "string", "enum": MARKETS }), "市場別"),
+            query_parameter("symbol", json!({ "type": "integer" }), "股票代碼"),
+            query_parameter("name", json!({ "type": "string" }), "名稱包含的字串"),
+            query_parameter("stock_date_from", date(), "公告日起"),
+            query_parameter("stock_date_to", date(), "公告日迄"),
+            query_parameter("period_from", date(), "處置期間與 [period_from, period_to] 重疊"),
+            query_parameter("period_to", date(), "處置期間與 [period_from, period_to] 重疊"),
+            query_parameter("sort", json!({ "type": "string", "examples": ["stock_date:desc"] }), "`欄位:asc|desc`，欄位為 stock_date、market、symbol、name、start、end、created_at、updated_at"),
+        ],
+        Query::AsOf => vec![query_parameter("as_of", date(), "查詢日期，預設為台北時間的今天")],
+        Query::Export => vec![query_parameter("format", json!({ "type": "string", "enum": ["csv", "xlsx"], "default": "csv" }), "匯出格式")],
+        Query::OnConflict => vec![query_parameter("on_conflict", json!({ "type": "string", "enum": ["fail", "skip", "upsert"], "default": "fail" }), "與既有資料重複時的處理方式")],
+    }
+}
+
+fn request_body(body: Body) -> Option<Value> {
+    let content = match body {
+        Body::None => return None,
+        Body::Schema(name) => json!({ "application/json": { "schema": schema_ref(name) } }),
+        Body::Bulk => json!({
+            "application/json": { "schema": { "type": "array", "items": schema_ref("CreateDisposition") } },
+            "text/csv": { "schema": { "type": "string" } },
+        }),
+        Body::Raw(types) => types.iter().map(|t| (t.to_string(), json!({ "schema": { "type": "string" } }))).collect(),
+        Body::List(_) | Body::Page(_) | Body::Primitive(_) => unreachable!("request body 只會是單一型別或原始內容"),
+    };
+    Some(json!({ "required": true, "content": content }))
+}
+
+// JSON 回應放在 ApiResponse 的 data 中
+fn success_response(op: &Operation) -> Value {
+    let data = match op.response {
+        Body::None => return json!({ "description": "Switching Protocols" }),
+        Body::Raw(types) => {
+            let content: Map<String, Value> = types.iter().map(|t| (t.to_string(), json!({ "schema": { "type": "string" } }))).collect();
+            return json!({ "description": op.summary, "content": content });
+        }
+        Body::Schema(name) => schema_ref(name),
+        Body::List(name) => json!({ "type": "array", "items": schema_ref(name) }),
+        Body::Page(name) => json!({
+            "type": "object",
+            "required": ["items", "total", "limit", "next", "prev"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref(name) },
+                "total": { "type": "integer" },
+                "limit": { "type": "integer" },
+                "offset": { "type": "integer", "description": "offset 分頁時才有" },
+                "next": nullable(json!({ "type": "string" })),
+                "prev": nullable(json!({ "type": "string" })),
+            },
+        }),
+        Body::Primitive(kind) => json!({ "type": kind }),
+        Body::Bulk => unreachable!("Bulk 只用於 request body"),
+    };
+    json!({
+        "description": op.summary,
+        "content": {
+            "application/json": {
+                "schema": { "allOf": [schema_ref("ApiResponse"), { "properties": { "data": data } }] },
+            },
+        },
+    })
+}
+
+fn schemas() -> Value {
+    let date = || schema_ref("TwDate");
+    let datetime = || nullable(schema_ref("DateTime"));
+    let name = json!({ "type": "string", "minLength": 1, "maxLength": 100 });
+    let password = json!({ "type": "string", "minLength": MIN_PASSWORD_LEN, "maxLength": 128, "writeOnly": true });
+    let email = json!({ "type": "string", "format": "email" });
+    let url = json!({ "type": "string", "format": "uri", "maxLength": 500 });
+
+    // 依用途分組，單一 json! 巢狀太深會超過巨集的遞迴上限
+    let groups = [
+        json!({
+            "TwDate": {
+                "type": "string",
+                "description": "日期。輸入可用 ISO 或民國年（114/10/17、114.10.17、114年10月17日、1141017）；輸出預設 ISO，`date_format=roc` 時為民國年",
+                "examples": ["2025-10-17", "114/10/17"],
+            },
+            "DateTime": {
+                "type": "string",
+                "description": "伺服器當地時間，不含時區",
+                "examples": ["2025-10-17T09:30:00"],
+            },
+            "Role": { "type": "string", "enum": ["analyst", "operator", "admin"] },
+            "LogLevel": {
+                "type": "object",
+                "required": ["level"],
+                "properties": { "level": { "type": "string", "enum": LOG_LEVELS } },
+            },
+            "Scope": { "type": "string", "enum": ["disposition:read", "disposition:write"] },
+            "FieldError": {
+                "type": "object",
+                "required": ["field", "message"],
+                "properties": { "field": { "type": "string" }, "message": { "type": "string" } },
+            },
+            "ApiResponse": {
+                "type": "object",
+                "required": ["success", "message", "data"],
+                "properties": {
+                    "success": { "type": "boolean" },
+                    "message": { "type": "string" },
+                    "data": {},
+                },
+            },
+            "ErrorResponse": {
+                "type": "object",
+                "required": ["success", "message", "data", "code"],
+                "properties": {
+                    "success": { "const": false },
+                    "message": { "type": "string" },
+                    "data": { "type": "null" },
+                    "code": { "type": "string", "enum": ["UNAUTHORIZED", "FORBIDDEN", "NOT_FOUND", "CONFLICT", "VALIDATION_FAILED", "BAD_REQUEST", "DATABASE_ERROR", "INTERNAL_ERROR", "SERVICE_UNAVAILABLE"] },
+                    "errors": { "type": "array", "items": schema_ref("FieldError") },
+                },
+            },
+        }),
+        json!({
+            "User": {
+                "type": "object",
+                "required": ["id", "name", "email", "role", "created_at", "updated_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "name": { "type": "string" },
+                    "email": { "type": "string" },
+                    "role": schema_ref("Role"),
+                    "created_at": datetime(),
+                    "updated_at": datetime(),
+                },
+            },
+            "CreateUser": {
+                "type": "object",
+                "required": ["name", "email", "password"],
+                "properties": {
+                    "name": name,
+                    "email": email,
+                    "password": password,
+                    "role": nullable(schema_ref("Role")),
+                },
+            },
+            "UpdateUser": {
+                "type": "object",
+                "description": "省略的欄位不變",
+                "properties": {
+                    "name": nullable(name.clone()),
+                    "email": nullable(email.clone()),
+                    "role": nullable(schema_ref("Role")),
+                },
+            },
+            "LoginRequest": {
+                "type": "object",
+                "required": ["email", "password"],
+                "properties": { "email": email, "password": { "type": "string", "writeOnly": true } },
+            },
+            "RefreshRequest": {
+                "type": "object",
+                "required": ["refresh_token"],
+                "properties": { "refresh_token": { "type": "string" } },
+            },
+            "TokenPair": {
+                "type": "object",
+                "required": ["access_token", "refresh_token", "token_type", "expires_in", "user"],
+                "properties": {
+                    "access_token": { "type": "string" },
+                    "refresh_token": { "type": "string" },
+                    "token_type": { "const": "Bearer" },
+                    "expires_in": { "type": "integer", "description": "access token 的有效秒數" },
+                    "user": schema_ref("User"),
+                },
+            },
+            "ChangePassword": {
+                "type": "object",
+                "required": ["old_password", "new_password"],
+                "properties": { "old_password": { "type": "string", "writeOnly": true, "description": "admin 變更他人密碼時不檢查，可為空字串" }, "new_password": password },
+            },
+            "PasswordResetRequest": {
+                "type": "object",
+                "required": ["email"],
+                "properties": { "email": email },
+            },
+            "PasswordResetConfirm": {
+                "type": "object",
+                "required": ["token", "new_password"],
+                "properties": { "token": { "type": "string" }, "new_password": password },
+            },
+        }),
+        json!({
+            "ApiKey": {
+                "type": "object",
+                "required": ["id", "user_id", "name", "prefix", "scopes", "expires_at", "last_used_at", "revoked_at", "created_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "user_id": { "type": "integer" },
+                    "name": { "type": "string" },
+                    "prefix": { "type": "string", "description": "明碼的前幾個字元" },
+                    "scopes": { "type": "array", "items": schema_ref("Scope") },
+                    "expires_at": datetime(),
+                    "last_used_at": datetime(),
+                    "revoked_at": datetime(),
+                    "created_at": datetime(),
+                },
+            },
+            "CreateApiKey": {
+                "type": "object",
+                "required": ["name", "scopes"],
+                "properties": {
+                    "name": name,
+                    "scopes": { "type": "array", "items": schema_ref("Scope"), "minItems": 1 },
+                    "expires_in_days": nullable(json!({ "type": "integer", "minimum": 1, "maximum": 3650 })),
+                },
+            },
+            "CreatedApiKey": {
+                "allOf": [schema_ref("ApiKey"), {
+                    "type": "object",
+                    "required": ["key"],
+                    "properties": { "key": { "type": "string", "description": "明碼，只會出現這一次" } },
+                }],
+            },
+            "ApiKeyUse": {
+                "type": "object",
+                "required": ["id", "api_key_id", "method", "path", "status", "ip", "created_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "api_key_id": { "type": "integer" },
+                    "method": { "type": "string" },
+                    "path": { "type": "string" },
+                    "status": { "type": "integer" },
+                    "ip": nullable(json!({ "type": "string" })),
+                    "created_at": datetime(),
+                },
+            },
+        }),
+        json!({
+            "Webhook": {
+                "type": "object",
+                "required": ["id", "url", "description", "active", "created_at", "updated_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "url": { "type": "string" },
+                    "description": { "type": "string" },
+                    "active": { "type": "boolean" },
+                    "created_at": datetime(),
+                    "updated_at": datetime(),
+                },
+            },
+            "CreateWebhook": {
+                "type": "object",
+                "required": ["url"],
+                "properties": { "url": url, "description": { "type": "string", "maxLength": 255 } },
+            },
+            "UpdateWebhook": {
+                "type": "object",
+                "description": "省略的欄位不變",
+                "properties": {
+                    "url": nullable(url.clone()),
+                    "description": nullable(json!({ "type": "string", "maxLength": 255 })),
+                    "active": nullable(json!({ "type": "boolean" })),
+                },
+            },
+            "CreatedWebhook": {
+                "allOf": [schema_ref("Webhook"), {
+                    "type": "object",
+                    "required": ["secret"],
+                    "properties": { "secret": { "type": "string", "description": "簽章用的 secret，只會出現這一次" } },
+                }],
+            },
+            "WebhookDelivery": {
+                "type": "object",
+                "required": ["id", "webhook_id", "event", "payload", "status", "attempts", "next_attempt_at", "last_status", "last_error", "created_at", "delivered_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "webhook_id": { "type": "integer" },
+                    "event": { "type": "string", "enum": ["disposition.created", "disposition.updated", "disposition.deleted"] },
+                    "payload": { "type": "object" },
+                    "status": { "type": "string", "enum": ["pending", "delivered", "failed"] },
+                    "attempts": { "type": "integer" },
+                    "next_attempt_at": datetime(),
+                    "last_status": nullable(json!({ "type": "integer" })),
+                    "last_error": nullable(json!({ "type": "string" })),
+                    "created_at": datetime(),
+                    "delivered_at": datetime(),
+                },
+            },
+        }),
+        json!({
+            "Disposition": {
+                "type": "object",
+                "required": ["id", "stock_date", "market", "symbol", "name", "start", "end", "created_at", "updated_at"],
+                "properties": {
+                    "id": { "type": "integer" },
+                    "stock_date": nullable(date()),
+                    "market": { "type": "string", "enum": MARKETS },
+                    "symbol": { "type": "integer" },
+                    "name": { "type": "string" },
+                    "start": nullable(date()),
+                    "end": nullable(date()),
+                    "created_at": datetime(),
+                    "updated_at": datetime(),
+                },
+            },
+            "CreateDisposition": {
+                "type": "object",
+                "required": ["stock_date", "market", "symbol", "name"],
+                "properties": {
+                    "stock_date": date(),
+                    "market": { "type": "string", "enum": MARKETS },
+                    "symbol": { "type": "string", "pattern": "^[0-9]{1,6}$" },
+                    "name": { "type": "string", "minLength": 1, "maxLength": 50 },
+                    "start": nullable(date()),
+                    "end": nullable(date()),
+                },
+                "description": "start 不可晚於 end",
+            },
+            "UpdateDisposition": {
+                "type": "object",
+                "description": "更新處置期間，start 不可晚於 end",
+                "properties": {
+                    "start": nullable(date()),
+                    "end": nullable(date()),
+                },
+            },
+            "ActiveStatus": {
+                "type": "object",
+                "required": ["symbol", "as_of", "active", "until", "disposition"],
+                "properties": {
+                    "symbol": { "type": "integer" },
+                    "as_of": date(),
+                    "active": { "type": "boolean" },
+                    "until": nullable(date()),
+                    "disposition": nullable(schema_ref("Disposition")),
+                },
+            },
+            "BulkImportReport": {
+                "type": "object",
+                "required": ["on_conflict", "total", "created", "updated", "skipped", "invalid", "rows"],
+                "properties": {
+                    "on_conflict": { "type": "string", "enum": ["fail", "skip", "upsert"] },
+                    "total": { "type": "integer" },
+                    "created": { "type": "integer" },
+                    "updated": { "type": "integer" },
+                    "skipped": { "type": "integer" },
+                    "invalid": { "type": "integer" },
+                    "rows": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "required": ["row", "status"],
+                            "properties": {
+                                "row": { "type": "integer", "description": "從 1 開始，CSV 不含標題列" },
+                                "status": { "type": "string", "enum": ["created", "updated", "skipped", "invalid"] },
+                                "id": { "type": "integer" },
+                                "stock_date": date(),
+                                "symbol": { "type": "string" },
+                                "errors": { "type": "array", "items": schema_ref("FieldError") },
+                            },
+                        },
+                    },
+                },
+            },
+        }),
+    ];
+    let mut schemas = Map::new();
+    for group in groups {
+        if let Value::Object(group) = group {
+            schemas.extend(group);
+        }
+    }
+    Value::Object(schemas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        ChangePassword, CreateApiKey, CreateDispositionRequest, CreateUser, CreateWebhook, Disposition, LogLevel, LoginRequest,
+        PasswordResetConfirm, PasswordResetRequest, RefreshRequest, Role, TwDate, UpdateDisposition, UpdateDispositionRequest,
+        UpdateUser, UpdateWebhook, User,
+    };
+    use serde::de::value::Error as FieldsOnly;
+    use serde::de::{Deserialize, Deserializer, Error as _, Visitor};
+    use std::collections::BTreeSet;
+
+    // 路由表中的每條路由都要有說明，說明也不能對應到已經不存在的 handler
+    #[test]
+    fn every_route_is_documented() {
+        let paths = &document()["paths"];
+        let missing: Vec<String> = ROUTES.iter()
+            .filter(|route| paths[route.path][route.method.as_str().to_lowercase()].is_null())
+            .map(|route| format!("{} {} ({})", route.method, route.path, route.handler))
+            .collect();
+        assert!(missing.is_empty(), "OpenAPI 文件缺少路由: {:?}", missing);
+
+        let stale: Vec<&str> = OPERATIONS.iter()
+            .filter(|op| !ROUTES.iter().any(|route| route.handler == op.handler))
+            .map(|op| op.handler)
+            .collect();
+        assert!(stale.is_empty(), "OPERATIONS 有不存在的 handler: {:?}", stale);
+    }
+
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value.as_object().map(|o| o.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    // schema 的欄位與 models 序列化的結果一致
+    #[test]
+    fn schemas_match_models() {
+        let schemas = &document()["components"]["schemas"];
+        let date = TwDate::parse("114/10/17");
+        let user = User { id: 1, name: "測試".to_string(), email: "a@b.co".to_string(), role: Role::Admin, created_at: None, updated_at: None };
+        let disposition = Disposition {
+            id: 1,
+            stock_date: date,
+            market: "TWSE".to_string(),
+            symbol: 2330,
+            name: "台積電".to_string(),
+            start: date,
+            end: date,
+            created_at: None,
+            updated_at: None,
+        };
+        let samples = [
+            ("User", serde_json::to_value(user).unwrap()),
+            ("Disposition", serde_json::to_value(disposition).unwrap()),
+            ("UpdateDisposition", serde_json::to_value(UpdateDisposition { start: date, end: date }).unwrap()),
+        ];
+        for (name, sample) in samples {
+            assert_eq!(keys(&schemas[name]["properties"]), keys(&sample), "{} 的欄位不一致", name);
+        }
+    }
+
+    // 只記下 struct 的欄位名稱（已套用 serde rename）就中止的 Deserializer，用來比對沒有 Serialize 的 request 型別
+    struct FieldNames<'a>(&'a mut Vec<&'static str>);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = FieldsOnly;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(FieldsOnly::custom("不是 struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> {
+            self.0.extend_from_slice(fields);
+            Err(FieldsOnly::custom("已取得欄位"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+            unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    fn fields<'de, T: Deserialize<'de>>() -> BTreeSet<String> {
+        let mut names = Vec::new();
+        let _ = T::deserialize(FieldNames(&mut names));
+        names.into_iter().map(String::from).collect()
+    }
+
+    // 每個 request body 的 schema 欄位與實際解析的型別一致，required 也只能是其中的欄位；
+    // 新增 request body 時要在這裡補上對應的型別
+    #[test]
+    fn request_schemas_match_models() {
+        let schemas = &document()["components"]["schemas"];
+        let models = [
+            ("LogLevel", fields::<LogLevel>()),
+            ("LoginRequest", fields::<LoginRequest>()),
+            ("RefreshRequest", fields::<RefreshRequest>()),
+            ("PasswordResetRequest", fields::<PasswordResetRequest>()),
+            ("PasswordResetConfirm", fields::<PasswordResetConfirm>()),
+            ("CreateUser", fields::<CreateUser>()),
+            ("UpdateUser", fields::<UpdateUser>()),
+            ("ChangePassword", fields::<ChangePassword>()),
+            ("CreateApiKey", fields::<CreateApiKey>()),
+            ("CreateWebhook", fields::<CreateWebhook>()),
+            ("UpdateWebhook", fields::<UpdateWebhook>()),
+            ("CreateDisposition", fields::<CreateDispositionRequest>()),
+            ("UpdateDisposition", fields::<UpdateDispositionRequest>()),
+        ];
+
+        let documented: BTreeSet<&str> = OPERATIONS.iter()
+            .filter_map(|op| match op.request {
+                Body::Schema(name) => Some(name),
+                Body::Bulk => Some("CreateDisposition"),
+                _ => None,
+            })
+            .collect();
+        let checked: BTreeSet<&str> = models.iter().map(|(name, _)| *name).collect();
+        assert_eq!(documented, checked, "request body 的型別清單不一致");
+
+        for (name, fields) in &models {
+            let properties = keys(&schemas[name]["properties"]);
+            assert!(!properties.is_empty(), "{} 沒有 schema", name);
+            assert_eq!(&properties, fields, "{} 的欄位不一致", name);
+            let required: BTreeSet<String> = schemas[name]["required"].as_array().into_iter().flatten()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect();
+            assert!(required.is_subset(&properties), "{} 的 required 有不存在的欄位: {:?}", name, required);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::models::{User, Disposition, Page};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+pub const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+
+// 列表端點共用的查詢參數：`?limit=&offset=` 或 `?limit=&cursor=`
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<u32>,
+    pub offset: Option<u64>,
+    pub cursor: Option<String>,
+}
+
+// 游標內容：排序鍵，能轉成字串並還原
+pub trait CursorKey: Sized + Ord + Clone {
+    fn encode(&self) -> String;
+    fn decode(s: &str) -> Option<Self>;
+}
+
+impl CursorKey for u32 {
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+impl CursorKey for (NaiveDate, i32) {
+    fn encode(&self) -> String {
+        format!("{}|{}", self.0, self.1)
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        let (date, symbol) = s.split_once('|')?;
+        Some((NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?, symbol.parse().ok()?))
+    }
+}
+
+// 可以被游標分頁的資料列
+pub trait Keyed {
+    type Key: CursorKey;
+    fn key(&self) -> Self::Key;
+}
+
+impl Keyed for User {
+    type Key = u32;
+
+    fn key(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Keyed for Disposition {
+    type Key = (NaiveDate, i32);
+
+    fn key(&self) -> (NaiveDate, i32) {
+        (self.stock_date.map(|d| d.0).unwrap_or_default(), self.symbol)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PageMode<K> {
+    // 沒有指定 offset/cursor：從頭開始，之後的連結使用游標
+    Start,
+    Offset(u64),
+    After(K),
+    Before(K),
+}
+
+#[derive(Debug, Clone)]
+pub struct PageRequest<K> {
+    pub limit: u32,
+    pub mode: PageMode<K>,
+}
+
+impl<K: CursorKey> PageRequest<K> {
+    pub fn from_query(query: &PageQuery) -> Result<Self, String> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(format!("limit 必須介於 1 到 {}", MAX_LIMIT));
+        }
+
+        let mode = match (&query.cursor, query.offset) {
+            (Some(_), Some(_)) => return Err("cursor 與 offset 不能同時使用".to_string()),
+            (Some(cursor), None) => decode_cursor(cursor).ok_or("無效的 cursor")?,
+            (None, Some(offset)) => PageMode::Offset(offset),
+            (None, None) => PageMode::Start,
+        };
+        Ok(Self { limit, mode })
+    }
+
+    // 多取一筆用來判斷是否還有下一頁
+    pub fn fetch_limit(&self) -> u64 {
+        self.limit as u64 + 1
+    }
+}
+
+// 游標對客戶端是不透明的字串，內容為方向加上排序鍵
+fn encode_cursor<K: CursorKey>(forward: bool, key: &K) -> String {
+    let dir = if forward { "a" } else { "b" };
+    URL_SAFE_NO_PAD.encode(format!("{}:{}", dir, key.encode()))
+}
+
+fn decode_cursor<K: CursorKey>(cursor: &str) -> Option<PageMode<K>> {
+    let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    let (dir, key) = raw.split_once(':')?;
+    match dir {
+        "a" => Some(PageMode::After(K::decode(key)?)),
+        "b" => Some(PageMode::Before(K::decode(key)?)),
+        _ => None,
+    }
+}
+
+// repository 回傳的一頁資料（依排序鍵遞增），has_more 表示查詢方向上還有資料
+pub struct Slice<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+impl<T> Slice<T> {
+    // rows 是依查詢方向取出的結果（Before 時為遞減），最多 limit + 1 筆
+    pub fn from_rows<K>(mut rows: Vec<T>, total: u64, page: &PageRequest<K>) -> Self {
+        let has_more = rows.len() > page.limit as usize;
+        rows.truncate(page.limit as usize);
+        if let PageMode::Before(_) = page.mode {
+            rows.reverse();
+        }
+        Self { items: rows, total, has_more }
+    }
+}
+
+// 記憶體儲存使用：對已經篩選的資料套用相同的分頁規則
+pub fn paginate<T: Keyed>(mut rows: Vec<T>, page: &PageRequest<T::Key>) -> Slice<T> {
+    rows.sort_by_key(|r| r.key());
+    paginate_sorted(rows, page)
+}
+
+// rows 已經排好序（自訂排序時只會是 Start/Offset）
+pub fn paginate_sorted<T: Keyed>(rows: Vec<T>, page: &PageRequest<T::Key>) -> Slice<T> {
+    let total = rows.len() as u64;
+    let limit = page.fetch_limit() as usize;
+    let picked: Vec<T> = match &page.mode {
+        PageMode::Start => rows.into_iter().take(limit).collect(),
+        PageMode::Offset(offset) => rows.into_iter().skip(*offset as usize).take(limit).collect(),
+        PageMode::After(k) => rows.into_iter().filter(|r| r.key() > *k).take(limit).collect(),
+        PageMode::Before(k) => rows.into_iter().rev().filter(|r| r.key() < *k).take(limit).collect(),
+    };
+    Slice::from_rows(picked, total, page)
+}
+
+// 保留原本的查詢參數（篩選條件），只替換分頁參數
+fn link(path: &str, query_string: &str, limit: u32, paging: (&str, String)) -> String {
+    let mut params: Vec<(String, String)> = serde_urlencoded::from_str(query_string).unwrap_or_default();
+    params.retain(|(k, _)| !matches!(k.as_str(), "limit" | "offset" | "cursor"));
+    params.push(("limit".to_string(), limit.to_string()));
+    params.push((paging.0.to_string(), paging.1));
+    format!("{}?{}", path, serde_urlencoded::to_string(&params).unwrap_or_default())
+}
+
+impl<T: Keyed> Page<T> {
+    pub fn build(slice: Slice<T>, page: &PageRequest<T::Key>, path: &str, query_string: &str) -> Self {
+        let limit = page.limit;
+        let (next, prev, offset) = match &page.mode {
+            PageMode::Offset(offset) => {
+                let offset = *offset;
+                let next = slice.has_more
+                    .then(|| link(path, query_string, limit, ("offset", (offset + limit as u64).to_string())));
+                let prev = (offset > 0)
+                    .then(|| link(path, query_string, limit, ("offset", offset.saturating_sub(limit as u64).to_string())));
+                (next, prev, Some(offset))
+            }
+            mode => {
+                let (has_next, has_prev) = match mode {
+                    PageMode::Before(_) => (!slice.items.is_empty(), slice.has_more),
+                    PageMode::After(_) => (slice.has_more, !slice.items.is_empty()),
+                    _ => (slice.has_more, false),
+                };
+                let next = slice.items.last().filter(|_| has_next).map(|last| {
+                    link(path, query_string, limit, ("cursor", encode_cursor(true, &last.key())))
+                });
+                let prev = slice.items.first().filter(|_| has_prev).map(|first| {
+                    link(path, query_string, limit, ("cursor", encode_cursor(false, &first.key())))
+                });
+                (next, prev, None)
+            }
+        };
+
+        Self {
+            items: slice.items,
+            total: slice.total,
+            limit,
+            offset,
+            next,
+            prev,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+
+    fn users(count: u32) -> Vec<User> {
+        // 故意打亂順序，paginate 需自行依 id 排序
+        (1..=count).rev()
+            .map(|id| User { id, name: format!("u{}", id), email: format!("u{}@example.com", id), role: Role::Analyst, created_at: None, updated_at: None })
+            .collect()
+    }
+
+    fn query(query_string: &str) -> Result<PageRequest<u32>, String> {
+        PageRequest::from_query(&serde_urlencoded::from_str::<PageQuery>(query_string).unwrap())
+    }
+
+    fn list(query_string: &str) -> Page<User> {
+        let page = query(query_string).unwrap();
+        Page::build(paginate(users(5), &page), &page, "/user", query_string)
+    }
+
+    fn ids(page: &Page<User>) -> Vec<u32> {
+        page.items.iter().map(|u| u.id).collect()
+    }
+
+    // 連結的查詢字串，沿著 next/prev 繼續翻頁
+    fn follow(link: &Option<String>) -> String {
+        link.as_deref().expect("link").split_once('?').unwrap().1.to_string()
+    }
+
+    #[test]
+    fn rejects_bad_limits_and_cursors() {
+        assert_eq!(query("").unwrap().limit, DEFAULT_LIMIT);
+        assert_eq!(query(&format!("limit={}", MAX_LIMIT)).unwrap().limit, MAX_LIMIT);
+        assert!(query("limit=0").is_err());
+        assert!(query(&format!("limit={}", MAX_LIMIT + 1)).is_err());
+        assert!(query("offset=2&cursor=YTox").is_err());
+        assert!(query("cursor=***").is_err());
+        // 方向只能是 a 或 b，排序鍵也要能還原
+        assert!(query(&format!("cursor={}", URL_SAFE_NO_PAD.encode("c:1"))).is_err());
+        assert!(query(&format!("cursor={}", URL_SAFE_NO_PAD.encode("a:abc"))).is_err());
+
+        assert!(matches!(query(&format!("cursor={}", encode_cursor(true, &3u32))).unwrap().mode, PageMode::After(3)));
+        assert!(matches!(query(&format!("cursor={}", encode_cursor(false, &3u32))).unwrap().mode, PageMode::Before(3)));
+        let key = (NaiveDate::from_ymd_opt(2025, 10, 17).unwrap(), 2330);
+        assert_eq!(<(NaiveDate, i32)>::decode(&key.encode()), Some(key));
+        assert_eq!(<(NaiveDate, i32)>::decode("2025-10-17"), None);
+    }
+
+    // 往後翻到底再往前翻回第一頁；Before 取出的資料為遞減，回傳前要轉回遞增
+    #[test]
+    fn cursor_pages_walk_both_ways() {
+        let first = list("limit=2");
+        assert_eq!((ids(&first), first.total, first.offset), (vec![1, 2], 5, None));
+        assert!(first.prev.is_none());
+
+        let second = list(&follow(&first.next));
+        assert_eq!(ids(&second), vec![3, 4]);
+        let last = list(&follow(&second.next));
+        assert_eq!(ids(&last), vec![5]);
+        assert!(last.next.is_none());
+
+        let back = list(&follow(&last.prev));
+        assert_eq!(ids(&back), vec![3, 4]);
+        assert!(back.next.is_some());
+        let start = list(&follow(&back.prev));
+        assert_eq!(ids(&start), vec![1, 2]);
+        assert!(start.prev.is_none());
+        assert_eq!(list(&follow(&start.next)).items.len(), 2);
+    }
+
+    #[test]
+    fn offset_links_clamp_at_zero() {
+        let page = list("limit=2&offset=1");
+        assert_eq!((ids(&page), page.offset), (vec![2, 3], Some(1)));
+        assert_eq!(page.next.as_deref(), Some("/user?limit=2&offset=3"));
+        assert_eq!(page.prev.as_deref(), Some("/user?limit=2&offset=0"));
+
+        let page = list("limit=2&offset=4");
+        assert_eq!(ids(&page), vec![5]);
+        assert!(page.next.is_none());
+        assert_eq!(page.prev.as_deref(), Some("/user?limit=2&offset=2"));
+        assert!(list("limit=2&offset=0").prev.is_none());
+        assert!(list("offset=10").items.is_empty());
+    }
+
+    // 篩選條件原樣保留，分頁參數只出現一次
+    #[test]
+    fn links_keep_filters() {
+        let page = query("market=TWSE&limit=2&name=%E5%8F%B0").unwrap();
+        let built = Page::build(paginate(users(5), &page), &page, "/disposition", "market=TWSE&limit=2&name=%E5%8F%B0");
+        let next = built.next.unwrap();
+        let params: Vec<(String, String)> = serde_urlencoded::from_str(next.split_once('?').unwrap().1).unwrap();
+        let names: Vec<&str> = params.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(names, ["market", "name", "limit", "cursor"]);
+        assert!(params.contains(&("name".to_string(), "台".to_string())));
+    }
+}
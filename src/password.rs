@@ -0,0 +1,47 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use sha2::{Digest, Sha256};
+use crate::error::{AppError, AppResult};
+
+// Argon2id 很吃 CPU，丟到 blocking 執行緒避免卡住 actix worker
+pub async fn hash(password: String) -> AppResult<String> {
+    actix_web::web::block(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|h| h.to_string())
+            .map_err(|e| AppError::Internal(format!("密碼雜湊失敗: {}", e)))
+    })
+    .await
+    .map_err(|e| AppError::Unavailable(format!("密碼雜湊執行失敗: {}", e)))?
+}
+
+// 雜湊格式錯誤視為不符，不回傳錯誤
+pub async fn verify(password: String, hash: String) -> AppResult<bool> {
+    actix_web::web::block(move || {
+        PasswordHash::new(&hash)
+            .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+            .unwrap_or(false)
+    })
+    .await
+    .map_err(|e| AppError::Unavailable(format!("密碼驗證執行失敗: {}", e)))
+}
+
+// 隨機 token（重設密碼、API key 共用）：回傳 (交給使用者的 token, 存進資料庫的 SHA-256)
+pub fn new_secret_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    let digest = token_digest(&token);
+    (token, digest)
+}
+
+pub fn token_digest(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -1,10 +1,14 @@
-use crate::models::{User, CreateUser, UpdateUser, Disposition, CreateDisposition, UpdateDisposition};
-use anyhow::Result;
-use mysql::{prelude::*, PooledConn, Value};
-use chrono::{NaiveDate, NaiveTime, NaiveDateTime};
+use crate::models::{ApiKey, ApiKeyUse, CreateApiKey, Scope, Role, User, CreateUser, UpdateUser, Disposition, CreateDisposition, ConflictMode, ImportOutcome, TwDate, UpdateDisposition, DispositionEvent, DispositionEventKind, DispositionFilter, DispositionSortField, Webhook, CreateWebhook, UpdateWebhook, WebhookDelivery, PendingDelivery, DeliveryAttempt, DeliveryStatus};
+use crate::pagination::{PageMode, PageRequest, Slice};
+use crate::error::{AppError, AppResult};
+use crate::events::StreamEvent;
+use mysql::{prelude::*, PooledConn, TxOpts, Value};
+use chrono::{Datelike, NaiveDate, NaiveTime, NaiveDateTime};
 
 pub struct UserRepository;
 
+type UserRow = (u32, String, String, String, Value, Value);
+
 // 提取重複的 datetime 解析邏輯
 pub fn parse_datetime(val: Value) -> Option<NaiveDateTime> {
     match val {
@@ -17,48 +21,89 @@ pub fn parse_datetime(val: Value) -> Option<NaiveDateTime> {
     }
 }     
 
+// 不認得的角色字串視為權限最低的 analyst
+fn user_from_row((id, name, email, role, created_val, updated_val): UserRow) -> User {
+    User {
+        id,
+        name,
+        email,
+        role: Role::parse(&role).unwrap_or_default(),
+        created_at: parse_datetime(created_val),
+        updated_at: parse_datetime(updated_val),
+    }
+}
+
 impl UserRepository {
-    pub fn get_all(conn: &mut PooledConn) -> Result<Vec<User>> {
-        let query = "SELECT id, name, email, created_at, updated_at FROM user";
-        
-        let rows: Vec<(u32, String, String, Value, Value)> = conn.exec(query, ())?;
-      
-        let user: Vec<User> = rows.into_iter().map(|(id, name, email, created_val, updated_val)| {
-            let created_at = parse_datetime(created_val);
-            let updated_at = parse_datetime(updated_val);
-            User { id, name, email, created_at, updated_at }
-        }).collect();
-      
-        Ok(user)
-    }
-
-    pub fn get_by_id(conn: &mut PooledConn, id: u32) -> Result<Option<User>> {
-        let query = "SELECT id, name, email, created_at, updated_at FROM user WHERE id = ?";
+    pub fn list(conn: &mut PooledConn, page: &PageRequest<u32>) -> AppResult<Slice<User>> {
+        let total: u64 = conn.query_first("SELECT COUNT(*) FROM user")?.unwrap_or(0);
+        let fetch = page.fetch_limit();
+
+        let rows: Vec<UserRow> = match &page.mode {
+            PageMode::Start => conn.exec(
+                "SELECT id, name, email, role, created_at, updated_at FROM user ORDER BY id LIMIT ?",
+                (fetch,),
+            )?,
+            PageMode::Offset(offset) => conn.exec(
+                "SELECT id, name, email, role, created_at, updated_at FROM user ORDER BY id LIMIT ? OFFSET ?",
+                (fetch, offset),
+            )?,
+            PageMode::After(id) => conn.exec(
+                "SELECT id, name, email, role, created_at, updated_at FROM user WHERE id > ? ORDER BY id LIMIT ?",
+                (id, fetch),
+            )?,
+            PageMode::Before(id) => conn.exec(
+                "SELECT id, name, email, role, created_at, updated_at FROM user WHERE id < ? ORDER BY id DESC LIMIT ?",
+                (id, fetch),
+            )?,
+        };
+
+        let user: Vec<User> = rows.into_iter().map(user_from_row).collect();
+
+        Ok(Slice::from_rows(user, total, page))
+    }
+
+    // 依 id 逐列讀出全部使用者，emit 回傳 false 時停止
+    pub fn export(conn: &mut PooledConn, emit: &mut dyn FnMut(User) -> bool) -> AppResult<()> {
+        let result = conn.query_iter("SELECT id, name, email, role, created_at, updated_at FROM user ORDER BY id")?;
+        for row in result {
+            let row: UserRow = mysql::from_row_opt(row?).map_err(mysql::Error::from)?;
+            if !emit(user_from_row(row)) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_by_id(conn: &mut PooledConn, id: u32) -> AppResult<Option<User>> {
+        let query = "SELECT id, name, email, role, created_at, updated_at FROM user WHERE id = ?";
         
-        let row_opt: Option<(u32, String, String, Value, Value)> = conn.exec_first(query, (id,))?;
+        let row_opt: Option<UserRow> = conn.exec_first(query, (id,))?;
     
-        if let Some((id, name, email, created_val, updated_val)) = row_opt {
-            let created_at = parse_datetime(created_val);
-            let updated_at = parse_datetime(updated_val);    
-            Ok(Some(User { id, name, email, created_at, updated_at }))
-        } else {
-            Ok(None)
-        }
+        Ok(row_opt.map(user_from_row))
     }
 
-    pub fn create(conn: &mut PooledConn, user: &CreateUser) -> Result<User> {
-        let query = "INSERT INTO user (name, email) VALUES (?, ?)";
-        conn.exec_drop(query, ( &user.name, &user.email ))?;
+    pub fn get_by_email(conn: &mut PooledConn, email: &str) -> AppResult<Option<User>> {
+        let query = "SELECT id, name, email, role, created_at, updated_at FROM user WHERE email = ?";
+
+        let row_opt: Option<UserRow> = conn.exec_first(query, (email,))?;
+
+        Ok(row_opt.map(user_from_row))
+    }
+
+    pub fn create(conn: &mut PooledConn, user: &CreateUser, password_hash: &str) -> AppResult<User> {
+        let query = "INSERT INTO user (name, email, role, password_hash) VALUES (?, ?, ?, ?)";
+        let role = user.role.unwrap_or_default();
+        conn.exec_drop(query, ( &user.name, &user.email, role.as_str(), password_hash ))?;
 
         let user_id = conn.last_insert_id();
         if let Some(user) = Self::get_by_id(conn, user_id as u32)? {
             Ok(user)
         } else {
-            anyhow::bail!("無法獲取新創建的使用者")
+            Err(AppError::Database("無法獲取新創建的使用者".to_string()))
         }
     }
 
-    pub fn update(conn: &mut PooledConn, id: u32, user: &UpdateUser) -> Result<Option<User>> {
+    pub fn update(conn: &mut PooledConn, id: u32, user: &UpdateUser) -> AppResult<Option<User>> {
         let mut updates = Vec::new();
         let mut params = Vec::new();
 
@@ -72,6 +117,11 @@ impl UserRepository {
             params.push(email.clone());
         }
 
+        if let Some(role) = user.role {
+            updates.push("role = ?");
+            params.push(role.as_str().to_string());
+        }
+
         if updates.is_empty() {
             return Self::get_by_id(conn, id);
         }
@@ -84,7 +134,48 @@ impl UserRepository {
         Self::get_by_id(conn, id)
     }
 
-    pub fn delete(conn: &mut PooledConn, id: u32) -> Result<bool> {
+    // 使用者不存在或尚未設定密碼時回傳 None
+    pub fn get_password_hash(conn: &mut PooledConn, id: u32) -> AppResult<Option<String>> {
+        let row: Option<Option<String>> = conn.exec_first("SELECT password_hash FROM user WHERE id = ?", (id,))?;
+        Ok(row.flatten())
+    }
+
+    pub fn set_password_hash(conn: &mut PooledConn, id: u32, password_hash: &str) -> AppResult<bool> {
+        let result = conn.exec_iter("UPDATE user SET password_hash = ? WHERE id = ?", (password_hash, id))?;
+        Ok(result.affected_rows() > 0)
+    }
+
+    pub fn create_password_reset(conn: &mut PooledConn, user_id: u32, token_hash: &str, ttl_secs: u64) -> AppResult<()> {
+        conn.exec_drop(
+            "INSERT INTO password_reset (user_id, token_hash, expires_at) VALUES (?, ?, DATE_ADD(NOW(), INTERVAL ? SECOND))",
+            (user_id, token_hash, ttl_secs),
+        )?;
+        Ok(())
+    }
+
+    // 在同一個 transaction 內確認 token 有效、更新密碼，並讓該使用者其他未使用的 token 一起失效
+    pub fn reset_password(conn: &mut PooledConn, token_hash: &str, password_hash: &str) -> AppResult<Option<u32>> {
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let user_id: Option<u32> = tx.exec_first(
+            "SELECT user_id FROM password_reset
+             WHERE token_hash = ? AND used_at IS NULL AND expires_at > NOW()
+             FOR UPDATE",
+            (token_hash,),
+        )?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        tx.exec_drop("UPDATE user SET password_hash = ? WHERE id = ?", (password_hash, user_id))?;
+        tx.exec_drop(
+            "UPDATE password_reset SET used_at = NOW() WHERE user_id = ? AND used_at IS NULL",
+            (user_id,),
+        )?;
+        tx.commit()?;
+        Ok(Some(user_id))
+    }
+
+    pub fn delete(conn: &mut PooledConn, id: u32) -> AppResult<bool> {
         let query = "DELETE FROM user WHERE id = ?";
         
         let result = conn.exec_iter(query, (id,))?;
@@ -97,97 +188,629 @@ impl UserRepository {
 
 pub struct DispositionRepository;
 
-pub fn parse_date(val: Value) -> Option<NaiveDate> {
+// 排序欄位只能從白名單對應，不會把使用者輸入拼進 SQL
+fn sort_column(field: DispositionSortField) -> &'static str {
+    match field {
+        DispositionSortField::StockDate => "stock_date",
+        DispositionSortField::Market => "market",
+        DispositionSortField::Symbol => "symbol",
+        DispositionSortField::Name => "name",
+        DispositionSortField::Start => "start",
+        DispositionSortField::End => "end",
+        DispositionSortField::CreatedAt => "created_at",
+        DispositionSortField::UpdatedAt => "updated_at",
+    }
+}
+
+fn filter_conditions(filter: &DispositionFilter) -> (Vec<&'static str>, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut params: Vec<Value> = Vec::new();
+
+    if let Some(market) = &filter.market {
+        conditions.push("market = ?");
+        params.push(market.clone().into());
+    }
+    if let Some(symbol) = filter.symbol {
+        conditions.push("symbol = ?");
+        params.push(symbol.into());
+    }
+    if let Some(name) = &filter.name {
+        let escaped = name.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        conditions.push("name LIKE ?");
+        params.push(format!("%{}%", escaped).into());
+    }
+    if let Some(from) = filter.stock_date_from {
+        conditions.push("stock_date >= ?");
+        params.push(from.into());
+    }
+    if let Some(to) = filter.stock_date_to {
+        conditions.push("stock_date <= ?");
+        params.push(to.into());
+    }
+    // 區間重疊：start <= period_to 且 end >= period_from
+    if let Some(to) = filter.period_to {
+        conditions.push("start <= ?");
+        params.push(to.into());
+    }
+    if let Some(from) = filter.period_from {
+        conditions.push("end >= ?");
+        params.push(from.into());
+    }
+
+    (conditions, params)
+}
+
+fn where_sql(conditions: &[&str]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+type DispositionRow = (u64, Value, String, i32, String, Value, Value, Value, Value);
+
+fn disposition_from_row((id, stock_date_val, market, symbol, name, start_val, end_val, created_val, updated_val): DispositionRow) -> Disposition {
+    Disposition {
+        id,
+        stock_date: parse_date(stock_date_val),
+        market,
+        symbol,
+        name,
+        start: parse_date(start_val),
+        end: parse_date(end_val),
+        created_at: parse_datetime(created_val),
+        updated_at: parse_datetime(updated_val),
+    }
+}
+
+// DATE 欄位與 TwDate 互轉：讀取用 parse_date，寫入與查詢條件一律以 TwDate 綁定參數，不再依賴 MySQL 解析字串
+pub fn parse_date(val: Value) -> Option<TwDate> {
     match val {
         Value::Date(y, m, d, _, _, _, _) => {
-            NaiveDate::from_ymd_opt(y as i32, m as u32, d as u32)
+            NaiveDate::from_ymd_opt(y as i32, m as u32, d as u32).map(TwDate)
         },
         _ => None,
     }
-}    
+}
 
-impl DispositionRepository {
-    pub fn get_all(conn: &mut PooledConn) -> Result<Vec<Disposition>> {
-        let query = "SELECT stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition";
-        
-        let rows: Vec<(Value, String, i32, String, Value, Value, Value, Value)> = conn.exec(query, ())?;
-      
-        let disposition: Vec<Disposition> = rows.into_iter().map(|(stock_date_val, market, symbol, name, start_val, end_val, created_val, updated_val)| {
-            let stock_date = parse_date(stock_date_val);
-            let start = parse_date(start_val);
-            let end = parse_date(end_val);
-            let created_at = parse_datetime(created_val);
-            let updated_at = parse_datetime(updated_val);
-            Disposition { stock_date, market, symbol, name, start, end, created_at, updated_at }
-        }).collect();
-      
-        Ok(disposition)
+impl From<TwDate> for Value {
+    fn from(date: TwDate) -> Self {
+        Value::Date(date.0.year() as u16, date.0.month() as u8, date.0.day() as u8, 0, 0, 0, 0)
     }
+}
 
-    pub fn get_by_symbol(conn: &mut PooledConn, symbol: i32) -> Result<Option<Disposition>> {
-        let query = "SELECT stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE symbol = ? ORDER BY end DESC LIMIT 1";
+const INSERT_DISPOSITION: &str = "INSERT INTO s_disposition (stock_date, market, symbol, name, start, end) VALUES (?, ?, ?, ?, ?, ?)";
 
-        let row_opt: Option<(Value, String, i32, String, Value, Value, Value, Value)> = conn.exec_first(query, (symbol,))?;
-    
-        if let Some((stock_date_val, market, symbol, name, start_val, end_val, created_val, updated_val)) = row_opt {
-            let stock_date = parse_date(stock_date_val);
-            let start = parse_date(start_val);
-            let end = parse_date(end_val);
-            let created_at = parse_datetime(created_val);
-            let updated_at = parse_datetime(updated_val);    
-            Ok(Some(Disposition { stock_date, market, symbol, name, start, end, created_at, updated_at }))
-        } else {
-            Ok(None)
+fn insert_params(disposition: &CreateDisposition, symbol: i32) -> (TwDate, &str, i32, &str, Option<TwDate>, Option<TwDate>) {
+    (
+        disposition.stock_date,
+        &disposition.market,
+        symbol,
+        &disposition.name,
+        disposition.start,
+        disposition.end,
+    )
+}
+
+// 異動與事件在同一個 transaction 內寫入（outbox）：寫入 disposition_event 供 events::relay 廣播，
+// 並為每個啟用中的 webhook 排入一筆投遞。先鎖住事件表的最大 id 再取號，
+// 並行的寫入會在這裡排隊，事件 id 因此依 commit 順序遞增，relay 以 id 往後讀不會漏掉
+fn record_event(tx: &mut impl Queryable, kind: DispositionEventKind, data: Disposition) -> AppResult<()> {
+    let payload = DispositionEvent::new(kind, data).payload()?;
+    let last: Option<u64> = tx.query_first("SELECT MAX(id) FROM disposition_event FOR UPDATE")?.flatten();
+    tx.exec_drop(
+        "INSERT INTO disposition_event (id, event, payload) VALUES (?, ?, ?)",
+        (last.unwrap_or(0) + 1, kind.as_str(), &payload),
+    )?;
+    tx.exec_drop(
+        "INSERT INTO webhook_delivery (webhook_id, event, payload) SELECT id, ?, ? FROM webhook WHERE active",
+        (kind.as_str(), &payload),
+    )?;
+    Ok(())
+}
+
+const DISPOSITION_COLUMNS: &str = "id, stock_date, market, symbol, name, start, end, created_at, updated_at";
+
+// 在 transaction 內鎖住要修改的處置期間：id 為 None 時是最新一筆（與 get_by_symbol 相同的排序），
+// 選取與鎖定在同一個查詢內完成，並行新增的期間不會讓這裡改到已經不是最新的那一筆
+fn lock_period<T: FromRow>(tx: &mut impl Queryable, columns: &str, symbol: i32, id: Option<u64>) -> AppResult<Option<T>> {
+    let row = match id {
+        Some(id) => tx.exec_first(
+            format!("SELECT {} FROM s_disposition WHERE id = ? AND symbol = ? FOR UPDATE", columns),
+            (id, symbol),
+        )?,
+        None => tx.exec_first(
+            format!("SELECT {} FROM s_disposition WHERE symbol = ? ORDER BY end DESC, id DESC LIMIT 1 FOR UPDATE", columns),
+            (symbol,),
+        )?,
+    };
+    Ok(row)
+}
+
+fn symbol_number(disposition: &CreateDisposition) -> AppResult<i32> {
+    disposition.symbol.parse().map_err(|e| {
+        AppError::invalid("symbol", format!("無效的股票代碼格式 '{}': {}", disposition.symbol, e))
+    })
+}
+
+impl DispositionRepository {
+    pub fn list(conn: &mut PooledConn, filter: &DispositionFilter, page: &PageRequest<(NaiveDate, i32)>) -> AppResult<Slice<Disposition>> {
+        let (mut conditions, mut params) = filter_conditions(filter);
+
+        let count_query = format!("SELECT COUNT(*) FROM s_disposition{}", where_sql(&conditions));
+        let total: u64 = conn.exec_first(count_query, params.clone())?.unwrap_or(0);
+
+        // 預設以唯一鍵 uk_s_disposition_date_symbol (stock_date, symbol) 排序並做 keyset 分頁；自訂排序時只支援 offset
+        let mut order = match filter.sort {
+            Some(sort) => format!("{} {}, stock_date, symbol", sort_column(sort.field), if sort.desc { "DESC" } else { "ASC" }),
+            None => "stock_date, symbol".to_string(),
+        };
+        match &page.mode {
+            PageMode::Start | PageMode::Offset(_) => {}
+            PageMode::After((date, symbol)) => {
+                conditions.push("(stock_date, symbol) > (?, ?)");
+                params.push(TwDate(*date).into());
+                params.push((*symbol).into());
+            }
+            PageMode::Before((date, symbol)) => {
+                conditions.push("(stock_date, symbol) < (?, ?)");
+                params.push(TwDate(*date).into());
+                params.push((*symbol).into());
+                order = "stock_date DESC, symbol DESC".to_string();
+            }
+        }
+        params.push(page.fetch_limit().into());
+        let mut query = format!(
+            "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition{} ORDER BY {} LIMIT ?",
+            where_sql(&conditions), order
+        );
+        if let PageMode::Offset(offset) = page.mode {
+            query.push_str(" OFFSET ?");
+            params.push(offset.into());
         }
+
+        let rows: Vec<DispositionRow> = conn.exec(query, params)?;
+        Ok(Slice::from_rows(rows.into_iter().map(disposition_from_row).collect(), total, page))
     }
 
-    pub fn create(conn: &mut PooledConn, disposition: &CreateDisposition) -> Result<Disposition> {
-        let query = "INSERT INTO s_disposition (stock_date, market, symbol, name) VALUES (?, ?, ?, ?)";
-        let symbol_num: i32 = disposition.symbol.parse().map_err(|e| {
-            anyhow::anyhow!("無效的股票代碼格式 '{}': {}", disposition.symbol, e)
-        })?;
-        conn.exec_drop(query, ( &disposition.stock_date, &disposition.market, symbol_num, &disposition.name ))?;
+    // 與 list 相同的篩選與排序，但不分頁，逐列交給 emit
+    pub fn export(conn: &mut PooledConn, filter: &DispositionFilter, emit: &mut dyn FnMut(Disposition) -> bool) -> AppResult<()> {
+        let (conditions, params) = filter_conditions(filter);
+        let order = match filter.sort {
+            Some(sort) => format!("{} {}, stock_date, symbol", sort_column(sort.field), if sort.desc { "DESC" } else { "ASC" }),
+            None => "stock_date, symbol".to_string(),
+        };
+        let query = format!(
+            "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition{} ORDER BY {}",
+            where_sql(&conditions), order
+        );
 
-        if let Some(disposition) = Self::get_by_symbol(conn, symbol_num)? {
-            Ok(disposition)
-        } else {
-            anyhow::bail!("無法獲取新創建的處置股")
+        let result = conn.exec_iter(query, params)?;
+        for row in result {
+            let row: DispositionRow = mysql::from_row_opt(row?).map_err(mysql::Error::from)?;
+            if !emit(disposition_from_row(row)) {
+                break;
+            }
         }
+        Ok(())
     }
 
-    pub fn update(conn: &mut PooledConn, symbol: i32, disposition: &UpdateDisposition) -> Result<Option<Disposition>> {
-        let mut updates = Vec::new();
-        let mut params = Vec::new();
+    pub fn get_by_symbol(conn: &mut PooledConn, symbol: i32) -> AppResult<Option<Disposition>> {
+        let query = "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE symbol = ? ORDER BY end DESC, id DESC LIMIT 1";
+
+        let row_opt: Option<DispositionRow> = conn.exec_first(query, (symbol,))?;
+
+        Ok(row_opt.map(disposition_from_row))
+    }
+
+    // 處置期間 [start, end] 涵蓋 as_of 的所有處置股
+    pub fn get_active(conn: &mut PooledConn, as_of: NaiveDate) -> AppResult<Vec<Disposition>> {
+        let query = "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE start <= ? AND end >= ? ORDER BY market, symbol";
+        let as_of = TwDate(as_of);
+
+        let rows: Vec<DispositionRow> = conn.exec(query, (as_of, as_of))?;
+
+        Ok(rows.into_iter().map(disposition_from_row).collect())
+    }
+
+    pub fn get_active_by_symbol(conn: &mut PooledConn, symbol: i32, as_of: NaiveDate) -> AppResult<Option<Disposition>> {
+        let query = "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE symbol = ? AND start <= ? AND end >= ? ORDER BY end DESC LIMIT 1";
+        let as_of = TwDate(as_of);
+
+        let row_opt: Option<DispositionRow> = conn.exec_first(query, (symbol, as_of, as_of))?;
+
+        Ok(row_opt.map(disposition_from_row))
+    }
 
-        if let Some(start) = &disposition.start {
-            updates.push("start = ?");
-            params.push(start.clone());
+    // 同一檔股票的所有處置期間，最新的在前
+    pub fn get_history(conn: &mut PooledConn, symbol: i32) -> AppResult<Vec<Disposition>> {
+        let query = "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE symbol = ? ORDER BY stock_date DESC, id DESC";
+
+        let rows: Vec<DispositionRow> = conn.exec(query, (symbol,))?;
+
+        Ok(rows.into_iter().map(disposition_from_row).collect())
+    }
+
+    pub fn get_by_id(conn: &mut impl Queryable, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        let query = "SELECT id, stock_date, market, symbol, name, start, end, created_at, updated_at FROM s_disposition WHERE id = ? AND symbol = ?";
+
+        let row_opt: Option<DispositionRow> = conn.exec_first(query, (id, symbol))?;
+
+        Ok(row_opt.map(disposition_from_row))
+    }
+
+    pub fn create(conn: &mut PooledConn, disposition: &CreateDisposition) -> AppResult<Disposition> {
+        let symbol_num = symbol_number(disposition)?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        tx.exec_drop(INSERT_DISPOSITION, insert_params(disposition, symbol_num))?;
+
+        let disposition = tx.last_insert_id()
+            .map(|id| Self::get_by_id(&mut tx, symbol_num, id))
+            .transpose()?
+            .flatten()
+            .ok_or_else(|| AppError::Database("無法獲取新創建的處置股".to_string()))?;
+        record_event(&mut tx, DispositionEventKind::Created, disposition.clone())?;
+        tx.commit()?;
+        Ok(disposition)
+    }
+
+    // 整批在同一個 transaction 內逐列處理；fail 模式遇到重複時直接 drop transaction（回滾）。
+    // 事件在最後才寫入，事件表的鎖只在 commit 前持有一下
+    pub fn import(conn: &mut PooledConn, rows: &[CreateDisposition], mode: ConflictMode) -> AppResult<Vec<ImportOutcome>> {
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let mut outcomes = Vec::with_capacity(rows.len());
+        let mut changed = Vec::new();
+
+        for disposition in rows {
+            let symbol_num = symbol_number(disposition)?;
+            let existing: Option<(u64, Value, Value)> = tx.exec_first(
+                "SELECT id, start, end FROM s_disposition WHERE stock_date = ? AND symbol = ? FOR UPDATE",
+                (disposition.stock_date, symbol_num),
+            )?;
+            let existing = existing.map(|(id, start, end)| (id, parse_date(start), parse_date(end)));
+            let outcome = match (existing, mode) {
+                (None, _) => {
+                    tx.exec_drop(INSERT_DISPOSITION, insert_params(disposition, symbol_num))?;
+                    let id = tx.last_insert_id()
+                        .ok_or_else(|| AppError::Database("無法獲取新創建的處置股".to_string()))?;
+                    changed.push((DispositionEventKind::Created, symbol_num, id));
+                    ImportOutcome::Created(id)
+                }
+                (Some((id, start, end)), ConflictMode::Upsert) => {
+                    let (start, end) = (disposition.start.or(start), disposition.end.or(end));
+                    if matches!((start, end), (Some(start), Some(end)) if start > end) {
+                        ImportOutcome::InvalidPeriod(id)
+                    } else {
+                        tx.exec_drop(
+                            "UPDATE s_disposition SET market = ?, name = ?, start = ?, end = ? WHERE id = ?",
+                            (&disposition.market, &disposition.name, start, end, id),
+                        )?;
+                        changed.push((DispositionEventKind::Updated, symbol_num, id));
+                        ImportOutcome::Updated(id)
+                    }
+                }
+                (Some((id, _, _)), ConflictMode::Skip) => ImportOutcome::Skipped(id),
+                (Some(_), ConflictMode::Fail) => {
+                    outcomes.push(ImportOutcome::Conflict);
+                    return Ok(outcomes);
+                }
+            };
+            outcomes.push(outcome);
         }
 
-        if let Some(end) = &disposition.end {
-            updates.push("end = ?");
-            params.push(end.clone());
+        for (kind, symbol, id) in changed {
+            if let Some(disposition) = Self::get_by_id(&mut tx, symbol, id)? {
+                record_event(&mut tx, kind, disposition)?;
+            }
         }
+        tx.commit()?;
+        Ok(outcomes)
+    }
 
-        if updates.is_empty() {
+    // 更新最新一筆（end 最大）處置期間
+    pub fn update(conn: &mut PooledConn, symbol: i32, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        if disposition.start.is_none() && disposition.end.is_none() {
             return Self::get_by_symbol(conn, symbol);
         }
+        Self::update_period(conn, symbol, None, disposition)
+    }
 
-        let query = format!("UPDATE s_disposition SET {} WHERE symbol = ? ORDER BY end DESC LIMIT 1", updates.join(", "));
-        params.push(symbol.to_string());
+    pub fn update_by_id(conn: &mut PooledConn, symbol: i32, id: u64, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        if disposition.start.is_none() && disposition.end.is_none() {
+            return Self::get_by_id(conn, symbol, id);
+        }
+        Self::update_period(conn, symbol, Some(id), disposition)
+    }
 
-        conn.exec_drop(&query, params)?;
+    // 鎖住該列後與既有的起訖日合併檢查，避免只更新一端時寫入起日晚於迄日的期間
+    fn update_period(conn: &mut PooledConn, symbol: i32, id: Option<u64>, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let current: Option<(u64, Value, Value)> = lock_period(&mut tx, "id, start, end", symbol, id)?;
+        let Some((id, start, end)) = current else {
+            return Ok(None);
+        };
+        let (start, end) = disposition.merged_period(parse_date(start), parse_date(end))?;
+        tx.exec_drop("UPDATE s_disposition SET start = ?, end = ? WHERE id = ?", (start, end, id))?;
+        let updated = Self::get_by_id(&mut tx, symbol, id)?;
+        if let Some(disposition) = &updated {
+            record_event(&mut tx, DispositionEventKind::Updated, disposition.clone())?;
+        }
+        tx.commit()?;
+        Ok(updated)
+    }
 
-        Self::get_by_symbol(conn, symbol)
+    // 刪除最新一筆（end 最大）處置期間
+    pub fn delete(conn: &mut PooledConn, symbol: i32) -> AppResult<Option<Disposition>> {
+        Self::delete_period(conn, symbol, None)
     }
 
-    pub fn delete(conn: &mut PooledConn, symbol: i32) -> Result<bool> {
-        let query = "DELETE FROM s_disposition WHERE symbol = ? ORDER BY end DESC LIMIT 1";
-        
-        let result = conn.exec_iter(query, (symbol,))?;
-        
-        let affected_rows = result.affected_rows();
+    pub fn delete_by_id(conn: &mut PooledConn, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        Self::delete_period(conn, symbol, Some(id))
+    }
 
-        Ok(affected_rows > 0)
+    // 先讀出再刪除，回傳被刪除的資料；同一個 transaction 內鎖住該列，避免讀到的與刪掉的不一致
+    fn delete_period(conn: &mut PooledConn, symbol: i32, id: Option<u64>) -> AppResult<Option<Disposition>> {
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let row: Option<DispositionRow> = lock_period(&mut tx, DISPOSITION_COLUMNS, symbol, id)?;
+        let Some(deleted) = row.map(disposition_from_row) else {
+            return Ok(None);
+        };
+        tx.exec_drop("DELETE FROM s_disposition WHERE id = ?", (deleted.id,))?;
+        record_event(&mut tx, DispositionEventKind::Deleted, deleted.clone())?;
+        tx.commit()?;
+        Ok(Some(deleted))
+    }
+
+    // id 大於 after 的事件，依 id 排序
+    pub fn events_after(conn: &mut PooledConn, after: u64, limit: u32) -> AppResult<Vec<StreamEvent>> {
+        let rows: Vec<(u64, String)> = conn.exec(
+            "SELECT id, CAST(payload AS CHAR) FROM disposition_event WHERE id > ? ORDER BY id LIMIT ?",
+            (after, limit),
+        )?;
+        rows.into_iter().map(|(id, payload)| {
+            let event = serde_json::from_str(&payload)
+                .map_err(|e| AppError::Database(format!("無法解析事件 {}: {}", id, e)))?;
+            Ok(StreamEvent { id, event })
+        }).collect()
+    }
+
+    pub fn last_event_id(conn: &mut PooledConn) -> AppResult<u64> {
+        let last: Option<u64> = conn.query_first("SELECT MAX(id) FROM disposition_event")?.flatten();
+        Ok(last.unwrap_or(0))
+    }
+}
+
+pub struct ApiKeyRepository;
+
+type ApiKeyRow = (u64, u32, String, String, String, Value, Value, Value, Value);
+type ApiKeyUseRow = (u64, u64, String, String, u16, Option<String>, Value);
+
+const API_KEY_COLUMNS: &str = "id, user_id, name, prefix, scopes, expires_at, last_used_at, revoked_at, created_at";
+
+// scopes 以逗號分隔存放，不認得的值直接略過
+fn api_key_from_row((id, user_id, name, prefix, scopes, expires_val, used_val, revoked_val, created_val): ApiKeyRow) -> ApiKey {
+    ApiKey {
+        id,
+        user_id,
+        name,
+        prefix,
+        scopes: scopes.split(',').filter_map(Scope::parse).collect(),
+        expires_at: parse_datetime(expires_val),
+        last_used_at: parse_datetime(used_val),
+        revoked_at: parse_datetime(revoked_val),
+        created_at: parse_datetime(created_val),
+    }
+}
+
+impl ApiKeyRepository {
+    pub fn list(conn: &mut PooledConn, user_id: u32) -> AppResult<Vec<ApiKey>> {
+        let query = format!("SELECT {} FROM api_key WHERE user_id = ? ORDER BY id DESC", API_KEY_COLUMNS);
+        let rows: Vec<ApiKeyRow> = conn.exec(query, (user_id,))?;
+        Ok(rows.into_iter().map(api_key_from_row).collect())
+    }
+
+    pub fn get_by_id(conn: &mut PooledConn, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        let query = format!("SELECT {} FROM api_key WHERE user_id = ? AND id = ?", API_KEY_COLUMNS);
+        let row: Option<ApiKeyRow> = conn.exec_first(query, (user_id, id))?;
+        Ok(row.map(api_key_from_row))
+    }
+
+    pub fn create(conn: &mut PooledConn, user_id: u32, key: &CreateApiKey, prefix: &str, key_hash: &str) -> AppResult<ApiKey> {
+        let scopes = key.scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(",");
+        conn.exec_drop(
+            "INSERT INTO api_key (user_id, name, prefix, key_hash, scopes, expires_at)
+             VALUES (?, ?, ?, ?, ?, IF(? IS NULL, NULL, DATE_ADD(NOW(), INTERVAL ? DAY)))",
+            (user_id, &key.name, prefix, key_hash, scopes, key.expires_in_days, key.expires_in_days),
+        )?;
+
+        let id = conn.last_insert_id();
+        Self::get_by_id(conn, user_id, id)?
+            .ok_or_else(|| AppError::Database("無法獲取新建立的 API key".to_string()))
+    }
+
+    // 已撤銷的 key 維持原本的撤銷時間
+    pub fn revoke(conn: &mut PooledConn, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        conn.exec_drop(
+            "UPDATE api_key SET revoked_at = COALESCE(revoked_at, NOW()) WHERE user_id = ? AND id = ?",
+            (user_id, id),
+        )?;
+        Self::get_by_id(conn, user_id, id)
+    }
+
+    // 未撤銷、未過期的 key
+    pub fn find_active(conn: &mut PooledConn, key_hash: &str) -> AppResult<Option<ApiKey>> {
+        let query = format!(
+            "SELECT {} FROM api_key
+             WHERE key_hash = ? AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())",
+            API_KEY_COLUMNS
+        );
+        let row: Option<ApiKeyRow> = conn.exec_first(query, (key_hash,))?;
+        Ok(row.map(api_key_from_row))
+    }
+
+    pub fn record_use(conn: &mut PooledConn, id: u64, method: &str, path: &str, status: u16, ip: Option<&str>) -> AppResult<()> {
+        conn.exec_drop("UPDATE api_key SET last_used_at = NOW() WHERE id = ?", (id,))?;
+        conn.exec_drop(
+            "INSERT INTO api_key_use (api_key_id, method, path, status, ip) VALUES (?, ?, ?, ?, ?)",
+            (id, method, path, status, ip),
+        )?;
+        Ok(())
+    }
+
+    pub fn list_uses(conn: &mut PooledConn, id: u64, limit: u32) -> AppResult<Vec<ApiKeyUse>> {
+        let rows: Vec<ApiKeyUseRow> = conn.exec(
+            "SELECT id, api_key_id, method, path, status, ip, created_at FROM api_key_use
+             WHERE api_key_id = ? ORDER BY id DESC LIMIT ?",
+            (id, limit),
+        )?;
+        Ok(rows.into_iter().map(|(id, api_key_id, method, path, status, ip, created_val)| ApiKeyUse {
+            id,
+            api_key_id,
+            method,
+            path,
+            status,
+            ip,
+            created_at: parse_datetime(created_val),
+        }).collect())
+    }
+}
+
+pub struct WebhookRepository;
+
+type WebhookRow = (u64, String, String, bool, Value, Value);
+type DeliveryRow = (u64, u64, String, String, String, u32, Value, Option<u16>, Option<String>, Value, Value);
+
+const WEBHOOK_COLUMNS: &str = "id, url, description, active, created_at, updated_at";
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, CAST(payload AS CHAR), status, attempts, next_attempt_at, last_status, last_error, created_at, delivered_at";
+
+// 錯誤訊息欄位長度有限，超過時截斷
+const MAX_DELIVERY_ERROR_CHARS: usize = 500;
+
+fn webhook_from_row((id, url, description, active, created_val, updated_val): WebhookRow) -> Webhook {
+    Webhook {
+        id,
+        url,
+        description,
+        active,
+        created_at: parse_datetime(created_val),
+        updated_at: parse_datetime(updated_val),
+    }
+}
+
+fn delivery_from_row(row: DeliveryRow) -> WebhookDelivery {
+    let (id, webhook_id, event, payload, status, attempts, next_val, last_status, last_error, created_val, delivered_val) = row;
+    WebhookDelivery {
+        id,
+        webhook_id,
+        event,
+        payload: serde_json::from_str(&payload).unwrap_or(serde_json::Value::Null),
+        status: DeliveryStatus::parse(&status).unwrap_or(DeliveryStatus::Pending),
+        attempts,
+        next_attempt_at: parse_datetime(next_val),
+        last_status,
+        last_error,
+        created_at: parse_datetime(created_val),
+        delivered_at: parse_datetime(delivered_val),
+    }
+}
+
+impl WebhookRepository {
+    pub fn list(conn: &mut PooledConn) -> AppResult<Vec<Webhook>> {
+        let rows: Vec<WebhookRow> = conn.query(format!("SELECT {} FROM webhook ORDER BY id", WEBHOOK_COLUMNS))?;
+        Ok(rows.into_iter().map(webhook_from_row).collect())
+    }
+
+    pub fn get_by_id(conn: &mut PooledConn, id: u64) -> AppResult<Option<Webhook>> {
+        let row: Option<WebhookRow> = conn.exec_first(format!("SELECT {} FROM webhook WHERE id = ?", WEBHOOK_COLUMNS), (id,))?;
+        Ok(row.map(webhook_from_row))
+    }
+
+    pub fn create(conn: &mut PooledConn, webhook: &CreateWebhook, secret: &str) -> AppResult<Webhook> {
+        conn.exec_drop(
+            "INSERT INTO webhook (url, description, secret) VALUES (?, ?, ?)",
+            (&webhook.url, &webhook.description, secret),
+        )?;
+
+        let id = conn.last_insert_id();
+        Self::get_by_id(conn, id)?
+            .ok_or_else(|| AppError::Database("無法獲取新建立的 webhook".to_string()))
+    }
+
+    pub fn update(conn: &mut PooledConn, id: u64, webhook: &UpdateWebhook) -> AppResult<Option<Webhook>> {
+        conn.exec_drop(
+            "UPDATE webhook SET url = COALESCE(?, url), description = COALESCE(?, description), active = COALESCE(?, active) WHERE id = ?",
+            (&webhook.url, &webhook.description, webhook.active, id),
+        )?;
+        Self::get_by_id(conn, id)
+    }
+
+    // 投遞紀錄由外鍵一併刪除
+    pub fn delete(conn: &mut PooledConn, id: u64) -> AppResult<bool> {
+        let result = conn.exec_iter("DELETE FROM webhook WHERE id = ?", (id,))?;
+        Ok(result.affected_rows() > 0)
+    }
+
+    pub fn due(conn: &mut PooledConn, limit: u32) -> AppResult<Vec<PendingDelivery>> {
+        let rows: Vec<(u64, u64, String, String, String, String, u32)> = conn.exec(
+            "SELECT d.id, d.webhook_id, w.url, w.secret, d.event, CAST(d.payload AS CHAR), d.attempts
+             FROM webhook_delivery d JOIN webhook w ON w.id = d.webhook_id
+             WHERE d.status = 'pending' AND d.next_attempt_at <= NOW()
+             ORDER BY d.next_attempt_at, d.id LIMIT ?",
+            (limit,),
+        )?;
+        Ok(rows.into_iter().map(|(id, webhook_id, url, secret, event, payload, attempts)| PendingDelivery {
+            id, webhook_id, url, secret, event, payload, attempts,
+        }).collect())
+    }
+
+    pub fn record_attempt(conn: &mut PooledConn, id: u64, attempt: &DeliveryAttempt) -> AppResult<()> {
+        let error = attempt.error.as_ref().map(|e| e.chars().take(MAX_DELIVERY_ERROR_CHARS).collect::<String>());
+        conn.exec_drop(
+            "UPDATE webhook_delivery SET
+                 status = ?, attempts = attempts + 1, last_status = ?, last_error = ?,
+                 next_attempt_at = IF(? IS NULL, NULL, DATE_ADD(NOW(), INTERVAL ? SECOND)),
+                 delivered_at = IF(?, NOW(), NULL)
+             WHERE id = ?",
+            (attempt.status().as_str(), attempt.status, error, attempt.retry_in, attempt.retry_in, attempt.delivered, id),
+        )?;
+        Ok(())
+    }
+
+    pub fn list_deliveries(conn: &mut PooledConn, webhook_id: u64, limit: u32) -> AppResult<Vec<WebhookDelivery>> {
+        let rows: Vec<DeliveryRow> = conn.exec(
+            format!("SELECT {} FROM webhook_delivery WHERE webhook_id = ? ORDER BY id DESC LIMIT ?", DELIVERY_COLUMNS),
+            (webhook_id, limit),
+        )?;
+        Ok(rows.into_iter().map(delivery_from_row).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LIKE 的萬用字元要跳脫，名稱中的 % 與 _ 只比對字面
+    #[test]
+    fn escapes_like_wildcards() {
+        let filter = DispositionFilter { name: Some(r"50%_\x".to_string()), ..DispositionFilter::default() };
+        let (conditions, params) = filter_conditions(&filter);
+        assert_eq!(conditions, ["name LIKE ?"]);
+        assert_eq!(params, [Value::from(r"%50\%\_\\x%")]);
+    }
+
+    // 期間篩選是區間重疊：start <= period_to 且 end >= period_from
+    #[test]
+    fn period_filter_is_overlap() {
+        let filter = DispositionFilter {
+            market: Some("TWSE".to_string()),
+            period_from: TwDate::parse("2025-10-20"),
+            period_to: TwDate::parse("2025-10-31"),
+            ..DispositionFilter::default()
+        };
+        let (conditions, params) = filter_conditions(&filter);
+        assert_eq!(where_sql(&conditions), " WHERE market = ? AND start <= ? AND end >= ?");
+        assert_eq!(params, [Value::from("TWSE"), TwDate::parse("2025-10-31").unwrap().into(), TwDate::parse("2025-10-20").unwrap().into()]);
+        assert_eq!(where_sql(&filter_conditions(&DispositionFilter::default()).0), "");
     }
 }
@@ -0,0 +1,113 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-ID";
+// 接受上游（gateway、前端）帶來的 id 的長度上限
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// 全域 middleware：沿用請求的 X-Request-ID（格式不符時重新產生），並寫回回應標頭。
+// 整個請求在 request span 內處理，期間的 log（含 SQL、錯誤）都會帶上 request_id，結束時記錄狀態碼與耗時
+pub struct RequestTracing;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestTracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTracingMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequestTracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let id = req.headers().get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| valid(v))
+            .map(str::to_string)
+            .unwrap_or_else(generate);
+        let span = tracing::info_span!("request", request_id = %id, method = %req.method(), path = %req.path());
+
+        Box::pin(async move {
+            let started = Instant::now();
+            // 驗證類 middleware 已把錯誤轉成回應；這裡的 Err 只會是未預期的錯誤
+            let mut res = match service.call(req).await {
+                Ok(res) => res,
+                Err(e) => {
+                    tracing::error!(error = %e, elapsed_ms = started.elapsed().as_millis() as u64, "請求處理失敗");
+                    return Err(e);
+                }
+            };
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+            }
+            tracing::info!(status = res.status().as_u16(), elapsed_ms = started.elapsed().as_millis() as u64, "請求完成");
+            Ok(res)
+        }.instrument(span))
+    }
+}
+
+fn valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+fn generate() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, web, App, HttpResponse};
+
+    // 沿用合法的 X-Request-ID，缺少或格式不符時產生新的
+    #[actix_web::test]
+    async fn propagates_or_assigns_request_id() {
+        let app = test::init_service(App::new().wrap(RequestTracing).route("/", web::get().to(HttpResponse::Ok))).await;
+
+        for incoming in [Some("gw-123.abc"), None, Some("has space")] {
+            let mut req = test::TestRequest::get().uri("/");
+            if let Some(id) = incoming {
+                req = req.insert_header((REQUEST_ID_HEADER, id));
+            }
+            let res = test::call_service(&app, req.to_request()).await;
+            let id = res.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap();
+            match incoming {
+                Some(incoming) if valid(incoming) => assert_eq!(id, incoming),
+                _ => assert!(id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())),
+            }
+        }
+    }
+}
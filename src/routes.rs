@@ -0,0 +1,282 @@
+use actix_web::http::Method;
+use actix_web::{web, Route};
+use crate::auth::require_role;
+use crate::api_key::api_key_auth;
+use crate::handler::*;
+use crate::import::MAX_BULK_BYTES;
+use crate::models::{Role, Scope};
+
+// 路由需要的權限：Public 不需登入，Role(r) 需要 r 以上的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Public,
+    Role(Role),
+}
+
+pub struct RouteSpec {
+    pub method: Method,
+    pub path: &'static str,
+    pub permission: Permission,
+    // 可以用帶有此 scope 的 API key 呼叫；None 時只接受 access token
+    pub api_key_scope: Option<Scope>,
+    // handler 函式名稱，OpenAPI 文件以此對應說明（operationId）
+    pub handler: &'static str,
+}
+
+// 每條路由只寫一次：同時產生 ROUTES（權限一覽、OpenAPI 文件）與 configure（實際註冊）
+macro_rules! route_table {
+    ($( $method:ident $path:literal => $handler:ident, $permission:expr, $scope:expr; )*) => {
+        pub const ROUTES: &[RouteSpec] = &[
+            $( RouteSpec { method: Method::$method, path: $path, permission: $permission, api_key_scope: $scope, handler: stringify!($handler) }, )*
+        ];
+
+        const HANDLERS: &[fn(Route) -> Route] = &[
+            $( |route| route.to($handler), )*
+        ];
+    };
+}
+
+use Permission::Public;
+const ANALYST: Permission = Permission::Role(Role::Analyst);
+const OPERATOR: Permission = Permission::Role(Role::Operator);
+const ADMIN: Permission = Permission::Role(Role::Admin);
+const NO_KEY: Option<Scope> = None;
+const READ: Option<Scope> = Some(Scope::DispositionRead);
+const WRITE: Option<Scope> = Some(Scope::DispositionWrite);
+
+// 註冊順序即比對順序：/disposition/active 需在 /disposition/{symbol} 之前
+route_table! {
+    GET    "/health"                          => health_check,                  Public,   NO_KEY;
+    GET    "/openapi.json"                    => get_openapi,                   Public,   NO_KEY;
+    GET    "/docs"                            => get_docs,                      Public,   NO_KEY;
+    // 設定 metrics.token 時由 handler 檢查
+    GET    "/metrics"                         => get_metrics,                   Public,   NO_KEY;
+    POST   "/auth/login"                      => login,                         Public,   NO_KEY;
+    POST   "/auth/refresh"                    => refresh_token,                 Public,   NO_KEY;
+    POST   "/auth/password-reset"             => request_password_reset,        Public,   NO_KEY;
+    POST   "/auth/password-reset/confirm"     => confirm_password_reset,        Public,   NO_KEY;
+
+    GET    "/user"                            => get_user,                      ADMIN,    NO_KEY;
+    GET    "/user/export"                     => export_users,                  ADMIN,    NO_KEY;
+    GET    "/user/{id}"                       => get_user_by_id,                ADMIN,    NO_KEY;
+    POST   "/user"                            => create_user,                   ADMIN,    NO_KEY;
+    PUT    "/user/{id}"                       => update_user,                   ADMIN,    NO_KEY;
+    DELETE "/user/{id}"                       => delete_user,                   ADMIN,    NO_KEY;
+    // 本人或 admin，handler 內再檢查
+    POST   "/user/{id}/password"              => change_password,               ANALYST,  NO_KEY;
+
+    POST   "/api-keys"                        => create_api_key,                ANALYST,  NO_KEY;
+    GET    "/api-keys"                        => get_api_keys,                  ANALYST,  NO_KEY;
+    DELETE "/api-keys/{id}"                   => revoke_api_key,                ANALYST,  NO_KEY;
+    GET    "/api-keys/{id}/uses"              => get_api_key_uses,              ANALYST,  NO_KEY;
+
+    GET    "/admin/log-level"                 => get_log_level,                 ADMIN,    NO_KEY;
+    PUT    "/admin/log-level"                 => update_log_level,              ADMIN,    NO_KEY;
+
+    GET    "/webhooks"                        => get_webhooks,                  ADMIN,    NO_KEY;
+    POST   "/webhooks"                        => create_webhook,                ADMIN,    NO_KEY;
+    GET    "/webhooks/{id}"                   => get_webhook,                   ADMIN,    NO_KEY;
+    PUT    "/webhooks/{id}"                   => update_webhook,                ADMIN,    NO_KEY;
+    DELETE "/webhooks/{id}"                   => delete_webhook,                ADMIN,    NO_KEY;
+    GET    "/webhooks/{id}/deliveries"        => get_webhook_deliveries,        ADMIN,    NO_KEY;
+
+    GET    "/ws"                              => ws_connect,                    ANALYST,  READ;
+
+    GET    "/disposition"                     => get_disposition,               ANALYST,  READ;
+    GET    "/disposition/active"              => get_active_disposition,        ANALYST,  READ;
+    GET    "/disposition/export"              => export_dispositions,           ANALYST,  READ;
+    GET    "/disposition/calendar.ics"        => get_disposition_calendar,      ANALYST,  READ;
+    GET    "/disposition/stream"              => stream_dispositions,           ANALYST,  READ;
+    GET    "/disposition/{symbol}"            => get_disposition_by_symbol,     ANALYST,  READ;
+    GET    "/disposition/{symbol}/active"     => get_disposition_active_status, ANALYST,  READ;
+    GET    "/disposition/{symbol}/history"    => get_disposition_history,       ANALYST,  READ;
+    GET    "/disposition/{symbol}/{id}"       => get_disposition_period,        ANALYST,  READ;
+    POST   "/disposition"                     => create_disposition,            OPERATOR, WRITE;
+    POST   "/disposition/bulk"                => import_dispositions,           OPERATOR, WRITE;
+    POST   "/disposition/import/{exchange}"   => import_announcement,           OPERATOR, WRITE;
+    PUT    "/disposition/{symbol}"            => update_disposition,            OPERATOR, WRITE;
+    DELETE "/disposition/{symbol}"            => delete_disposition,            OPERATOR, WRITE;
+    PUT    "/disposition/{symbol}/{id}"       => update_disposition_period,     OPERATOR, WRITE;
+    DELETE "/disposition/{symbol}/{id}"       => delete_disposition_period,     OPERATOR, WRITE;
+}
+
+// 以 web::Bytes 讀取整個 body 的匯入路由，body 上限放寬到 MAX_BULK_BYTES
+const BULK_BODY_PATHS: &[&str] = &["/disposition/bulk", "/disposition/import/{exchange}"];
+
+// 行事曆 App 與瀏覽器的 EventSource、WebSocket 無法帶 Authorization 或 X-API-Key，
+// 這些唯讀路由的 API key 也可以放在 ?api_key=
+pub const QUERY_KEY_PATHS: &[&str] = &["/disposition/calendar.ics", "/disposition/stream", "/ws"];
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    for (spec, handler) in ROUTES.iter().zip(HANDLERS) {
+        let route = handler(web::route().method(spec.method.clone()));
+        let route = match spec.permission {
+            Permission::Public => route,
+            Permission::Role(role) => {
+                let route = route.wrap(require_role(role));
+                match spec.api_key_scope {
+                    Some(scope) if QUERY_KEY_PATHS.contains(&spec.path) => route.wrap(api_key_auth(scope).or_query()),
+                    Some(scope) => route.wrap(api_key_auth(scope)),
+                    None => route,
+                }
+            }
+        };
+        if BULK_BODY_PATHS.contains(&spec.path) {
+            cfg.service(web::resource(spec.path).app_data(web::PayloadConfig::new(MAX_BULK_BYTES)).route(route));
+        } else {
+            cfg.route(spec.path, route);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::TokenService;
+    use crate::config::AuthConfig;
+    use crate::events::EventHub;
+    use crate::api_key;
+    use crate::memory::{InMemoryApiKeyStore, InMemoryDispositionStore, InMemoryUserStore};
+    use crate::models::{CreateApiKey, CreateUser, Scope, User};
+    use crate::password;
+    use crate::store::{ApiKeyStore, DispositionStore, UserStore};
+    use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+    use actix_web::{test, App};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const PASSWORD: &str = "password-123";
+
+    // analyst、operator、admin 各一位使用者
+    async fn seed_users() -> (Arc<dyn UserStore>, Vec<User>) {
+        let users: Arc<dyn UserStore> = Arc::new(InMemoryUserStore::new());
+        let hash = password::hash(PASSWORD.to_string()).await.unwrap();
+        let mut created = Vec::new();
+        for role in [Role::Analyst, Role::Operator, Role::Admin] {
+            let user = CreateUser {
+                name: role.as_str().to_string(),
+                email: format!("{}@example.com", role.as_str()),
+                password: PASSWORD.to_string(),
+                role: Some(role),
+            };
+            created.push(users.create(&user, &hash).await.unwrap());
+        }
+        (users, created)
+    }
+
+    fn tokens() -> TokenService {
+        TokenService::new(&AuthConfig { jwt_secret: Some("x".repeat(32)), ..AuthConfig::default() })
+    }
+
+    // 以實際的路由表建立 app
+    fn app(users: Arc<dyn UserStore>) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+        let dispositions: Arc<dyn DispositionStore> = Arc::new(InMemoryDispositionStore::new());
+        App::new()
+            .app_data(web::Data::from(users))
+            .app_data(web::Data::from(dispositions))
+            .app_data(web::Data::new(EventHub::new()))
+            .app_data(web::Data::new(tokens()))
+            .configure(configure)
+    }
+
+    fn bearer(tokens: &TokenService, user: &User) -> (&'static str, String) {
+        ("Authorization", format!("Bearer {}", tokens.issue(user.clone()).unwrap().access_token))
+    }
+
+    fn disposition(symbol: &str) -> serde_json::Value {
+        json!({ "stock_date": "114/10/17", "market": "TWSE", "symbol": symbol, "name": "測試", "start": "114/10/20", "end": "114/10/31" })
+    }
+
+    // analyst 只能讀，寫入需要 operator 以上；使用者管理只有 admin
+    #[actix_web::test]
+    async fn write_routes_need_operator() {
+        let (store, users) = seed_users().await;
+        let (app, tokens) = (test::init_service(app(store)).await, tokens());
+        let [analyst, operator, admin] = &users[..] else { unreachable!() };
+        let status = |req: test::TestRequest| {
+            let app = &app;
+            async move { test::call_service(app, req.to_request()).await.status().as_u16() }
+        };
+
+        assert_eq!(status(test::TestRequest::post().uri("/disposition").set_json(disposition("2330"))).await, 401);
+        assert_eq!(status(test::TestRequest::post().uri("/disposition").insert_header(bearer(&tokens, analyst)).set_json(disposition("2330"))).await, 403);
+        assert_eq!(status(test::TestRequest::delete().uri("/disposition/2330").insert_header(bearer(&tokens, analyst))).await, 403);
+        assert_eq!(status(test::TestRequest::get().uri("/disposition").insert_header(bearer(&tokens, analyst))).await, 200);
+
+        assert_eq!(status(test::TestRequest::post().uri("/disposition").insert_header(bearer(&tokens, operator)).set_json(disposition("2330"))).await, 201);
+        assert_eq!(status(test::TestRequest::post().uri("/disposition").insert_header(bearer(&tokens, admin)).set_json(disposition("2317"))).await, 201);
+        assert_eq!(status(test::TestRequest::delete().uri("/disposition/2330").insert_header(bearer(&tokens, operator))).await, 200);
+
+        assert_eq!(status(test::TestRequest::get().uri("/user").insert_header(bearer(&tokens, operator))).await, 403);
+        assert_eq!(status(test::TestRequest::get().uri("/user").insert_header(bearer(&tokens, admin))).await, 200);
+    }
+
+    // 非 admin 只能變更自己的密碼且要帶舊密碼；admin 變更別人的密碼不需要對方的舊密碼
+    #[actix_web::test]
+    async fn only_admin_changes_other_passwords() {
+        let (store, users) = seed_users().await;
+        let (app, tokens) = (test::init_service(app(store.clone())).await, tokens());
+        let [analyst, operator, admin] = &users[..] else { unreachable!() };
+        let change = |caller: &User, target: &User, old_password: &str, new_password: &str| {
+            let req = test::TestRequest::post()
+                .uri(&format!("/user/{}/password", target.id))
+                .insert_header(bearer(&tokens, caller))
+                .set_json(json!({ "old_password": old_password, "new_password": new_password }))
+                .to_request();
+            let app = &app;
+            async move { test::call_service(app, req).await.status().as_u16() }
+        };
+        let password_is = |user: &User, password: &str| {
+            let (store, id, password) = (store.clone(), user.id, password.to_string());
+            async move { password::verify(password, store.get_password_hash(id).await.unwrap().unwrap()).await.unwrap() }
+        };
+
+        assert_eq!(change(analyst, operator, PASSWORD, "taken-over-1").await, 403);
+        assert_eq!(change(operator, admin, PASSWORD, "taken-over-2").await, 403);
+        assert_eq!(change(analyst, analyst, "wrong-password", "analyst-new-1").await, 422);
+        assert_eq!(change(analyst, analyst, PASSWORD, "analyst-new-1").await, 200);
+        assert!(password_is(analyst, "analyst-new-1").await);
+
+        assert_eq!(change(admin, operator, "", "operator-new-1").await, 200);
+        assert!(password_is(operator, "operator-new-1").await);
+        assert_eq!(change(admin, analyst, "wrong-password", "analyst-new-2").await, 200);
+        assert!(password_is(analyst, "analyst-new-2").await);
+        // admin 變更自己的密碼仍要舊密碼
+        assert_eq!(change(admin, admin, "wrong-password", "admin-new-1").await, 422);
+    }
+
+    // 瀏覽器的串流連線把只有 disposition:read 的 key 放在網址；其他路由或權限較大的 key 不接受
+    #[actix_web::test]
+    async fn stream_routes_accept_read_only_query_key() {
+        let (users, created) = seed_users().await;
+        let keys: Arc<dyn ApiKeyStore> = Arc::new(InMemoryApiKeyStore::new());
+        let mut raw = Vec::new();
+        for scopes in [vec![Scope::DispositionRead], vec![Scope::DispositionRead, Scope::DispositionWrite]] {
+            let (key, prefix, digest) = api_key::generate();
+            let create = CreateApiKey { name: "browser".to_string(), scopes, expires_in_days: None };
+            keys.create(created[0].id, &create, &prefix, &digest).await.unwrap();
+            raw.push(key);
+        }
+        let (read_only, read_write) = (&raw[0], &raw[1]);
+        let app = test::init_service(app(users).app_data(web::Data::from(keys))).await;
+        let status = |req: test::TestRequest| {
+            let req = req.to_request();
+            let app = &app;
+            async move { test::call_service(app, req).await.status().as_u16() }
+        };
+        let websocket = |uri: String| test::TestRequest::get().uri(&uri)
+            .insert_header(("Upgrade", "websocket"))
+            .insert_header(("Connection", "Upgrade"))
+            .insert_header(("Sec-WebSocket-Version", "13"))
+            .insert_header(("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="));
+
+        assert_eq!(status(test::TestRequest::get().uri(&format!("/disposition/stream?api_key={}", read_only))).await, 200);
+        assert_eq!(status(websocket(format!("/ws?api_key={}", read_only))).await, 101);
+        assert_eq!(status(test::TestRequest::get().uri("/disposition/stream")).await, 401);
+        assert_eq!(status(websocket("/ws".to_string())).await, 401);
+        assert_eq!(status(test::TestRequest::get().uri(&format!("/disposition/stream?api_key={}", read_write))).await, 403);
+        assert_eq!(status(websocket(format!("/ws?api_key={}", read_write))).await, 403);
+        // 一般的 API 仍只接受標頭
+        assert_eq!(status(test::TestRequest::get().uri(&format!("/disposition?api_key={}", read_only))).await, 401);
+    }
+}
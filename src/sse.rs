@@ -0,0 +1,64 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, StreamExt};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use crate::error::AppError;
+use crate::events::{Replay, StreamEvent};
+use crate::models::{DateFormat, DATE_FORMAT};
+
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+// 沒有事件時定期送出註解，避免 proxy 或瀏覽器因閒置而斷線
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+// 建議客戶端斷線後多久重連（毫秒）
+const RETRY_MS: u64 = 3000;
+
+// 一個事件：id 讓客戶端重連時帶 Last-Event-ID，data 為該筆處置股
+fn frame(event: &StreamEvent, format: DateFormat) -> Bytes {
+    let data = DATE_FORMAT.sync_scope(format, || serde_json::to_string(&event.event.data)).unwrap_or_default();
+    Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", event.id, event.event.event.action(), data))
+}
+
+// 日期格式由 handler 傳入，原因同 export::respond
+pub fn response(replay: Replay, receiver: broadcast::Receiver<Arc<StreamEvent>>, format: DateFormat) -> HttpResponse {
+    let mut head = format!("retry: {}\n\n", RETRY_MS).into_bytes();
+    match replay {
+        Replay::Events(events) => {
+            for event in &events {
+                head.extend_from_slice(&frame(event, format));
+            }
+        }
+        Replay::Reset => head.extend_from_slice(b"event: reset\ndata: {}\n\n"),
+    }
+
+    let body = stream::once(async move { Ok::<_, AppError>(Bytes::from(head)) })
+        .chain(stream::unfold(receiver, move |mut receiver| async move {
+            let next = {
+                let recv = pin!(receiver.recv());
+                let sleep = pin!(actix_web::rt::time::sleep(KEEP_ALIVE));
+                match future::select(recv, sleep).await {
+                    Either::Left((result, _)) => Some(result),
+                    Either::Right(_) => None,
+                }
+            };
+            let chunk = match next {
+                Some(Ok(event)) => frame(&event, format),
+                // 落後太多時結束連線，客戶端會帶 Last-Event-ID 重連，從緩衝區補送
+                Some(Err(RecvError::Lagged(_) | RecvError::Closed)) => return None,
+                None => Bytes::from_static(b": keep-alive\n\n"),
+            };
+            Some((Ok(chunk), receiver))
+        }));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        // 避免 nginx 緩衝整個回應
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body)
+}
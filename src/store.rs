@@ -0,0 +1,319 @@
+use crate::models::{ApiKey, ApiKeyUse, CreateApiKey, User, CreateUser, UpdateUser, Disposition, CreateDisposition, ConflictMode, ImportOutcome, UpdateDisposition, DispositionFilter, Webhook, CreateWebhook, UpdateWebhook, WebhookDelivery, PendingDelivery, DeliveryAttempt};
+use crate::repository::{UserRepository, DispositionRepository, ApiKeyRepository, WebhookRepository};
+use crate::db::DbPool;
+use crate::pagination::{PageRequest, Slice};
+use chrono::NaiveDate;
+use crate::error::AppResult;
+use crate::events::StreamEvent;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+// 匯出用的逐列串流，錯誤會當作最後一筆送出
+pub type RowStream<T> = mpsc::Receiver<AppResult<T>>;
+
+// handler 只依賴這兩個 trait，實際儲存可以是 MySQL 或記憶體
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn list(&self, page: &PageRequest<u32>) -> AppResult<Slice<User>>;
+    // 依 id 排序的全部使用者，不分頁
+    async fn export(&self) -> AppResult<RowStream<User>>;
+    async fn get_by_id(&self, id: u32) -> AppResult<Option<User>>;
+    async fn get_by_email(&self, email: &str) -> AppResult<Option<User>>;
+    // password_hash 由呼叫端先算好，store 不碰明碼
+    async fn create(&self, user: &CreateUser, password_hash: &str) -> AppResult<User>;
+    async fn update(&self, id: u32, user: &UpdateUser) -> AppResult<Option<User>>;
+    async fn delete(&self, id: u32) -> AppResult<bool>;
+    async fn get_password_hash(&self, id: u32) -> AppResult<Option<String>>;
+    async fn set_password_hash(&self, id: u32, password_hash: &str) -> AppResult<bool>;
+    async fn create_password_reset(&self, user_id: u32, token_hash: &str, ttl_secs: u64) -> AppResult<()>;
+    // token 有效時更新密碼並回傳使用者 id，token 只能使用一次
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> AppResult<Option<u32>>;
+}
+
+#[async_trait]
+pub trait DispositionStore: Send + Sync {
+    async fn list(&self, filter: &DispositionFilter, page: &PageRequest<(NaiveDate, i32)>) -> AppResult<Slice<Disposition>>;
+    // 篩選、排序與 list 相同，但不分頁
+    async fn export(&self, filter: &DispositionFilter) -> AppResult<RowStream<Disposition>>;
+    async fn get_by_symbol(&self, symbol: i32) -> AppResult<Option<Disposition>>;
+    async fn get_active(&self, as_of: NaiveDate) -> AppResult<Vec<Disposition>>;
+    async fn get_active_by_symbol(&self, symbol: i32, as_of: NaiveDate) -> AppResult<Option<Disposition>>;
+    async fn get_history(&self, symbol: i32) -> AppResult<Vec<Disposition>>;
+    async fn get_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>>;
+    // 寫入類的方法在同一個 transaction 內記錄事件並排入 webhook 投遞，呼叫端不需要另外發布
+    async fn create(&self, disposition: &CreateDisposition) -> AppResult<Disposition>;
+    // 全部寫入或全部不寫入；回傳值依序對應 rows，fail 模式遇到重複時停在該列（Conflict）且不寫入任何資料
+    async fn import(&self, rows: &[CreateDisposition], mode: ConflictMode) -> AppResult<Vec<ImportOutcome>>;
+    // update/delete 不指定 id 時作用在最新一筆處置期間
+    async fn update(&self, symbol: i32, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>>;
+    async fn update_by_id(&self, symbol: i32, id: u64, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>>;
+    // 回傳被刪除的資料（通知用），找不到時為 None
+    async fn delete(&self, symbol: i32) -> AppResult<Option<Disposition>>;
+    async fn delete_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>>;
+    // 事件紀錄：id 大於 after 的事件依 id 排序，由 events::relay 讀取後廣播
+    async fn events_after(&self, after: u64, limit: u32) -> AppResult<Vec<StreamEvent>>;
+    async fn last_event_id(&self) -> AppResult<u64>;
+}
+
+// webhook 設定與投遞佇列；投遞由 DispositionStore 的寫入排入，webhook::Dispatcher 取出 due 的投遞並回寫結果
+#[async_trait]
+pub trait WebhookStore: Send + Sync {
+    async fn list(&self) -> AppResult<Vec<Webhook>>;
+    async fn get_by_id(&self, id: u64) -> AppResult<Option<Webhook>>;
+    async fn create(&self, webhook: &CreateWebhook, secret: &str) -> AppResult<Webhook>;
+    async fn update(&self, id: u64, webhook: &UpdateWebhook) -> AppResult<Option<Webhook>>;
+    async fn delete(&self, id: u64) -> AppResult<bool>;
+    // 狀態為 pending 且已到重試時間的投遞，依到期時間排序
+    async fn due(&self, limit: u32) -> AppResult<Vec<PendingDelivery>>;
+    async fn record_attempt(&self, id: u64, attempt: &DeliveryAttempt) -> AppResult<()>;
+    async fn list_deliveries(&self, webhook_id: u64, limit: u32) -> AppResult<Vec<WebhookDelivery>>;
+}
+
+// API key 一律以擁有者 user_id 限定範圍，只有 find_active 是用雜湊全域查詢
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    async fn list(&self, user_id: u32) -> AppResult<Vec<ApiKey>>;
+    async fn get_by_id(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>>;
+    async fn create(&self, user_id: u32, key: &CreateApiKey, prefix: &str, key_hash: &str) -> AppResult<ApiKey>;
+    async fn revoke(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>>;
+    async fn find_active(&self, key_hash: &str) -> AppResult<Option<ApiKey>>;
+    // 更新 last_used_at 並寫入使用紀錄
+    async fn record_use(&self, id: u64, method: &str, path: &str, status: u16, ip: Option<&str>) -> AppResult<()>;
+    async fn list_uses(&self, id: u64, limit: u32) -> AppResult<Vec<ApiKeyUse>>;
+}
+
+// MySQL 實作：在 blocking 執行緒上取連線後交給 repository
+pub struct MySqlUserStore {
+    pool: DbPool,
+}
+
+impl MySqlUserStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserStore for MySqlUserStore {
+    async fn list(&self, page: &PageRequest<u32>) -> AppResult<Slice<User>> {
+        let page = page.clone();
+        self.pool.run("user.list", move |conn| UserRepository::list(conn, &page)).await
+    }
+
+    async fn export(&self) -> AppResult<RowStream<User>> {
+        self.pool.stream("user.export", UserRepository::export).await
+    }
+
+    async fn get_by_id(&self, id: u32) -> AppResult<Option<User>> {
+        self.pool.run("user.get_by_id", move |conn| UserRepository::get_by_id(conn, id)).await
+    }
+
+    async fn get_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        let email = email.to_string();
+        self.pool.run("user.get_by_email", move |conn| UserRepository::get_by_email(conn, &email)).await
+    }
+
+    async fn create(&self, user: &CreateUser, password_hash: &str) -> AppResult<User> {
+        let (user, password_hash) = (user.clone(), password_hash.to_string());
+        self.pool.run("user.create", move |conn| UserRepository::create(conn, &user, &password_hash)).await
+    }
+
+    async fn update(&self, id: u32, user: &UpdateUser) -> AppResult<Option<User>> {
+        let user = user.clone();
+        self.pool.run("user.update", move |conn| UserRepository::update(conn, id, &user)).await
+    }
+
+    async fn delete(&self, id: u32) -> AppResult<bool> {
+        self.pool.run("user.delete", move |conn| UserRepository::delete(conn, id)).await
+    }
+
+    async fn get_password_hash(&self, id: u32) -> AppResult<Option<String>> {
+        self.pool.run("user.get_password_hash", move |conn| UserRepository::get_password_hash(conn, id)).await
+    }
+
+    async fn set_password_hash(&self, id: u32, password_hash: &str) -> AppResult<bool> {
+        let password_hash = password_hash.to_string();
+        self.pool.run("user.set_password_hash", move |conn| UserRepository::set_password_hash(conn, id, &password_hash)).await
+    }
+
+    async fn create_password_reset(&self, user_id: u32, token_hash: &str, ttl_secs: u64) -> AppResult<()> {
+        let token_hash = token_hash.to_string();
+        self.pool.run("user.create_password_reset", move |conn| UserRepository::create_password_reset(conn, user_id, &token_hash, ttl_secs)).await
+    }
+
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> AppResult<Option<u32>> {
+        let (token_hash, password_hash) = (token_hash.to_string(), password_hash.to_string());
+        self.pool.run("user.reset_password", move |conn| UserRepository::reset_password(conn, &token_hash, &password_hash)).await
+    }
+}
+
+pub struct MySqlDispositionStore {
+    pool: DbPool,
+}
+
+impl MySqlDispositionStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl DispositionStore for MySqlDispositionStore {
+    async fn list(&self, filter: &DispositionFilter, page: &PageRequest<(NaiveDate, i32)>) -> AppResult<Slice<Disposition>> {
+        let (filter, page) = (filter.clone(), page.clone());
+        self.pool.run("disposition.list", move |conn| DispositionRepository::list(conn, &filter, &page)).await
+    }
+
+    async fn export(&self, filter: &DispositionFilter) -> AppResult<RowStream<Disposition>> {
+        let filter = filter.clone();
+        self.pool.stream("disposition.export", move |conn, emit| DispositionRepository::export(conn, &filter, emit)).await
+    }
+
+    async fn get_by_symbol(&self, symbol: i32) -> AppResult<Option<Disposition>> {
+        self.pool.run("disposition.get_by_symbol", move |conn| DispositionRepository::get_by_symbol(conn, symbol)).await
+    }
+
+    async fn get_active(&self, as_of: NaiveDate) -> AppResult<Vec<Disposition>> {
+        self.pool.run("disposition.get_active", move |conn| DispositionRepository::get_active(conn, as_of)).await
+    }
+
+    async fn get_active_by_symbol(&self, symbol: i32, as_of: NaiveDate) -> AppResult<Option<Disposition>> {
+        self.pool.run("disposition.get_active_by_symbol", move |conn| DispositionRepository::get_active_by_symbol(conn, symbol, as_of)).await
+    }
+
+    async fn get_history(&self, symbol: i32) -> AppResult<Vec<Disposition>> {
+        self.pool.run("disposition.get_history", move |conn| DispositionRepository::get_history(conn, symbol)).await
+    }
+
+    async fn get_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        self.pool.run("disposition.get_by_id", move |conn| DispositionRepository::get_by_id(conn, symbol, id)).await
+    }
+
+    async fn create(&self, disposition: &CreateDisposition) -> AppResult<Disposition> {
+        let disposition = disposition.clone();
+        self.pool.run("disposition.create", move |conn| DispositionRepository::create(conn, &disposition)).await
+    }
+
+    async fn import(&self, rows: &[CreateDisposition], mode: ConflictMode) -> AppResult<Vec<ImportOutcome>> {
+        let rows = rows.to_vec();
+        self.pool.run("disposition.import", move |conn| DispositionRepository::import(conn, &rows, mode)).await
+    }
+
+    async fn update(&self, symbol: i32, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let disposition = disposition.clone();
+        self.pool.run("disposition.update", move |conn| DispositionRepository::update(conn, symbol, &disposition)).await
+    }
+
+    async fn update_by_id(&self, symbol: i32, id: u64, disposition: &UpdateDisposition) -> AppResult<Option<Disposition>> {
+        let disposition = disposition.clone();
+        self.pool.run("disposition.update_by_id", move |conn| DispositionRepository::update_by_id(conn, symbol, id, &disposition)).await
+    }
+
+    async fn delete(&self, symbol: i32) -> AppResult<Option<Disposition>> {
+        self.pool.run("disposition.delete", move |conn| DispositionRepository::delete(conn, symbol)).await
+    }
+
+    async fn delete_by_id(&self, symbol: i32, id: u64) -> AppResult<Option<Disposition>> {
+        self.pool.run("disposition.delete_by_id", move |conn| DispositionRepository::delete_by_id(conn, symbol, id)).await
+    }
+
+    async fn events_after(&self, after: u64, limit: u32) -> AppResult<Vec<StreamEvent>> {
+        self.pool.run("disposition.events_after", move |conn| DispositionRepository::events_after(conn, after, limit)).await
+    }
+
+    async fn last_event_id(&self) -> AppResult<u64> {
+        self.pool.run("disposition.last_event_id", DispositionRepository::last_event_id).await
+    }
+}
+
+pub struct MySqlApiKeyStore {
+    pool: DbPool,
+}
+
+impl MySqlApiKeyStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MySqlApiKeyStore {
+    async fn list(&self, user_id: u32) -> AppResult<Vec<ApiKey>> {
+        self.pool.run("api_key.list", move |conn| ApiKeyRepository::list(conn, user_id)).await
+    }
+
+    async fn get_by_id(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        self.pool.run("api_key.get_by_id", move |conn| ApiKeyRepository::get_by_id(conn, user_id, id)).await
+    }
+
+    async fn create(&self, user_id: u32, key: &CreateApiKey, prefix: &str, key_hash: &str) -> AppResult<ApiKey> {
+        let (key, prefix, key_hash) = (key.clone(), prefix.to_string(), key_hash.to_string());
+        self.pool.run("api_key.create", move |conn| ApiKeyRepository::create(conn, user_id, &key, &prefix, &key_hash)).await
+    }
+
+    async fn revoke(&self, user_id: u32, id: u64) -> AppResult<Option<ApiKey>> {
+        self.pool.run("api_key.revoke", move |conn| ApiKeyRepository::revoke(conn, user_id, id)).await
+    }
+
+    async fn find_active(&self, key_hash: &str) -> AppResult<Option<ApiKey>> {
+        let key_hash = key_hash.to_string();
+        self.pool.run("api_key.find_active", move |conn| ApiKeyRepository::find_active(conn, &key_hash)).await
+    }
+
+    async fn record_use(&self, id: u64, method: &str, path: &str, status: u16, ip: Option<&str>) -> AppResult<()> {
+        let (method, path, ip) = (method.to_string(), path.to_string(), ip.map(str::to_string));
+        self.pool.run("api_key.record_use", move |conn| ApiKeyRepository::record_use(conn, id, &method, &path, status, ip.as_deref())).await
+    }
+
+    async fn list_uses(&self, id: u64, limit: u32) -> AppResult<Vec<ApiKeyUse>> {
+        self.pool.run("api_key.list_uses", move |conn| ApiKeyRepository::list_uses(conn, id, limit)).await
+    }
+}
+
+pub struct MySqlWebhookStore {
+    pool: DbPool,
+}
+
+impl MySqlWebhookStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookStore for MySqlWebhookStore {
+    async fn list(&self) -> AppResult<Vec<Webhook>> {
+        self.pool.run("webhook.list", WebhookRepository::list).await
+    }
+
+    async fn get_by_id(&self, id: u64) -> AppResult<Option<Webhook>> {
+        self.pool.run("webhook.get_by_id", move |conn| WebhookRepository::get_by_id(conn, id)).await
+    }
+
+    async fn create(&self, webhook: &CreateWebhook, secret: &str) -> AppResult<Webhook> {
+        let (webhook, secret) = (webhook.clone(), secret.to_string());
+        self.pool.run("webhook.create", move |conn| WebhookRepository::create(conn, &webhook, &secret)).await
+    }
+
+    async fn update(&self, id: u64, webhook: &UpdateWebhook) -> AppResult<Option<Webhook>> {
+        let webhook = webhook.clone();
+        self.pool.run("webhook.update", move |conn| WebhookRepository::update(conn, id, &webhook)).await
+    }
+
+    async fn delete(&self, id: u64) -> AppResult<bool> {
+        self.pool.run("webhook.delete", move |conn| WebhookRepository::delete(conn, id)).await
+    }
+
+    async fn due(&self, limit: u32) -> AppResult<Vec<PendingDelivery>> {
+        self.pool.run("webhook.due", move |conn| WebhookRepository::due(conn, limit)).await
+    }
+
+    async fn record_attempt(&self, id: u64, attempt: &DeliveryAttempt) -> AppResult<()> {
+        let attempt = attempt.clone();
+        self.pool.run("webhook.record_attempt", move |conn| WebhookRepository::record_attempt(conn, id, &attempt)).await
+    }
+
+    async fn list_deliveries(&self, webhook_id: u64, limit: u32) -> AppResult<Vec<WebhookDelivery>> {
+        self.pool.run("webhook.list_deliveries", move |conn| WebhookRepository::list_deliveries(conn, webhook_id, limit)).await
+    }
+}
@@ -0,0 +1,310 @@
+use chrono::Utc;
+use futures_util::future;
+use futures_util::stream::{self, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use crate::config::WebhookConfig;
+use crate::error::AppResult;
+use crate::models::{DeliveryAttempt, PendingDelivery};
+use crate::password;
+use crate::store::WebhookStore;
+
+// 接收端驗證方式：以 secret 對 `{timestamp}.{body}` 計算 HMAC-SHA256，與 X-Webhook-Signature 比對
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+// 重試時不變，接收端可用來去除重複
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+const SECRET_PREFIX: &str = "whsec_";
+// 每次從佇列取出的投遞數
+const DISPATCH_BATCH: u32 = 50;
+
+pub fn generate_secret() -> String {
+    let (secret, _) = password::new_secret_token();
+    format!("{}{}", SECRET_PREFIX, secret)
+}
+
+// 回傳 `sha256=<hex>`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC 接受任意長度的 key");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+// 第 attempts 次失敗後的等待秒數：base * 2^(attempts-1)，不超過 max；次數用完時為 None
+pub fn backoff(config: &WebhookConfig, attempts: u32) -> Option<u64> {
+    if attempts >= config.max_attempts {
+        return None;
+    }
+    let factor = 1u64.checked_shl(attempts.saturating_sub(1)).unwrap_or(u64::MAX);
+    Some(config.base_delay_secs.saturating_mul(factor).min(config.max_delay_secs))
+}
+
+// 從投遞佇列取出到期的投遞送出並回寫結果；佇列存在資料庫，重啟後會接著重試
+pub struct Dispatcher {
+    store: Arc<dyn WebhookStore>,
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl Dispatcher {
+    pub fn new(store: Arc<dyn WebhookStore>, config: WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(concat!("rust-crud-api/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("無法建立 webhook HTTP client");
+        Self { store, client, config }
+    }
+
+    // 有新事件時由 EventHub 喚醒，否則每 poll_interval_secs 檢查一次到期的重試
+    pub async fn run(self, wake: Arc<Notify>) {
+        let poll = Duration::from_secs(self.config.poll_interval_secs);
+        loop {
+            match self.dispatch_due().await {
+                // 一批滿了代表可能還有，直接再取
+                Ok(sent) if sent == DISPATCH_BATCH as usize => continue,
+                Ok(_) => {}
+                Err(e) => tracing::error!(error = %e, "無法讀取 webhook 投遞佇列"),
+            }
+            let notified = pin!(wake.notified());
+            let sleep = pin!(actix_web::rt::time::sleep(poll));
+            future::select(notified, sleep).await;
+        }
+    }
+
+    // 送出一批到期的投遞，最多同時 concurrency 筆，回傳成功回寫結果的筆數。
+    // 單筆結果寫不回去時只記錄錯誤、不影響同批其他投遞；該筆仍是 pending，之後會再投遞一次（接收端可用 X-Webhook-Delivery 去除重複）
+    pub async fn dispatch_due(&self) -> AppResult<usize> {
+        let due = self.store.due(DISPATCH_BATCH).await?;
+        let recorded = stream::iter(&due)
+            .map(|delivery| self.dispatch(delivery))
+            .buffer_unordered(self.config.concurrency)
+            .filter(|recorded| future::ready(*recorded))
+            .count()
+            .await;
+        Ok(recorded)
+    }
+
+    // 投遞一筆並回寫結果，回寫成功時回傳 true
+    async fn dispatch(&self, delivery: &PendingDelivery) -> bool {
+        let attempt = self.deliver(delivery).await;
+        match (attempt.delivered, attempt.retry_in) {
+            (true, _) => tracing::debug!(webhook_id = delivery.webhook_id, delivery_id = delivery.id, "webhook 投遞成功"),
+            (false, Some(retry_in)) => tracing::warn!(
+                webhook_id = delivery.webhook_id, delivery_id = delivery.id, status = attempt.status, error = attempt.error.as_deref(), retry_in,
+                "webhook 投遞失敗，稍後重試"
+            ),
+            (false, None) => tracing::error!(
+                webhook_id = delivery.webhook_id, delivery_id = delivery.id, attempts = delivery.attempts + 1, error = attempt.error.as_deref(),
+                "webhook 投遞失敗次數已達上限，不再重試"
+            ),
+        }
+        match self.store.record_attempt(delivery.id, &attempt).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(webhook_id = delivery.webhook_id, delivery_id = delivery.id, error = %e, "無法記錄 webhook 投遞結果");
+                false
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: &PendingDelivery) -> DeliveryAttempt {
+        let timestamp = Utc::now().timestamp();
+        let result = self.client.post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &delivery.payload))
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (status, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                return DeliveryAttempt { delivered: true, status: Some(response.status().as_u16()), error: None, retry_in: None };
+            }
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("HTTP {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        DeliveryAttempt { delivered: false, status, error, retry_in: backoff(&self.config, delivery.attempts + 1) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{InMemoryDispositionStore, InMemoryWebhookStore};
+    use crate::error::AppError;
+    use crate::models::{CreateDisposition, CreateWebhook, DateFormat, DeliveryStatus, TwDate, UpdateWebhook, Webhook, WebhookDelivery, DATE_FORMAT};
+    use crate::store::DispositionStore;
+    use async_trait::async_trait;
+    use actix_web::http::{header::HeaderMap, StatusCode};
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    // 本機的替身接收端：記錄收到的請求，回應 status 指定的狀態碼
+    struct StandIn {
+        status: AtomicU16,
+        received: Mutex<Vec<(HeaderMap, String)>>,
+    }
+
+    async fn receive(stand_in: web::Data<StandIn>, req: HttpRequest, body: String) -> HttpResponse {
+        stand_in.received.lock().unwrap().push((req.headers().clone(), body));
+        HttpResponse::build(StatusCode::from_u16(stand_in.status.load(Ordering::SeqCst)).unwrap()).finish()
+    }
+
+    // 指定的投遞回寫結果時失敗，其餘交給記憶體儲存
+    struct FailingRecord {
+        inner: Arc<InMemoryWebhookStore>,
+        fail_id: u64,
+    }
+
+    #[async_trait]
+    impl WebhookStore for FailingRecord {
+        async fn list(&self) -> AppResult<Vec<Webhook>> {
+            self.inner.list().await
+        }
+
+        async fn get_by_id(&self, id: u64) -> AppResult<Option<Webhook>> {
+            self.inner.get_by_id(id).await
+        }
+
+        async fn create(&self, webhook: &CreateWebhook, secret: &str) -> AppResult<Webhook> {
+            self.inner.create(webhook, secret).await
+        }
+
+        async fn update(&self, id: u64, webhook: &UpdateWebhook) -> AppResult<Option<Webhook>> {
+            self.inner.update(id, webhook).await
+        }
+
+        async fn delete(&self, id: u64) -> AppResult<bool> {
+            self.inner.delete(id).await
+        }
+
+        async fn due(&self, limit: u32) -> AppResult<Vec<PendingDelivery>> {
+            self.inner.due(limit).await
+        }
+
+        async fn record_attempt(&self, id: u64, attempt: &DeliveryAttempt) -> AppResult<()> {
+            if id == self.fail_id {
+                return Err(AppError::Unavailable("測試用的失敗".to_string()));
+            }
+            self.inner.record_attempt(id, attempt).await
+        }
+
+        async fn list_deliveries(&self, webhook_id: u64, limit: u32) -> AppResult<Vec<WebhookDelivery>> {
+            self.inner.list_deliveries(webhook_id, limit).await
+        }
+    }
+
+    async fn stand_in_server(status: u16) -> (web::Data<StandIn>, std::net::SocketAddr) {
+        let stand_in = web::Data::new(StandIn { status: AtomicU16::new(status), received: Mutex::new(Vec::new()) });
+        let server = HttpServer::new({
+            let stand_in = stand_in.clone();
+            move || App::new().app_data(stand_in.clone()).route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        (stand_in, addr)
+    }
+
+    fn disposition() -> CreateDisposition {
+        CreateDisposition {
+            stock_date: TwDate::parse("114/10/17").unwrap(),
+            market: "TWSE".to_string(),
+            symbol: "2330".to_string(),
+            name: "台積電".to_string(),
+            start: TwDate::parse("114/10/20"),
+            end: TwDate::parse("114/10/31"),
+        }
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+        headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default()
+    }
+
+    #[test]
+    fn backoff_doubles_until_limit() {
+        let config = WebhookConfig::default();
+        let delays: Vec<Option<u64>> = (1..=8).map(|n| backoff(&config, n)).collect();
+        assert_eq!(delays, [Some(30), Some(60), Some(120), Some(240), Some(480), Some(960), Some(1920), None]);
+        let capped = WebhookConfig { max_attempts: 20, ..WebhookConfig::default() };
+        assert_eq!(backoff(&capped, 19), Some(capped.max_delay_secs));
+    }
+
+    // 第一次收到 500 會留在佇列重試，第二次成功；兩次的 delivery id 相同且簽章可驗證
+    #[actix_web::test]
+    async fn retries_failed_delivery_and_signs_payload() {
+        let (stand_in, addr) = stand_in_server(500).await;
+
+        let webhooks = Arc::new(InMemoryWebhookStore::new());
+        let secret = generate_secret();
+        let webhook = CreateWebhook { url: format!("http://{}/hook", addr), description: "替身".to_string() };
+        let webhook = webhooks.create(&webhook, &secret).await.unwrap();
+
+        // 寫入時排入投遞；即使請求要求民國格式，payload 仍是 ISO
+        let dispositions = InMemoryDispositionStore::with_webhooks(webhooks.clone());
+        DATE_FORMAT.scope(DateFormat::Roc, dispositions.create(&disposition())).await.unwrap();
+
+        let config = WebhookConfig { base_delay_secs: 0, ..WebhookConfig::default() };
+        let dispatcher = Dispatcher::new(webhooks.clone(), config);
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+        let deliveries = webhooks.list_deliveries(webhook.id, 10).await.unwrap();
+        assert_eq!((deliveries[0].status, deliveries[0].attempts, deliveries[0].last_status), (DeliveryStatus::Pending, 1, Some(500)));
+
+        stand_in.status.store(200, Ordering::SeqCst);
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 0);
+        let deliveries = webhooks.list_deliveries(webhook.id, 10).await.unwrap();
+        assert_eq!((deliveries[0].status, deliveries[0].attempts), (DeliveryStatus::Delivered, 2));
+
+        let received = stand_in.received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(header(&received[0].0, DELIVERY_HEADER), header(&received[1].0, DELIVERY_HEADER));
+        let (headers, body) = &received[1];
+        let timestamp: i64 = header(headers, TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(headers, SIGNATURE_HEADER), sign(&secret, timestamp, body));
+        assert_eq!(header(headers, EVENT_HEADER), "disposition.created");
+
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["event"], "disposition.created");
+        assert_eq!(payload["data"]["symbol"], 2330);
+        assert_eq!(payload["data"]["stock_date"], "2025-10-17");
+    }
+
+    // 一筆投遞的結果寫不回去時，同一批的其他投遞照常送出並記錄
+    #[actix_web::test]
+    async fn keeps_dispatching_when_recording_fails() {
+        let (stand_in, addr) = stand_in_server(200).await;
+        let webhooks = Arc::new(InMemoryWebhookStore::new());
+        let url = format!("http://{}/hook", addr);
+        let first = webhooks.create(&CreateWebhook { url: url.clone(), description: "一".to_string() }, &generate_secret()).await.unwrap();
+        let second = webhooks.create(&CreateWebhook { url, description: "二".to_string() }, &generate_secret()).await.unwrap();
+        InMemoryDispositionStore::with_webhooks(webhooks.clone()).create(&disposition()).await.unwrap();
+
+        let fail_id = webhooks.list_deliveries(first.id, 10).await.unwrap()[0].id;
+        let store = Arc::new(FailingRecord { inner: webhooks.clone(), fail_id });
+        let dispatcher = Dispatcher::new(store, WebhookConfig::default());
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+        assert_eq!(stand_in.received.lock().unwrap().len(), 2);
+
+        let failed = &webhooks.list_deliveries(first.id, 10).await.unwrap()[0];
+        assert_eq!((failed.status, failed.attempts), (DeliveryStatus::Pending, 0));
+        let delivered = &webhooks.list_deliveries(second.id, 10).await.unwrap()[0];
+        assert_eq!((delivered.status, delivered.attempts), (DeliveryStatus::Delivered, 1));
+    }
+}
@@ -0,0 +1,162 @@
+use actix_web::rt::time::{interval, timeout, Instant};
+use actix_web::web;
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Session};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use crate::clock::taipei_today;
+use crate::events::EventHub;
+use crate::models::{BoundaryKind, DateFormat, Disposition, DispositionEventKind, TwDate, DATE_FORMAT};
+
+// 每隔 HEARTBEAT_INTERVAL 送一次 ping；超過 CLIENT_TIMEOUT 沒收到任何訊息（含 pong）就斷線
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+// 送出佇列滿了（客戶端讀得太慢）時最多等這麼久，逾時就斷線，不讓一條連線拖住伺服器
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+// 單一連線最多訂閱的股票數，以及客戶端單一訊息的大小上限（位元組）
+pub const MAX_SYMBOLS: usize = 200;
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { symbols: Vec<i32> },
+    Unsubscribe { symbols: Vec<i32> },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame<'a> {
+    // 回應 subscribe / unsubscribe，內容為目前訂閱的全部股票
+    Subscribed { symbols: &'a BTreeSet<i32> },
+    // 處置股資料異動；active 表示異動後這筆資料今天是否在處置期間內
+    Disposition { action: &'static str, active: bool, data: &'a Disposition },
+    Entered { date: TwDate, data: &'a Disposition },
+    Left { date: TwDate, data: &'a Disposition },
+    // 處理太慢而漏掉的事件數，客戶端應重新查詢訂閱的股票
+    Lagged { missed: u64 },
+    Error { message: String },
+}
+
+// 日期格式由 handler 傳入，原因同 export::respond
+fn encode(frame: &ServerFrame, format: DateFormat) -> String {
+    DATE_FORMAT.sync_scope(format, || serde_json::to_string(frame)).unwrap_or_default()
+}
+
+fn covers(d: &Disposition, day: NaiveDate) -> bool {
+    matches!((d.start, d.end), (Some(start), Some(end)) if start.0 <= day && day <= end.0)
+}
+
+// 套用 subscribe / unsubscribe；超過 MAX_SYMBOLS 時整個 subscribe 不生效
+fn apply(text: &str, symbols: &mut BTreeSet<i32>) -> Result<(), String> {
+    let message: ClientMessage = serde_json::from_str(text).map_err(|e| format!("無效的訊息: {}", e))?;
+    match message {
+        ClientMessage::Subscribe { symbols: added } => {
+            let merged: BTreeSet<i32> = symbols.iter().copied().chain(added).collect();
+            if merged.len() > MAX_SYMBOLS {
+                return Err(format!("單一連線最多訂閱 {} 檔股票", MAX_SYMBOLS));
+            }
+            *symbols = merged;
+        }
+        ClientMessage::Unsubscribe { symbols: removed } => {
+            for symbol in removed {
+                symbols.remove(&symbol);
+            }
+        }
+    }
+    Ok(())
+}
+
+// session 的送出佇列有上限，佇列滿時 text() 會等待；逾時或連線已關閉時回傳 false
+async fn send(session: &mut Session, frame: &ServerFrame<'_>, format: DateFormat) -> bool {
+    matches!(timeout(SEND_TIMEOUT, session.text(encode(frame, format))).await, Ok(Ok(())))
+}
+
+// 一條 WebSocket 連線的生命週期：處理訂閱訊息、轉送訂閱股票的異動與處置起訖提醒、送心跳。
+// 送出較慢時 EventHub 的事件會在 broadcast 緩衝區累積，落後太多時改送 lagged
+pub async fn run(mut session: Session, mut messages: AggregatedMessageStream, hub: web::Data<EventHub>, format: DateFormat) {
+    let (_, mut events) = hub.subscribe(None);
+    let mut boundaries = hub.subscribe_boundaries();
+    let mut symbols = BTreeSet::new();
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    let mut last_heard = Instant::now();
+
+    let reason = loop {
+        let sent = tokio::select! {
+            message = messages.recv() => {
+                last_heard = Instant::now();
+                match message {
+                    Some(Ok(AggregatedMessage::Text(text))) => match apply(&text, &mut symbols) {
+                        Ok(()) => send(&mut session, &ServerFrame::Subscribed { symbols: &symbols }, format).await,
+                        Err(message) => send(&mut session, &ServerFrame::Error { message }, format).await,
+                    },
+                    Some(Ok(AggregatedMessage::Binary(_))) => {
+                        send(&mut session, &ServerFrame::Error { message: "只接受 JSON 文字訊息".to_string() }, format).await
+                    }
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => session.pong(&bytes).await.is_ok(),
+                    Some(Ok(AggregatedMessage::Pong(_))) => true,
+                    Some(Ok(AggregatedMessage::Close(reason))) => break reason,
+                    Some(Err(e)) => break Some(CloseReason { code: CloseCode::Protocol, description: Some(e.to_string()) }),
+                    None => break None,
+                }
+            }
+            event = events.recv() => match event {
+                Ok(event) if symbols.contains(&event.event.data.symbol) => {
+                    let kind = event.event.event;
+                    let data = &event.event.data;
+                    let active = kind != DispositionEventKind::Deleted && covers(data, taipei_today());
+                    send(&mut session, &ServerFrame::Disposition { action: kind.action(), active, data }, format).await
+                }
+                Ok(_) => true,
+                Err(RecvError::Lagged(missed)) => send(&mut session, &ServerFrame::Lagged { missed }, format).await,
+                Err(RecvError::Closed) => break None,
+            },
+            alert = boundaries.recv() => match alert {
+                Ok(alert) if symbols.contains(&alert.data.symbol) => {
+                    let frame = match alert.kind {
+                        BoundaryKind::Entered => ServerFrame::Entered { date: alert.date, data: &alert.data },
+                        BoundaryKind::Left => ServerFrame::Left { date: alert.date, data: &alert.data },
+                    };
+                    send(&mut session, &frame, format).await
+                }
+                Ok(_) => true,
+                Err(RecvError::Lagged(missed)) => send(&mut session, &ServerFrame::Lagged { missed }, format).await,
+                Err(RecvError::Closed) => break None,
+            },
+            _ = heartbeat.tick() => {
+                if last_heard.elapsed() > CLIENT_TIMEOUT {
+                    break Some(CloseReason { code: CloseCode::Policy, description: Some("心跳逾時".to_string()) });
+                }
+                session.ping(b"").await.is_ok()
+            }
+        };
+        if !sent {
+            break None;
+        }
+    };
+
+    // 客戶端不讀資料時 close 也送不出去；放棄後 session 被釋放，連線隨之關閉
+    let _ = timeout(SEND_TIMEOUT, session.close(reason)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 訂閱會合併、取消只移除指定股票；超過上限的 subscribe 整個不生效
+    #[test]
+    fn applies_subscriptions_within_limit() {
+        let mut symbols = BTreeSet::new();
+        apply(r#"{"type":"subscribe","symbols":[2330,1101]}"#, &mut symbols).unwrap();
+        apply(r#"{"type":"subscribe","symbols":[2330,2317]}"#, &mut symbols).unwrap();
+        apply(r#"{"type":"unsubscribe","symbols":[1101,9999]}"#, &mut symbols).unwrap();
+        assert_eq!(symbols.iter().copied().collect::<Vec<_>>(), [2317, 2330]);
+
+        let too_many = serde_json::json!({ "type": "subscribe", "symbols": (0..MAX_SYMBOLS as i32).collect::<Vec<_>>() });
+        assert!(apply(&too_many.to_string(), &mut symbols).is_err());
+        assert_eq!(symbols.len(), 2);
+        assert!(apply(r#"{"type":"watch","symbols":[]}"#, &mut symbols).is_err());
+    }
+}